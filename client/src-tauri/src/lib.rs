@@ -11,6 +11,18 @@ struct ListItem {
     name: String,
 }
 
+// summary & suggested questions of the document shown in the sidebar
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+struct DocumentInsights {
+    // pending, ready or failed
+    #[serde(default, rename = "insights")]
+    status: Option<String>,
+    #[serde(default)]
+    summary: Option<String>,
+    #[serde(default)]
+    questions: Vec<String>,
+}
+
 fn get_backend_url() -> String {
     let backend_url = std::env::var("BACKEND_URL")
        .expect("BACKEND_URL not found");
@@ -53,14 +65,28 @@ fn style_text(text: &str) -> Vec<String> {
 }
 
 
+// error code returned by the server when the document password is missing or wrong
+async fn password_error(response: reqwest::Response) -> String {
+    let failures = response.json::<serde_json::Value>().await.unwrap_or_default();
+    let failure = if failures.is_array() { failures[0].clone() } else { failures };
+    failure["code"].as_str().unwrap_or("password_required").to_string()
+}
+
 #[tauri::command]
-async fn fetch_content(item: ListItem) -> Result<String, String> {
+async fn fetch_content(item: ListItem, password: Option<String>) -> Result<String, String> {
     let backend_url = get_backend_url();
-    match reqwest::Client::new()
-       .get(&format!("{}/file/{}.pdf", backend_url, item.name))
+    let mut request = reqwest::Client::new()
+       .get(&format!("{}/file/{}.pdf", backend_url, item.name));
+    if let Some(password) = password {
+        request = request.header("x-document-password", password);
+    }
+    match request
        .send()
        .await
      {
+        Ok(response) if response.status() == reqwest::StatusCode::UNAUTHORIZED => {
+            Err(password_error(response).await)
+        },
         Ok(response) => {
            let content = response.text().await.unwrap();
            let result_content = style_text(content.as_str());
@@ -71,11 +97,12 @@ async fn fetch_content(item: ListItem) -> Result<String, String> {
 }
 
 #[tauri::command]
-async fn process_prompt(item: ListItem, query: String) -> Result<String, String> {
+async fn process_prompt(item: ListItem, query: String, filter: Option<serde_json::Value>) -> Result<String, String> {
     let backend_url  = get_backend_url();
     let payload = json!({
         "user_query": query,
-        "doc_name": format!("{}.pdf",item.name)
+        "doc_name": format!("{}.pdf",item.name),
+        "filter": filter
     });
 
     match reqwest::Client::new()
@@ -94,7 +121,7 @@ async fn process_prompt(item: ListItem, query: String) -> Result<String, String>
 }
 
 #[tauri::command]
-async fn upload_file(name: String, ct: String) -> Result<String, String> {
+async fn upload_file(name: String, ct: String, tags: Option<Vec<String>>, doc_type: Option<String>, password: Option<String>, workspace: Option<String>) -> Result<String, String> {
     let backend_url  = get_backend_url();
     let decoded_data = decode(&ct).map_err(|e| format!("Base64 Decode Error: {}", e))?;
 
@@ -103,17 +130,49 @@ async fn upload_file(name: String, ct: String) -> Result<String, String> {
         .mime_str("application/octet-stream")
         .map_err(|e| format!("File Part Error: {}", e))?;
 
-    let form = Form::new()
+    let mut form = Form::new()
         .part("file", file_part);
+    if let Some(tags) = tags {
+        form = form.text("tags", tags.join(","));
+    }
+    if let Some(doc_type) = doc_type {
+        form = form.text("doc_type", doc_type);
+    }
+    if let Some(password) = password {
+        form = form.text("password", password);
+    }
+    if let Some(workspace) = workspace {
+        form = form.text("workspace", workspace);
+    }
 
         let client = reqwest::Client::new();
         match client.post(&format!("{}/upload", backend_url))
             .multipart(form)
             .send()
             .await {
+                Ok(response) if response.status() == reqwest::StatusCode::UNAUTHORIZED => {
+                    Err(password_error(response).await)
+                },
                 Ok(response) => {
                     if response.status().is_success() {
-                        Ok("File uploaded successfully!".to_string())
+                        let reports = response.json::<Vec<serde_json::Value>>().await.unwrap_or_default();
+                        // the archive entries carry their ingestion report under `report`
+                        let mut warnings: Vec<String> = reports.iter()
+                            .map(|report| if report["report"].is_object() { &report["report"] } else { report })
+                            .flat_map(|report| report["warnings"].as_array().cloned().unwrap_or_default())
+                            .filter_map(|warning| warning.as_str().map(|w| w.to_string()))
+                            .collect();
+                        warnings.extend(reports.iter()
+                            .filter(|report| report["status"] == "failed" || report["status"] == "skipped")
+                            .map(|report| format!("{} {}: {}",
+                                report["file_name"].as_str().unwrap_or_default(),
+                                report["status"].as_str().unwrap_or_default(),
+                                report["error"].as_str().unwrap_or_default())));
+                        if warnings.is_empty() {
+                            Ok("File uploaded successfully!".to_string())
+                        } else {
+                            Ok(format!("File uploaded with warnings: {}", warnings.join("; ")))
+                        }
                     } else {
                         Err(format!("Upload failed with status: {}", response.status()))
                     }
@@ -122,11 +181,54 @@ async fn upload_file(name: String, ct: String) -> Result<String, String> {
             }
 }
 
+#[tauri::command]
+async fn ingest_directory(path: String, name: Option<String>, tags: Option<Vec<String>>) -> Result<String, String> {
+    let backend_url  = get_backend_url();
+    let payload = json!({
+        "path": path,
+        "name": name,
+        "tags": tags.unwrap_or_default(),
+    });
+    let client = reqwest::Client::new();
+    match client.post(&format!("{}/directory", backend_url))
+        .json(&payload)
+        .send()
+        .await {
+            Ok(response) if response.status().is_success() => {
+                let report = response.json::<serde_json::Value>().await.unwrap_or_default();
+                Ok(format!(
+                    "Directory ingested: {} files, {} chunks",
+                    report["total_files"], report["chunks"]
+                ))
+            },
+            Ok(response) => Err(response.text().await.unwrap_or_else(|e| e.to_string())),
+            Err(e) => Err(format!("Request failed: {}", e)),
+        }
+}
+
+#[tauri::command]
+async fn fetch_document_insights(item: ListItem) -> Result<DocumentInsights, String> {
+    let backend_url = get_backend_url();
+    match reqwest::Client::new()
+        .get(&format!("{}/documents/{}", backend_url, item.name))
+        .send()
+        .await
+    {
+        Ok(response) if response.status() == reqwest::StatusCode::NOT_FOUND => Ok(DocumentInsights::default()),
+        Ok(response) => {
+                response.json::<DocumentInsights>()
+                .await
+                .map_err(|e| e.to_string())
+            },
+        Err(e) => Err(e.to_string())
+    }
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
-        .invoke_handler(tauri::generate_handler![fetch_list_items, fetch_content, process_prompt, upload_file])
+        .invoke_handler(tauri::generate_handler![fetch_list_items, fetch_content, process_prompt, upload_file, ingest_directory, fetch_document_insights])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
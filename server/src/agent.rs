@@ -0,0 +1,225 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, info};
+
+use crate::{
+    extractor::{self, Chunk},
+    metadata::SearchFilter,
+    processor::Processor,
+    utils::{generate_json, generate_text},
+};
+
+// steps of the agent unless told otherwise, and at most
+pub const DEFAULT_STEPS: usize = 5;
+const MAX_STEPS: usize = 10;
+// characters of every search result shown to the model while planning
+const EXCERPT_CHARS: usize = 500;
+
+// AgentStep is a step of the reasoning trace: the thought of the model and its action
+#[derive(Serialize, Debug, Default)]
+pub struct AgentStep {
+    pub step: usize,
+    pub thought: String,
+    // search or answer
+    pub action: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub doc_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    // citations & excerpts of the chunks found by the search
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub results: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// AgentAnswer is the final answer along with the trace of the steps leading to it
+#[derive(Serialize, Debug)]
+pub struct AgentAnswer {
+    pub answer: String,
+    // the model decided to answer within the steps (else the answer is given from what was found)
+    pub finished: bool,
+    pub steps: Vec<AgentStep>,
+}
+
+// the decision of the model at every step
+#[derive(Deserialize)]
+struct Decision {
+    #[serde(default)]
+    thought: String,
+    action: String,
+    #[serde(default)]
+    doc_name: Option<String>,
+    #[serde(default)]
+    query: Option<String>,
+}
+
+// run lets the model plan the sub-questions of the question and search the documents for them,
+// one search per step, until it has found enough to answer (or the steps run out).
+// The final answer is then synthesized from all the chunks found.
+pub async fn run(
+    processor: &Processor,
+    question: &str,
+    doc_names: &[String],
+    filter: Option<&SearchFilter>,
+    max_steps: usize,
+) -> Result<AgentAnswer> {
+    if doc_names.is_empty() {
+        return Err(anyhow!("no document to search"));
+    }
+    for doc_name in doc_names.iter() {
+        processor.resolve_document(doc_name)?;
+    }
+    let settings = &processor.settings;
+    let max_steps = max_steps.clamp(1, MAX_STEPS);
+    let format = json!({
+        "type": "object",
+        "properties": {
+            "thought": { "type": "string" },
+            "action": { "type": "string", "enum": ["search", "answer"] },
+            "doc_name": { "type": "string", "enum": doc_names },
+            "query": { "type": "string" }
+        },
+        "required": ["thought", "action"]
+    });
+
+    let mut steps: Vec<AgentStep> = vec![];
+    let mut sources: Vec<(String, Chunk)> = vec![];
+    let mut finished = false;
+    for step in 1..=max_steps {
+        let prompt = format!(
+            "You are a research agent answering a question from documents you can search.
+            Break the question down into sub-questions (e.g. one per document, version or entity compared)
+            and search for them one at a time. Every step, give your thought and either:
+            the action search along with the doc_name to search and the query (a short search query),
+            or the action answer once the results found so far answer the question.
+            You have {} steps left.
+            Documents: {}
+            Question: {question}
+            Previous steps:
+            {}",
+            max_steps - step + 1,
+            doc_names.join(", "),
+            trace(&steps)
+        );
+        let output = generate_json(settings, &prompt, &format)
+            .await
+            .context("unable to plan the next step")?;
+        let mut agent_step = AgentStep {
+            step,
+            ..Default::default()
+        };
+        let decision = match serde_json::from_str::<Decision>(&output) {
+            Ok(decision) => decision,
+            Err(e) => {
+                agent_step.action = "invalid".to_string();
+                agent_step.error = Some(format!("the step is not valid JSON: {}", e));
+                steps.push(agent_step);
+                continue;
+            }
+        };
+        debug!(
+            "agent step {}: {} ({})",
+            step, decision.action, decision.thought
+        );
+        agent_step.thought = decision.thought;
+        agent_step.action = decision.action;
+        if agent_step.action == "answer" {
+            steps.push(agent_step);
+            finished = true;
+            break;
+        }
+        agent_step.doc_name = decision.doc_name;
+        agent_step.query = decision.query;
+        let (Some(doc_name), Some(query)) = (&agent_step.doc_name, &agent_step.query) else {
+            agent_step.error = Some("a search needs a doc_name and a query".to_string());
+            steps.push(agent_step);
+            continue;
+        };
+        if !doc_names.contains(doc_name) {
+            agent_step.error = Some(format!("unknown document {}", doc_name));
+            steps.push(agent_step);
+            continue;
+        }
+        match processor.search_tool(doc_name, query, filter).await {
+            Ok(found) => {
+                agent_step.results = found
+                    .iter()
+                    .map(|chunk| label(doc_name, chunk, EXCERPT_CHARS))
+                    .collect();
+                for chunk in found {
+                    if !sources
+                        .iter()
+                        .any(|(name, source)| name == doc_name && source.text == chunk.text)
+                    {
+                        sources.push((doc_name.to_string(), chunk));
+                    }
+                }
+            }
+            Err(e) => agent_step.error = Some(format!("{:#}", e)),
+        }
+        steps.push(agent_step);
+    }
+    info!(
+        "agent searched {} steps, {} chunks found",
+        steps.len(),
+        sources.len()
+    );
+
+    let context = sources
+        .iter()
+        .map(|(doc_name, chunk)| label(doc_name, chunk, usize::MAX))
+        .collect::<Vec<String>>()
+        .join("\n\n");
+    let prompt = format!(
+        "You are an expert providing factually accurate answers.
+        Use only the information from the context gathered from the documents to generate your answer.
+        If the context doesn't contain relevant information say I don't know as context doesn't have much info.
+        For comparisons, state what every document says and how they differ.
+        Cite the labels given in the context (e.g. warranty-v1.pdf, page 12) for the facts you use.
+        Context: {context} Question: {question} Answer(only use the context for your answer)"
+    );
+    let answer = generate_text(settings, &prompt).await?;
+    Ok(AgentAnswer {
+        answer,
+        finished,
+        steps,
+    })
+}
+
+// trace shows the previous steps along with what their searches found
+fn trace(steps: &[AgentStep]) -> String {
+    if steps.is_empty() {
+        return "none".to_string();
+    }
+    steps
+        .iter()
+        .map(|step| {
+            let mut text = format!("Step {}: {}", step.step, step.thought);
+            if let (Some(doc_name), Some(query)) = (&step.doc_name, &step.query) {
+                text.push_str(&format!("\nsearch {} for \"{}\"", doc_name, query));
+            }
+            if let Some(error) = &step.error {
+                text.push_str(&format!("\nerror: {}", error));
+            } else if step.action == "search" && step.results.is_empty() {
+                text.push_str("\nnothing found");
+            }
+            for result in step.results.iter() {
+                text.push_str(&format!("\n- {}", result));
+            }
+            text
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// label quotes the chunk along with its document & its citation
+fn label(doc_name: &str, chunk: &Chunk, chars: usize) -> String {
+    let name = extractor::doc_name(doc_name);
+    let text: String = chunk.text.chars().take(chars).collect();
+    match chunk.citation() {
+        Some(citation) => format!("[{}, {}] {}", name, citation, text),
+        None => format!("[{}] {}", name, text),
+    }
+}
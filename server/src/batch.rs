@@ -0,0 +1,220 @@
+use std::{
+    collections::HashSet,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::Path,
+};
+
+use anyhow::{anyhow, Context, Result};
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{metadata::SearchFilter, processor::Processor, query::RetrievalOptions};
+
+// questions answered at once unless told otherwise
+pub const DEFAULT_CONCURRENCY: usize = 4;
+const MAX_CONCURRENCY: usize = 32;
+// characters of the context chunk quoted along with its citation
+const EXCERPT_CHARS: usize = 200;
+
+// BatchQuery is a line of the input jsonl file
+#[derive(Deserialize, Debug)]
+struct BatchQuery {
+    // id of the question, defaults to its line number
+    #[serde(default)]
+    id: Option<String>,
+    question: String,
+    // the document to be asked, defaults to the document of the batch
+    #[serde(default)]
+    doc_name: Option<String>,
+    #[serde(default)]
+    filter: Option<SearchFilter>,
+    // query strategy & context diversity of the question, the configured ones by default
+    #[serde(flatten)]
+    retrieval: RetrievalOptions,
+}
+
+// BatchQuestion is the question of the batch along with the document it is asked to
+#[derive(Debug)]
+pub struct BatchQuestion {
+    pub id: String,
+    pub question: String,
+    pub doc_name: String,
+    pub filter: Option<SearchFilter>,
+    pub retrieval: RetrievalOptions,
+}
+
+// BatchResult is a line of the output jsonl file
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BatchResult {
+    pub id: String,
+    pub question: String,
+    pub doc_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub answer: Option<String>,
+    // the context chunks the answer was generated from
+    #[serde(default)]
+    pub citations: Vec<Citation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Citation {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub excerpt: String,
+}
+
+// BatchSummary counts the questions of the batch by their outcome
+#[derive(Serialize, Debug, Default)]
+pub struct BatchSummary {
+    pub total: usize,
+    // answered by a previous run of the batch
+    pub resumed: usize,
+    pub answered: usize,
+    pub failed: usize,
+}
+
+// parse_questions reads the jsonl questions (`{"id": "q1", "question": "..", "doc_name": ".."}`),
+// the questions without a document are asked to the given document
+pub fn parse_questions(input: &str, doc_name: Option<&str>) -> Result<Vec<BatchQuestion>> {
+    let mut questions = vec![];
+    let mut ids = HashSet::new();
+    for (i, line) in input.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let query: BatchQuery =
+            serde_json::from_str(line).with_context(|| format!("line {}: invalid query", i + 1))?;
+        let id = query.id.unwrap_or((i + 1).to_string());
+        if !ids.insert(id.to_string()) {
+            return Err(anyhow!("line {}: the id {} is used twice", i + 1, id));
+        }
+        let doc_name = query
+            .doc_name
+            .or(doc_name.map(|name| name.to_string()))
+            .ok_or(anyhow!("line {}: no document to ask the question", i + 1))?;
+        questions.push(BatchQuestion {
+            id,
+            question: query.question,
+            doc_name,
+            filter: query.filter,
+            retrieval: query.retrieval,
+        });
+    }
+    if questions.is_empty() {
+        return Err(anyhow!("no questions were given"));
+    }
+    Ok(questions)
+}
+
+// run answers the questions (a few at once) appending every result to the output jsonl file
+// as soon as it is generated. The questions answered by a previous run into the same file
+// are skipped so that an interrupted batch resumes where it stopped.
+pub async fn run(
+    processor: &Processor,
+    questions: Vec<BatchQuestion>,
+    output: &Path,
+    concurrency: usize,
+) -> Result<BatchSummary> {
+    let answered = resume(output)?;
+    let mut summary = BatchSummary {
+        total: questions.len(),
+        ..Default::default()
+    };
+    let pending: Vec<BatchQuestion> = questions
+        .into_iter()
+        .filter(|question| !answered.contains(&question.id))
+        .collect();
+    summary.resumed = summary.total - pending.len();
+    if summary.resumed > 0 {
+        info!("resuming the batch: {} questions answered", summary.resumed);
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(output)
+        .with_context(|| format!("unable to write the results to {}", output.display()))?;
+    let mut results = stream::iter(pending)
+        .map(|question| answer(processor, question))
+        .buffer_unordered(concurrency.clamp(1, MAX_CONCURRENCY));
+    while let Some(result) = results.next().await {
+        if result.error.is_some() {
+            summary.failed += 1;
+        } else {
+            summary.answered += 1;
+        }
+        writeln!(file, "{}", serde_json::to_string(&result)?)?;
+        file.flush()?;
+    }
+    Ok(summary)
+}
+
+// read_results reads back the results written to the output jsonl file
+pub fn read_results(output: &Path) -> Result<Vec<BatchResult>> {
+    let content = fs::read_to_string(output)
+        .with_context(|| format!("unable to read the results of {}", output.display()))?;
+    Ok(content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+async fn answer(processor: &Processor, question: BatchQuestion) -> BatchResult {
+    let result = processor
+        .answer_with_sources(
+            &question.question,
+            &question.doc_name,
+            question.filter.as_ref(),
+            &question.retrieval,
+        )
+        .await;
+    let mut batch_result = BatchResult {
+        id: question.id,
+        question: question.question,
+        doc_name: question.doc_name,
+        answer: None,
+        citations: vec![],
+        error: None,
+    };
+    match result {
+        Ok((answer, sources)) => {
+            batch_result.answer = Some(answer);
+            batch_result.citations = sources
+                .iter()
+                .map(|chunk| Citation {
+                    label: chunk.citation(),
+                    excerpt: chunk.text.chars().take(EXCERPT_CHARS).collect(),
+                })
+                .collect();
+        }
+        Err(e) => {
+            warn!("question {} failed: {:#}", batch_result.id, e);
+            batch_result.error = Some(format!("{:#}", e));
+        }
+    }
+    batch_result
+}
+
+// resume keeps the answered questions of the output file (dropping the failed ones
+// to be asked again) and returns their ids
+fn resume(output: &Path) -> Result<HashSet<String>> {
+    if !output.exists() {
+        return Ok(HashSet::new());
+    }
+    let results: Vec<BatchResult> = read_results(output)?
+        .into_iter()
+        .filter(|result| result.error.is_none())
+        .collect();
+    let mut content = String::new();
+    for result in results.iter() {
+        content.push_str(&serde_json::to_string(result)?);
+        content.push('\n');
+    }
+    fs::write(output, content)
+        .with_context(|| format!("unable to write the results to {}", output.display()))?;
+    Ok(results.into_iter().map(|result| result.id).collect())
+}
@@ -0,0 +1,94 @@
+use std::{collections::BTreeMap, fs, sync::RwLock};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+// the catalog is kept along with the uploaded files
+const CATALOG_PATH: &str = "./uploads/catalog.json";
+
+// DocumentEntry describes an ingested document
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct DocumentEntry {
+    // name of the document
+    pub name: String,
+    // collection holding its chunks (the document name unless ingested into a workspace)
+    pub collection: String,
+    pub file_name: String,
+    pub chunks: usize,
+    // unix timestamp (seconds) of the ingestion
+    pub ingested_at: i64,
+    // path of the file within the watched folder
+    #[serde(default)]
+    pub source: Option<String>,
+    // sha256 of the file content when it was ingested
+    #[serde(default)]
+    pub hash: Option<String>,
+    // summary & suggested questions generated after the ingestion:
+    // pending, ready or failed (none when disabled)
+    #[serde(default)]
+    pub insights: Option<String>,
+    #[serde(default)]
+    pub summary: Option<String>,
+    #[serde(default)]
+    pub questions: Vec<String>,
+}
+
+// Catalog keeps track of the ingested documents, saved as json on every change
+pub struct Catalog {
+    documents: RwLock<BTreeMap<String, DocumentEntry>>,
+}
+
+impl Catalog {
+    // load the saved catalog, starting an empty one when there is none
+    pub fn load() -> Self {
+        let documents = fs::read_to_string(CATALOG_PATH)
+            .ok()
+            .and_then(|content| {
+                serde_json::from_str(&content)
+                    .inspect_err(|e| warn!("ignoring the unreadable catalog: {}", e))
+                    .ok()
+            })
+            .unwrap_or_default();
+        Self {
+            documents: RwLock::new(documents),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<DocumentEntry> {
+        self.documents.read().unwrap().get(name).cloned()
+    }
+
+    pub fn list(&self) -> Vec<DocumentEntry> {
+        self.documents.read().unwrap().values().cloned().collect()
+    }
+
+    pub fn upsert(&self, entry: DocumentEntry) -> Result<()> {
+        let mut documents = self.documents.write().unwrap();
+        documents.insert(entry.name.to_string(), entry);
+        save(&documents)
+    }
+
+    // update applies the change to the document entry if it is in the catalog
+    pub fn update(&self, name: &str, change: impl FnOnce(&mut DocumentEntry)) -> Result<()> {
+        let mut documents = self.documents.write().unwrap();
+        if let Some(entry) = documents.get_mut(name) {
+            change(entry);
+            save(&documents)?;
+        }
+        Ok(())
+    }
+
+    pub fn remove(&self, name: &str) -> Result<Option<DocumentEntry>> {
+        let mut documents = self.documents.write().unwrap();
+        let entry = documents.remove(name);
+        save(&documents)?;
+        Ok(entry)
+    }
+}
+
+fn save(documents: &BTreeMap<String, DocumentEntry>) -> Result<()> {
+    fs::create_dir_all("./uploads")?;
+    let content = serde_json::to_string_pretty(documents)?;
+    fs::write(CATALOG_PATH, content).context("unable to save the catalog")
+}
@@ -0,0 +1,375 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{info, warn};
+
+use crate::{
+    extractor::{Chunk, PARENT_TEXT_KEY},
+    parents::{chunk_doc_name, to_parents, unit_index},
+    processor::Processor,
+    utils::{cosine_similarity, generate_json, get_content_embeddings, ConfigVar},
+};
+
+// sections compared per document at most, the larger documents are compared on a focus topic
+const MAX_SECTIONS: usize = 500;
+// sections retrieved per document for the focus topic
+const FOCUS_SECTIONS: usize = 10;
+// the sections less similar are not aligned (each being found in its document only)
+pub const ALIGN_THRESHOLD: f32 = 0.7;
+// the aligned sections this similar are the same when the model can't tell
+const SAME_THRESHOLD: f32 = 0.95;
+// aligned sections judged by the model per request
+const PAIRS_PER_CALL: usize = 10;
+
+// Section is a chunk of one of the documents along with its citation
+#[derive(Serialize, Debug, Clone)]
+pub struct Section {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub citation: Option<String>,
+    pub text: String,
+}
+
+impl From<&Chunk> for Section {
+    fn from(chunk: &Chunk) -> Self {
+        Section {
+            citation: chunk.citation(),
+            text: chunk.text.to_string(),
+        }
+    }
+}
+
+// AlignedSections are the sections of both documents covering the same topic
+#[derive(Serialize, Debug)]
+pub struct AlignedSections {
+    pub a: Section,
+    pub b: Section,
+    pub similarity: f32,
+    // what changed between the sections, as told by the model
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub difference: Option<String>,
+}
+
+// Comparison sorts the sections of both documents into the same, the changed and the unmatched ones
+#[derive(Serialize, Debug)]
+pub struct Comparison {
+    pub doc_a: String,
+    pub doc_b: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub focus: Option<String>,
+    pub same: Vec<AlignedSections>,
+    pub changed: Vec<AlignedSections>,
+    pub only_in_a: Vec<Section>,
+    pub only_in_b: Vec<Section>,
+}
+
+// Alignment pairs the indexes of the similar sections of both documents
+#[derive(Debug, Default, PartialEq)]
+pub struct Alignment {
+    // index in a, index in b & their similarity
+    pub pairs: Vec<(usize, usize, f32)>,
+    pub only_in_a: Vec<usize>,
+    pub only_in_b: Vec<usize>,
+}
+
+#[derive(Deserialize)]
+struct Judgement {
+    pairs: Vec<PairVerdict>,
+}
+
+#[derive(Deserialize)]
+struct PairVerdict {
+    // 1-based number of the pair
+    pair: usize,
+    status: PairStatus,
+    #[serde(default)]
+    difference: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum PairStatus {
+    Same,
+    Changed,
+}
+
+// compare aligns the sections of both documents (all of them, or those about the focus topic)
+// by the similarity of their embeddings and asks the model whether the aligned sections differ
+pub async fn compare(
+    processor: &Processor,
+    doc_a: &str,
+    doc_b: &str,
+    focus: Option<&str>,
+) -> Result<Comparison> {
+    let settings = &processor.settings;
+    let (sections_a, embeddings_a) = sections(processor, doc_a, focus).await?;
+    let (sections_b, embeddings_b) = sections(processor, doc_b, focus).await?;
+    let alignment = align(&embeddings_a, &embeddings_b, ALIGN_THRESHOLD);
+    info!(
+        "{} sections of {} aligned with {}",
+        alignment.pairs.len(),
+        doc_a,
+        doc_b
+    );
+
+    // the identical sections need no judgement
+    let mut verdicts: HashMap<usize, (PairStatus, Option<String>)> = HashMap::new();
+    let mut to_judge = vec![];
+    for (pair, (a, b, _)) in alignment.pairs.iter().enumerate() {
+        if normalize(&sections_a[*a].text) == normalize(&sections_b[*b].text) {
+            verdicts.insert(pair, (PairStatus::Same, None));
+        } else {
+            to_judge.push(pair);
+        }
+    }
+    for batch in to_judge.chunks(PAIRS_PER_CALL) {
+        let pairs: Vec<(&Chunk, &Chunk)> = batch
+            .iter()
+            .map(|pair| {
+                let (a, b, _) = alignment.pairs[*pair];
+                (&sections_a[a], &sections_b[b])
+            })
+            .collect();
+        match judge(settings, &pairs, focus).await {
+            Ok(judged) => {
+                for (number, verdict) in judged {
+                    if let Some(pair) = number.checked_sub(1).and_then(|i| batch.get(i)) {
+                        verdicts.insert(*pair, verdict);
+                    }
+                }
+            }
+            Err(e) => warn!("unable to judge the aligned sections: {:#}", e),
+        }
+    }
+
+    let mut comparison = Comparison {
+        doc_a: doc_a.to_string(),
+        doc_b: doc_b.to_string(),
+        focus: focus.map(|focus| focus.to_string()),
+        same: vec![],
+        changed: vec![],
+        only_in_a: alignment
+            .only_in_a
+            .iter()
+            .map(|a| Section::from(&sections_a[*a]))
+            .collect(),
+        only_in_b: alignment
+            .only_in_b
+            .iter()
+            .map(|b| Section::from(&sections_b[*b]))
+            .collect(),
+    };
+    for (pair, (a, b, similarity)) in alignment.pairs.into_iter().enumerate() {
+        // the pairs left unjudged are told apart by their similarity
+        let (status, difference) =
+            verdicts
+                .remove(&pair)
+                .unwrap_or(if similarity >= SAME_THRESHOLD {
+                    (PairStatus::Same, None)
+                } else {
+                    (PairStatus::Changed, None)
+                });
+        let aligned = AlignedSections {
+            a: Section::from(&sections_a[a]),
+            b: Section::from(&sections_b[b]),
+            similarity,
+            difference: difference.filter(|difference| !difference.trim().is_empty()),
+        };
+        match status {
+            PairStatus::Same => comparison.same.push(aligned),
+            PairStatus::Changed => comparison.changed.push(aligned),
+        }
+    }
+    Ok(comparison)
+}
+
+// align pairs every section with its most similar section of the other document:
+// the most similar pairs first, every section being aligned once at most
+pub fn align(a: &[Vec<f32>], b: &[Vec<f32>], threshold: f32) -> Alignment {
+    let mut candidates = vec![];
+    for (i, embedding_a) in a.iter().enumerate() {
+        for (j, embedding_b) in b.iter().enumerate() {
+            let similarity = cosine_similarity(embedding_a, embedding_b);
+            if similarity >= threshold {
+                candidates.push((i, j, similarity));
+            }
+        }
+    }
+    candidates.sort_by(|x, y| y.2.total_cmp(&x.2));
+    let mut aligned_a = vec![false; a.len()];
+    let mut aligned_b = vec![false; b.len()];
+    let mut pairs = vec![];
+    for (i, j, similarity) in candidates {
+        if !aligned_a[i] && !aligned_b[j] {
+            aligned_a[i] = true;
+            aligned_b[j] = true;
+            pairs.push((i, j, similarity));
+        }
+    }
+    // the pairs in the order of the first document
+    pairs.sort_by_key(|(i, _, _)| *i);
+    Alignment {
+        pairs,
+        only_in_a: (0..a.len()).filter(|i| !aligned_a[*i]).collect(),
+        only_in_b: (0..b.len()).filter(|j| !aligned_b[*j]).collect(),
+    }
+}
+
+// TooManySections reports a document too large to be compared section by section without a focus
+#[derive(Debug)]
+pub struct TooManySections {
+    pub doc_name: String,
+    pub sections: usize,
+}
+
+impl fmt::Display for TooManySections {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} has {} sections, at most {} are compared: give a focus topic",
+            self.doc_name, self.sections, MAX_SECTIONS
+        )
+    }
+}
+
+impl std::error::Error for TooManySections {}
+
+// sections reads the chunks of the document compared (those about the focus topic, else all of them)
+// along with their vectors
+async fn sections(
+    processor: &Processor,
+    doc_name: &str,
+    focus: Option<&str>,
+) -> Result<(Vec<Chunk>, Vec<Vec<f32>>)> {
+    let (collection, filter) = processor.document_scope(doc_name, None);
+    let candidates = match focus {
+        Some(focus) => {
+            let embedding = get_content_embeddings(processor.settings.clone(), focus)
+                .await
+                .context("unable to embed the focus")?;
+            processor
+                .vec_store
+                .search_candidates(&collection, embedding, Some(&filter), FOCUS_SECTIONS as u64)
+                .await?
+        }
+        None => {
+            processor
+                .vec_store
+                .document_vectors(&collection, doc_name)
+                .await?
+        }
+    };
+    let (chunks, vectors) = with_vectors(&processor.settings, candidates).await?;
+    if chunks.is_empty() {
+        return Err(anyhow!("no chunks were found for {}", doc_name));
+    }
+    if chunks.len() > MAX_SECTIONS {
+        return Err(TooManySections {
+            doc_name: doc_name.to_string(),
+            sections: chunks.len(),
+        }
+        .into());
+    }
+    Ok((chunks, vectors))
+}
+
+// with_vectors keeps the stored vector of the chunks embedded as is,
+// the child chunks being replaced by their parent (embedded once)
+async fn with_vectors(
+    settings: &ConfigVar,
+    candidates: Vec<(Chunk, Vec<f32>)>,
+) -> Result<(Vec<Chunk>, Vec<Vec<f32>>)> {
+    let mut seen = HashSet::new();
+    let mut chunks = vec![];
+    let mut vectors = vec![];
+    for (chunk, vector) in candidates {
+        let is_child = chunk.metadata.contains_key(PARENT_TEXT_KEY);
+        let Some(section) = to_parents(vec![chunk]).pop() else {
+            continue;
+        };
+        let key = (
+            chunk_doc_name(&section),
+            unit_index(&section)
+                .map(|i| i.to_string())
+                .unwrap_or(section.text.to_string()),
+        );
+        if !seen.insert(key) {
+            continue;
+        }
+        let vector = if is_child {
+            get_content_embeddings(settings.clone(), &section.text)
+                .await
+                .context("unable to embed the sections")?
+        } else {
+            vector
+        };
+        chunks.push(section);
+        vectors.push(vector);
+    }
+    Ok((chunks, vectors))
+}
+
+// judge asks the model whether every pair of aligned sections says the same, keyed by the pair number
+async fn judge(
+    settings: &ConfigVar,
+    pairs: &[(&Chunk, &Chunk)],
+    focus: Option<&str>,
+) -> Result<HashMap<usize, (PairStatus, Option<String>)>> {
+    let numbered = pairs
+        .iter()
+        .enumerate()
+        .map(|(i, (a, b))| format!("Pair {}:\nA: {}\nB: {}", i + 1, a.text, b.text))
+        .collect::<Vec<String>>()
+        .join("\n\n");
+    let focus = focus
+        .map(|focus| format!("Focus on {}.\n", focus))
+        .unwrap_or_default();
+    let prompt = format!(
+        "You are comparing two versions of a document section by section.
+        For every numbered pair of sections (A from the first document, B from the second) tell whether
+        they say the same (status same, ignoring the wording & the formatting) or not (status changed),
+        and for the changed ones describe in one sentence what changed from A to B.
+        {focus}Pairs:
+        {numbered}"
+    );
+    let format = json!({
+        "type": "object",
+        "properties": {
+            "pairs": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "pair": { "type": "integer" },
+                        "status": { "type": "string", "enum": ["same", "changed"] },
+                        "difference": { "type": "string" }
+                    },
+                    "required": ["pair", "status"]
+                }
+            }
+        },
+        "required": ["pairs"]
+    });
+    let output = generate_json(settings, &prompt, &format).await?;
+    let judgement: Judgement =
+        serde_json::from_str(&output).context("the comparison answer is not valid")?;
+    Ok(judgement
+        .pairs
+        .into_iter()
+        .map(|verdict| {
+            let difference = match verdict.status {
+                PairStatus::Same => None,
+                PairStatus::Changed => verdict.difference,
+            };
+            (verdict.pair, (verdict.status, difference))
+        })
+        .collect())
+}
+
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
@@ -0,0 +1,349 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{info, warn};
+
+use crate::{
+    extractor::{Chunk, PAGE_END_KEY, PAGE_START_KEY},
+    metadata::SearchFilter,
+    processor::{answer_prompt, Processor},
+    query::{QueryStrategy, RetrievalOptions},
+    utils::{generate_json, generate_text, ConfigVar},
+};
+
+// chunks ranked per question unless told otherwise (the size of the prompt context)
+pub const DEFAULT_K: usize = 6;
+// the reports are saved here unless an output file is given
+const EVAL_DIR: &str = "./evals";
+
+// GoldenQuestion is a line of the golden dataset
+#[derive(Deserialize, Debug)]
+struct GoldenQuestion {
+    question: String,
+    expected_answer: String,
+    // pages of the document holding the answer
+    #[serde(default)]
+    expected_pages: Vec<u64>,
+    // the document to be asked, defaults to the document of the evaluation
+    #[serde(default)]
+    doc_name: Option<String>,
+    #[serde(default)]
+    filter: Option<SearchFilter>,
+}
+
+// EvalConfig is the configuration evaluated, reports of the same dataset
+// are compared across the configurations
+#[derive(Serialize, Debug)]
+pub struct EvalConfig {
+    pub embedding_model_name: Option<String>,
+    pub embedding_model_chunk_size: Option<usize>,
+    pub child_chunk_size: Option<usize>,
+    pub generate_model_name: Option<String>,
+    pub query_strategy: QueryStrategy,
+    pub mmr_lambda: Option<f32>,
+    pub k: usize,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct Latency {
+    pub mean_ms: u64,
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+}
+
+// EvalMetrics averages the metrics over the questions,
+// the retrieval metrics over the questions having expected pages
+#[derive(Serialize, Debug, Default)]
+pub struct EvalMetrics {
+    pub recall_at_k: Option<f64>,
+    pub mrr: Option<f64>,
+    // LLM judge scores from 1 to 5
+    pub faithfulness: Option<f64>,
+    pub correctness: Option<f64>,
+    pub retrieval_latency: Latency,
+    pub answer_latency: Latency,
+}
+
+// QuestionResult reports how the question was answered
+#[derive(Serialize, Debug, Default)]
+pub struct QuestionResult {
+    pub question: String,
+    pub doc_name: String,
+    pub expected_pages: Vec<u64>,
+    // pages of the ranked chunks, most similar first
+    pub retrieved_pages: Vec<Vec<u64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recall: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reciprocal_rank: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub answer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub faithfulness: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub correctness: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub judge_reasoning: Option<String>,
+    // latencies of the retrieval & the generation, when they succeeded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retrieval_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub answer_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct EvalReport {
+    pub dataset: String,
+    pub config: EvalConfig,
+    // unix timestamp (seconds) of the evaluation
+    pub evaluated_at: i64,
+    pub questions: usize,
+    pub failed: usize,
+    pub metrics: EvalMetrics,
+    pub results: Vec<QuestionResult>,
+}
+
+// scores given by the LLM judge
+#[derive(Deserialize)]
+struct Judgement {
+    faithfulness: u8,
+    correctness: u8,
+    #[serde(default)]
+    reasoning: String,
+}
+
+// evaluate runs the retrieval & the generation for every question of the golden dataset
+// (one at a time so that the latencies are comparable) and reports the metrics
+pub async fn evaluate(
+    processor: &Processor,
+    dataset: &Path,
+    doc_name: Option<&str>,
+    k: usize,
+) -> Result<EvalReport> {
+    let content = fs::read_to_string(dataset)
+        .with_context(|| format!("unable to read the dataset {}", dataset.display()))?;
+    let mut questions = vec![];
+    for (i, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let question: GoldenQuestion = serde_json::from_str(line)
+            .with_context(|| format!("line {}: invalid golden question", i + 1))?;
+        questions.push(question);
+    }
+    if questions.is_empty() {
+        return Err(anyhow!("the dataset has no questions"));
+    }
+
+    let settings = &processor.settings;
+    let mut results = vec![];
+    for (i, golden) in questions.into_iter().enumerate() {
+        info!("evaluating question {}: {}", i + 1, golden.question);
+        let Some(doc_name) = golden.doc_name.as_deref().or(doc_name) else {
+            return Err(anyhow!("question {}: no document to ask", i + 1));
+        };
+        let doc_name = doc_name.to_string();
+        let mut result = QuestionResult {
+            question: golden.question.to_string(),
+            doc_name: doc_name.to_string(),
+            expected_pages: golden.expected_pages.clone(),
+            ..Default::default()
+        };
+        if let Err(e) =
+            evaluate_question(processor, settings, &golden, &doc_name, k, &mut result).await
+        {
+            warn!("question {} failed: {:#}", i + 1, e);
+            result.error = Some(format!("{:#}", e));
+        }
+        results.push(result);
+    }
+
+    Ok(EvalReport {
+        dataset: dataset.display().to_string(),
+        config: EvalConfig {
+            embedding_model_name: settings.embedding_model_name.clone(),
+            embedding_model_chunk_size: settings.embedding_model_chunk_size,
+            child_chunk_size: settings.child_chunk_size,
+            generate_model_name: settings.generate_model_name.clone(),
+            query_strategy: query_strategy(settings),
+            mmr_lambda: settings.mmr_lambda,
+            k,
+        },
+        evaluated_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default(),
+        questions: results.len(),
+        failed: results.iter().filter(|r| r.error.is_some()).count(),
+        metrics: metrics(&results),
+        results,
+    })
+}
+
+async fn evaluate_question(
+    processor: &Processor,
+    settings: &ConfigVar,
+    golden: &GoldenQuestion,
+    doc_name: &str,
+    k: usize,
+    result: &mut QuestionResult,
+) -> Result<()> {
+    // the retrieval as done for the prompt: the first k chunks of the context against the expected pages
+    let started = Instant::now();
+    let doc_name = processor.resolve_document(doc_name)?;
+    let (coll_name, filter) = processor.document_scope(&doc_name, golden.filter.as_ref());
+    let sources = processor
+        .retrieve_context(
+            &coll_name,
+            &golden.question,
+            Some(&filter),
+            &RetrievalOptions::default(),
+        )
+        .await?;
+    result.retrieval_ms = Some(started.elapsed().as_millis() as u64);
+    result.retrieved_pages = sources.iter().take(k).map(pages).collect();
+    if !golden.expected_pages.is_empty() {
+        let expected: BTreeSet<u64> = golden.expected_pages.iter().copied().collect();
+        let found: BTreeSet<u64> = result
+            .retrieved_pages
+            .iter()
+            .flatten()
+            .copied()
+            .filter(|page| expected.contains(page))
+            .collect();
+        result.recall = Some(found.len() as f64 / expected.len() as f64);
+        let rank = result
+            .retrieved_pages
+            .iter()
+            .position(|pages| pages.iter().any(|page| expected.contains(page)));
+        result.reciprocal_rank = Some(rank.map(|rank| 1.0 / (rank + 1) as f64).unwrap_or(0.0));
+    }
+
+    // the generation from the context retrieved, as answered to the prompt
+    let started = Instant::now();
+    let answer = generate_text(settings, &answer_prompt(&golden.question, &sources)).await?;
+    result.answer_ms = Some(started.elapsed().as_millis() as u64);
+    result.answer = Some(answer.to_string());
+    let judgement = judge(settings, golden, &answer, &sources).await?;
+    result.faithfulness = Some(judgement.faithfulness.clamp(1, 5));
+    result.correctness = Some(judgement.correctness.clamp(1, 5));
+    result.judge_reasoning = Some(judgement.reasoning);
+    Ok(())
+}
+
+// judge asks the generation model to score the answer against its context & the expected answer
+async fn judge(
+    settings: &ConfigVar,
+    golden: &GoldenQuestion,
+    answer: &str,
+    sources: &[Chunk],
+) -> Result<Judgement> {
+    let context = sources
+        .iter()
+        .map(|chunk| chunk.text.as_str())
+        .collect::<Vec<&str>>()
+        .join("\n\n");
+    let prompt = format!(
+        "You are grading the answer of a question answering system.
+        Score from 1 to 5:
+        faithfulness: is every claim of the answer supported by the context (5 = fully supported, 1 = mostly unsupported)
+        correctness: does the answer state the facts of the expected answer (5 = same facts, 1 = wrong or missing)
+        Give a one sentence reasoning.
+        Question: {}
+        Expected answer: {}
+        Context: {context}
+        Answer: {answer}",
+        golden.question, golden.expected_answer
+    );
+    let score = json!({ "type": "integer", "minimum": 1, "maximum": 5 });
+    let format = json!({
+        "type": "object",
+        "properties": {
+            "faithfulness": score,
+            "correctness": score,
+            "reasoning": { "type": "string" }
+        },
+        "required": ["faithfulness", "correctness", "reasoning"]
+    });
+    let output = generate_json(settings, &prompt, &format).await?;
+    serde_json::from_str(&output).context("the judge answer is not valid")
+}
+
+// save writes the report as json, by default under the evaluated configuration
+// (`evals/nomic-embed-text-2048-llama3.1:8b-1700000000.json`)
+pub fn save(report: &EvalReport, output: Option<&Path>) -> Result<PathBuf> {
+    let path = match output {
+        Some(output) => output.to_path_buf(),
+        None => {
+            fs::create_dir_all(EVAL_DIR)?;
+            let config = &report.config;
+            let name = format!(
+                "{}-{}-{}-{}.json",
+                config.embedding_model_name.as_deref().unwrap_or_default(),
+                config.embedding_model_chunk_size.unwrap_or_default(),
+                config.generate_model_name.as_deref().unwrap_or_default(),
+                report.evaluated_at
+            )
+            .replace(['/', '\\'], "_");
+            Path::new(EVAL_DIR).join(name)
+        }
+    };
+    fs::write(&path, serde_json::to_string_pretty(report)?)
+        .with_context(|| format!("unable to write the report to {}", path.display()))?;
+    Ok(path)
+}
+
+// the configured query strategy, the questions are asked without any of their own
+fn query_strategy(settings: &ConfigVar) -> QueryStrategy {
+    settings.query_strategy.unwrap_or_default()
+}
+
+// the pages covered by the chunk
+fn pages(chunk: &Chunk) -> Vec<u64> {
+    let page = |key: &str| chunk.metadata.get(key).and_then(|v| v.as_u64());
+    match (page(PAGE_START_KEY), page(PAGE_END_KEY)) {
+        (Some(start), Some(end)) => (start..=end.max(start)).collect(),
+        (Some(start), None) => vec![start],
+        _ => vec![],
+    }
+}
+
+// metrics averages every metric over the questions it was measured for,
+// the retrieval of a question being scored even when its generation or its judgement failed
+pub(crate) fn metrics(results: &[QuestionResult]) -> EvalMetrics {
+    EvalMetrics {
+        recall_at_k: mean(results.iter().filter_map(|r| r.recall)),
+        mrr: mean(results.iter().filter_map(|r| r.reciprocal_rank)),
+        faithfulness: mean(results.iter().filter_map(|r| r.faithfulness.map(f64::from))),
+        correctness: mean(results.iter().filter_map(|r| r.correctness.map(f64::from))),
+        retrieval_latency: latency(results.iter().filter_map(|r| r.retrieval_ms).collect()),
+        answer_latency: latency(results.iter().filter_map(|r| r.answer_ms).collect()),
+    }
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let values: Vec<f64> = values.collect();
+    (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+}
+
+fn latency(mut durations: Vec<u64>) -> Latency {
+    if durations.is_empty() {
+        return Latency::default();
+    }
+    durations.sort();
+    let percentile = |p: usize| durations[(durations.len() * p / 100).min(durations.len() - 1)];
+    Latency {
+        mean_ms: durations.iter().sum::<u64>() / durations.len() as u64,
+        p50_ms: percentile(50),
+        p95_ms: percentile(95),
+    }
+}
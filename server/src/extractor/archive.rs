@@ -0,0 +1,318 @@
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, Read},
+    path::{Component, Path},
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use zip::ZipArchive;
+
+use super::{doc_name, file_extension, is_supported, ARCHIVE_EXTENSION, SUPPORTED_EXTENSIONS};
+
+// limits guarding the server against the zip bombs
+const MAX_ENTRIES: usize = 10_000;
+pub(super) const MAX_ENTRY_SIZE: u64 = 200 * 1024 * 1024;
+const MAX_UNPACKED_SIZE: u64 = 2 * 1024 * 1024 * 1024;
+// uncompressed size / compressed size above which the entry is rejected
+pub(super) const MAX_COMPRESSION_RATIO: u64 = 100;
+
+// Unpacked lists the supported entries extracted from the archive
+// and the entries left out along with the reason
+#[derive(Debug, Default)]
+pub struct Unpacked {
+    // (entry path within the archive, extracted file name)
+    pub files: Vec<(String, String)>,
+    pub skipped: Vec<SkippedEntry>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SkippedEntry {
+    pub entry: String,
+    pub reason: String,
+}
+
+// unpack extracts the supported entries of the zip archive into the directory.
+// The entries are flattened (`reports/2024/q1.pdf` -> `reports_2024_q1.pdf`) so that no entry
+// can be written outside of the directory, whatever its path (zip slip).
+pub fn unpack(zip_path: &str, dest_dir: &str) -> Result<Unpacked> {
+    unpack_within(zip_path, dest_dir, MAX_UNPACKED_SIZE)
+}
+
+// unpack_within unpacks the archive as long as the unpacked files add up to the max size
+fn unpack_within(zip_path: &str, dest_dir: &str, max_size: u64) -> Result<Unpacked> {
+    let file = File::open(zip_path).context("Failed to read the archive")?;
+    let mut archive = ZipArchive::new(file).context("the archive is not a valid zip file")?;
+    if archive.len() > MAX_ENTRIES {
+        return Err(anyhow!(
+            "the archive has {} entries, at most {} are allowed",
+            archive.len(),
+            MAX_ENTRIES
+        ));
+    }
+    fs::create_dir_all(dest_dir)?;
+
+    let mut unpacked = Unpacked::default();
+    let mut names = HashSet::new();
+    let mut total_size = 0;
+    for i in 0..archive.len() {
+        let mut entry = match archive.by_index(i) {
+            Ok(entry) => entry,
+            Err(e) => {
+                unpacked.skipped.push(SkippedEntry {
+                    entry: format!("#{}", i),
+                    reason: e.to_string(),
+                });
+                continue;
+            }
+        };
+        if entry.is_dir() {
+            continue;
+        }
+        let entry_name = entry.name().to_string();
+        let skip = |reason: &str| SkippedEntry {
+            entry: entry_name.to_string(),
+            reason: reason.to_string(),
+        };
+        let Some(file_name) = entry.enclosed_name().and_then(|path| flat_name(&path)) else {
+            unpacked.skipped.push(skip("unsafe or hidden path"));
+            continue;
+        };
+        if !is_supported(&file_name) || file_extension(&file_name) == ARCHIVE_EXTENSION {
+            unpacked.skipped.push(skip("unsupported file type"));
+            continue;
+        }
+        if entry.encrypted() {
+            unpacked.skipped.push(skip("encrypted entry"));
+            continue;
+        }
+        if entry.size() > MAX_ENTRY_SIZE {
+            unpacked.skipped.push(skip("entry is larger than 200MB"));
+            continue;
+        }
+        if entry.size() > entry.compressed_size().max(1) * MAX_COMPRESSION_RATIO {
+            unpacked.skipped.push(skip("suspicious compression ratio"));
+            continue;
+        }
+        if total_size + entry.size() > max_size {
+            // the archive is rejected as a whole, the files already unpacked are removed
+            for (_, file_name) in unpacked.files.iter() {
+                let _ = fs::remove_file(Path::new(dest_dir).join(file_name));
+            }
+            return Err(anyhow!("the unpacked archive is larger than 2GB"));
+        }
+
+        let file_name = unique_name(&mut names, dest_dir, file_name);
+        let file_path = Path::new(dest_dir).join(&file_name);
+        // the declared size can't be trusted, the copy stops past the limit
+        let mut output = File::create(&file_path)?;
+        let written = io::copy(&mut (&mut entry).take(MAX_ENTRY_SIZE + 1), &mut output)
+            .with_context(|| format!("unable to extract {}", entry_name));
+        let written = match written {
+            Ok(written) if written <= MAX_ENTRY_SIZE && written == entry.size() => written,
+            Ok(_) => {
+                let _ = fs::remove_file(&file_path);
+                unpacked
+                    .skipped
+                    .push(skip("entry size does not match its header"));
+                continue;
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&file_path);
+                unpacked.skipped.push(skip(&format!("{:#}", e)));
+                continue;
+            }
+        };
+        total_size += written;
+        unpacked.files.push((entry_name, file_name));
+    }
+    Ok(unpacked)
+}
+
+// flat_name joins the components of the relative path,
+// leaving out the hidden files & the macOS resource forks
+fn flat_name(path: &Path) -> Option<String> {
+    let mut parts = vec![];
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => {
+                let part = part.to_str()?;
+                if part.starts_with('.') || part == "__MACOSX" {
+                    return None;
+                }
+                parts.push(part.to_string());
+            }
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!parts.is_empty()).then(|| parts.join("_"))
+}
+
+// suffix the name (`report-2.pdf`) when the archive holds it twice or a document of the same name
+// was uploaded before, so that no entry overwrites an uploaded document
+fn unique_name(names: &mut HashSet<String>, dest_dir: &str, file_name: String) -> String {
+    let extension = Path::new(&file_name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_string();
+    let stem = doc_name(&file_name).to_string();
+    let taken = |names: &HashSet<String>, stem: &str| {
+        names.contains(stem)
+            || SUPPORTED_EXTENSIONS.iter().any(|ext| {
+                Path::new(dest_dir)
+                    .join(format!("{}.{}", stem, ext))
+                    .exists()
+            })
+    };
+    let mut name = stem.to_string();
+    let mut n = 1;
+    while taken(names, &name) {
+        n += 1;
+        name = format!("{}-{}", stem, n);
+    }
+    names.insert(name.to_string());
+    format!("{}.{}", name, extension)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::zip_archive;
+
+    // write_zip packs the entries into the archive & returns its path
+    fn write_zip(dir: &Path, entries: &[(&str, &[u8])]) -> String {
+        let zip_path = dir.join("upload.zip");
+        fs::write(&zip_path, zip_archive(entries)).unwrap();
+        zip_path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn entries_never_overwrite_the_uploaded_documents() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("uploads");
+        fs::create_dir_all(&dest).unwrap();
+        fs::write(dest.join("report.pdf"), b"uploaded before").unwrap();
+        let zip_path = write_zip(
+            dir.path(),
+            &[
+                ("report.csv", b"a,b\n1,2\n"),
+                ("q1/report.csv", b"a,b\n3,4\n"),
+                ("other/report.csv", b"a,b\n5,6\n"),
+            ],
+        );
+
+        let unpacked = unpack(&zip_path, dest.to_str().unwrap()).unwrap();
+        let names: Vec<&str> = unpacked
+            .files
+            .iter()
+            .map(|(_, name)| name.as_str())
+            .collect();
+        assert_eq!(names, ["report-2.csv", "q1_report.csv", "other_report.csv"]);
+        assert_eq!(
+            fs::read(dest.join("report.pdf")).unwrap(),
+            b"uploaded before"
+        );
+
+        // the same names unpacked again get the next suffix
+        let unpacked = unpack(&zip_path, dest.to_str().unwrap()).unwrap();
+        assert_eq!(unpacked.files[0].1, "report-3.csv");
+        assert_eq!(unpacked.files[1].1, "q1_report-2.csv");
+    }
+
+    #[test]
+    fn paths_are_flattened_within_the_directory() {
+        assert_eq!(
+            flat_name(Path::new("reports/2024/q1.pdf")).as_deref(),
+            Some("reports_2024_q1.pdf")
+        );
+        assert_eq!(flat_name(Path::new("./q1.pdf")).as_deref(), Some("q1.pdf"));
+        for path in [
+            "../q1.pdf",
+            "/etc/q1.pdf",
+            "reports/../../q1.pdf",
+            ".hidden.pdf",
+            "reports/.git/q1.pdf",
+            "__MACOSX/reports/q1.pdf",
+            ".",
+        ] {
+            assert_eq!(flat_name(Path::new(path)), None, "{}", path);
+        }
+    }
+
+    #[test]
+    fn unsafe_and_unsupported_entries_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("uploads");
+        let bomb = vec![b'a'; 1024 * 1024];
+        let zip_path = write_zip(
+            dir.path(),
+            &[
+                ("../escape.csv", b"a,b\n1,2\n"),
+                ("__MACOSX/._notes.csv", b"resource fork"),
+                ("setup.exe", b"binary"),
+                ("nested.zip", b"PK"),
+                ("bomb.csv", &bomb),
+                ("docs/notes.csv", b"a,b\n1,2\n"),
+            ],
+        );
+
+        let unpacked = unpack(&zip_path, dest.to_str().unwrap()).unwrap();
+        assert_eq!(
+            unpacked.files,
+            [("docs/notes.csv".to_string(), "docs_notes.csv".to_string())]
+        );
+        let skipped: Vec<(&str, &str)> = unpacked
+            .skipped
+            .iter()
+            .map(|skipped| (skipped.entry.as_str(), skipped.reason.as_str()))
+            .collect();
+        assert_eq!(
+            skipped,
+            [
+                ("../escape.csv", "unsafe or hidden path"),
+                ("__MACOSX/._notes.csv", "unsafe or hidden path"),
+                ("setup.exe", "unsupported file type"),
+                ("nested.zip", "unsupported file type"),
+                ("bomb.csv", "suspicious compression ratio")
+            ]
+        );
+        // nothing was written outside of the directory nor for the skipped entries
+        assert!(!dir.path().join("escape.csv").exists());
+        let written: Vec<String> = fs::read_dir(&dest)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(written, ["docs_notes.csv"]);
+    }
+
+    #[test]
+    fn archives_past_the_size_limit_leave_no_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("uploads");
+        let zip_path = write_zip(
+            dir.path(),
+            &[
+                ("q1.csv", b"a,b\n1,2\n"),
+                ("q2.csv", b"a,b\n3,4\n"),
+                ("q3.csv", b"a,b\n5,6\n"),
+            ],
+        );
+
+        let error = unpack_within(&zip_path, dest.to_str().unwrap(), 20).unwrap_err();
+        assert!(error.to_string().contains("larger than"), "{}", error);
+        assert_eq!(fs::read_dir(&dest).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn invalid_archives_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let zip_path = dir.path().join("upload.zip");
+        fs::write(&zip_path, b"not a zip").unwrap();
+        let dest = dir.path().join("uploads");
+        let error = unpack(zip_path.to_str().unwrap(), dest.to_str().unwrap()).unwrap_err();
+        assert!(error.to_string().contains("not a valid zip"), "{}", error);
+    }
+}
@@ -0,0 +1,494 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use ignore::WalkBuilder;
+use serde_json::Map;
+use tree_sitter::{Node, Parser};
+
+use super::{
+    file_extension, Chunk, Extraction, CHUNK_TYPE_KEY, CODE_CHUNK, LANGUAGE_KEY, LINE_END_KEY,
+    LINE_START_KEY, PATH_KEY, SYMBOL_KEY,
+};
+
+// source files larger than this are most likely generated and skipped
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+// Language of the source files understood by the code ingestion
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Language {
+    Rust,
+    TypeScript,
+    Tsx,
+    Python,
+}
+
+impl Language {
+    pub fn from_path(path: &str) -> Option<Self> {
+        match file_extension(path).as_str() {
+            "rs" => Some(Language::Rust),
+            "ts" | "mts" | "cts" => Some(Language::TypeScript),
+            "tsx" => Some(Language::Tsx),
+            "py" => Some(Language::Python),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Language::Rust => "rust",
+            Language::TypeScript | Language::Tsx => "typescript",
+            Language::Python => "python",
+        }
+    }
+
+    fn grammar(&self) -> tree_sitter::Language {
+        match self {
+            Language::Rust => tree_sitter_rust::LANGUAGE.into(),
+            Language::TypeScript => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            Language::Tsx => tree_sitter_typescript::LANGUAGE_TSX.into(),
+            Language::Python => tree_sitter_python::LANGUAGE.into(),
+        }
+    }
+
+    // the syntax nodes chunked on their own
+    fn is_definition(&self, kind: &str) -> bool {
+        match self {
+            Language::Rust => matches!(
+                kind,
+                "function_item"
+                    | "impl_item"
+                    | "trait_item"
+                    | "struct_item"
+                    | "enum_item"
+                    | "union_item"
+                    | "mod_item"
+                    | "macro_definition"
+                    | "const_item"
+                    | "static_item"
+                    | "type_item"
+            ),
+            Language::TypeScript | Language::Tsx => matches!(
+                kind,
+                "function_declaration"
+                    | "generator_function_declaration"
+                    | "class_declaration"
+                    | "abstract_class_declaration"
+                    | "interface_declaration"
+                    | "enum_declaration"
+                    | "type_alias_declaration"
+                    | "module"
+                    | "internal_module"
+                    | "method_definition"
+                    | "export_statement"
+                    | "lexical_declaration"
+            ),
+            Language::Python => matches!(
+                kind,
+                "function_definition" | "class_definition" | "decorated_definition"
+            ),
+        }
+    }
+}
+
+// Span is a range of the source file making up a chunk
+struct Span {
+    start: usize,
+    end: usize,
+    symbol: Option<String>,
+}
+
+// extract walks the directory (respecting the .gitignore files)
+// and chunks every supported source file along its definitions
+pub fn extract(dir: &str, chunk_size: usize) -> Result<Extraction> {
+    let root = Path::new(dir);
+    if !root.is_dir() {
+        return Err(anyhow!("{} is not a directory", dir));
+    }
+    let mut extraction = Extraction::default();
+    let walker = WalkBuilder::new(root)
+        // honour the .gitignore even when the directory is not a git checkout
+        .require_git(false)
+        .build();
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                extraction.warnings.push(e.to_string());
+                continue;
+            }
+        };
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        let path = entry.path();
+        let relative = path
+            .strip_prefix(root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .replace('\\', "/");
+        let Some(language) = Language::from_path(&relative) else {
+            continue;
+        };
+        if entry.metadata().is_ok_and(|m| m.len() > MAX_FILE_SIZE) {
+            extraction
+                .warnings
+                .push(format!("{} skipped: larger than 1MB", relative));
+            continue;
+        }
+        match fs::read_to_string(path)
+            .with_context(|| format!("unable to read {}", relative))
+            .and_then(|source| chunk_source(&relative, &source, language, chunk_size))
+        {
+            Ok(chunks) => {
+                extraction.total_files += 1;
+                extraction.chunks.extend(chunks);
+            }
+            Err(e) => extraction
+                .warnings
+                .push(format!("{} skipped: {:#}", relative, e)),
+        }
+    }
+    if extraction.total_files == 0 {
+        return Err(anyhow!(
+            "no rust, typescript or python source files were found in {}",
+            dir
+        ));
+    }
+    Ok(extraction)
+}
+
+// chunk_source parses the file and splits it on the definition boundaries,
+// recording the path, the line range & the symbol of every chunk
+pub fn chunk_source(
+    path: &str,
+    source: &str,
+    language: Language,
+    chunk_size: usize,
+) -> Result<Vec<Chunk>> {
+    let mut parser = Parser::new();
+    parser
+        .set_language(&language.grammar())
+        .context("unable to load the grammar")?;
+    let tree = parser
+        .parse(source, None)
+        .ok_or(anyhow!("unable to parse the file"))?;
+
+    let mut spans = vec![];
+    collect_spans(
+        tree.root_node(),
+        source,
+        language,
+        chunk_size.max(1),
+        None,
+        &mut spans,
+    );
+    let chunks = spans
+        .into_iter()
+        .filter(|span| !source[span.start..span.end].trim().is_empty())
+        .map(|span| {
+            // keep the indentation of the first line
+            let line_offset = source[..span.start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+            let start = if source[line_offset..span.start].trim().is_empty() {
+                line_offset
+            } else {
+                span.start
+            };
+            let text = &source[start..span.end];
+            let line_start = line_at(source, start);
+            let line_end = line_start + text.trim_end().lines().count().max(1) - 1;
+            let mut metadata = Map::new();
+            metadata.insert(PATH_KEY.to_string(), path.into());
+            metadata.insert(LINE_START_KEY.to_string(), line_start.into());
+            metadata.insert(LINE_END_KEY.to_string(), line_end.into());
+            metadata.insert(LANGUAGE_KEY.to_string(), language.name().into());
+            if let Some(symbol) = span.symbol {
+                metadata.insert(SYMBOL_KEY.to_string(), symbol.into());
+            }
+            metadata.insert(CHUNK_TYPE_KEY.to_string(), CODE_CHUNK.into());
+            Chunk {
+                // the path gives the embedding the context the snippet alone lacks
+                text: format!("File: {}\n{}", path, text.trim_end()),
+                metadata,
+            }
+        })
+        .collect();
+    Ok(chunks)
+}
+
+// collect_spans keeps every definition as its own span, the code in between (imports,
+// statements..) is grouped up to the chunk size. The comments right above a definition
+// stay with it. Definitions too large for a chunk are split on their inner definitions
+// (methods of an impl or a class) and else on the line boundaries.
+fn collect_spans(
+    node: Node,
+    source: &str,
+    language: Language,
+    chunk_size: usize,
+    parent: Option<&str>,
+    spans: &mut Vec<Span>,
+) {
+    let mut cursor = node.walk();
+    let children: Vec<Node> = node.named_children(&mut cursor).collect();
+    // the pending code between the definitions and where its trailing comments start
+    let mut group: Option<(usize, usize)> = None;
+    let mut comments_start: Option<usize> = None;
+    for child in children {
+        // the doc comments & the attributes (`#[derive(..)]`) lead the next definition
+        if child.kind().contains("comment") || child.kind() == "attribute_item" {
+            comments_start.get_or_insert(child.start_byte());
+            group = Some(match group {
+                Some((start, _)) => (start, child.end_byte()),
+                None => (child.start_byte(), child.end_byte()),
+            });
+            continue;
+        }
+        if !language.is_definition(child.kind()) {
+            comments_start = None;
+            let start = group.map(|(start, _)| start).unwrap_or(child.start_byte());
+            if child.end_byte() - start > chunk_size {
+                if let Some((start, end)) = group {
+                    push_lines(source, start, end, chunk_size, parent, spans);
+                }
+                group = Some((child.start_byte(), child.end_byte()));
+            } else {
+                group = Some((start, child.end_byte()));
+            }
+            continue;
+        }
+
+        let start = comments_start.take().unwrap_or(child.start_byte());
+        if let Some((group_start, _)) = group.take() {
+            if group_start < start {
+                push_lines(source, group_start, start, chunk_size, parent, spans);
+            }
+        }
+        let symbol = symbol_name(child, source).map(|name| match parent {
+            Some(parent) => format!("{}::{}", parent, name),
+            None => name,
+        });
+        if child.end_byte() - start <= chunk_size {
+            spans.push(Span {
+                start,
+                end: child.end_byte(),
+                symbol,
+            });
+            continue;
+        }
+        match definition_body(child).filter(|body| has_definitions(*body, language)) {
+            Some(body) => {
+                // the signature (e.g. `impl Trait for Type {`) along with the leading comments
+                push_lines(
+                    source,
+                    start,
+                    body.start_byte(),
+                    chunk_size,
+                    symbol.as_deref(),
+                    spans,
+                );
+                // the members of an impl are scoped by the type (`Point::fmt`)
+                let scope = match child.kind() {
+                    "impl_item" => child
+                        .child_by_field_name("type")
+                        .and_then(|n| n.utf8_text(source.as_bytes()).ok())
+                        .map(|ty| match parent {
+                            Some(parent) => format!("{}::{}", parent, ty),
+                            None => ty.to_string(),
+                        }),
+                    _ => symbol,
+                };
+                let scope = scope.as_deref().or(parent);
+                collect_spans(body, source, language, chunk_size, scope, spans);
+            }
+            None => push_lines(
+                source,
+                start,
+                child.end_byte(),
+                chunk_size,
+                symbol.as_deref(),
+                spans,
+            ),
+        }
+    }
+    if let Some((start, end)) = group {
+        push_lines(source, start, end, chunk_size, parent, spans);
+    }
+}
+
+// push_lines splits the range into spans of whole lines up to the chunk size
+fn push_lines(
+    source: &str,
+    start: usize,
+    end: usize,
+    chunk_size: usize,
+    symbol: Option<&str>,
+    spans: &mut Vec<Span>,
+) {
+    let mut span_start = start;
+    let mut offset = start;
+    for line in source[start..end].split_inclusive('\n') {
+        if offset > span_start && offset + line.len() - span_start > chunk_size {
+            spans.push(Span {
+                start: span_start,
+                end: offset,
+                symbol: symbol.map(|s| s.to_string()),
+            });
+            span_start = offset;
+        }
+        offset += line.len();
+    }
+    if span_start < end {
+        spans.push(Span {
+            start: span_start,
+            end,
+            symbol: symbol.map(|s| s.to_string()),
+        });
+    }
+}
+
+// symbol_name names the definition, e.g. `Foo` or `Display for Foo` for an impl
+fn symbol_name(node: Node, source: &str) -> Option<String> {
+    let text = |field: &str| {
+        node.child_by_field_name(field)
+            .and_then(|n| n.utf8_text(source.as_bytes()).ok())
+            .map(|text| text.to_string())
+    };
+    match node.kind() {
+        "impl_item" => match (text("trait"), text("type")) {
+            (Some(t), Some(ty)) => Some(format!("{} for {}", t, ty)),
+            (None, ty) => ty,
+            (t, None) => t,
+        },
+        "export_statement" => node
+            .child_by_field_name("declaration")
+            .and_then(|declaration| symbol_name(declaration, source)),
+        "decorated_definition" => node
+            .child_by_field_name("definition")
+            .and_then(|definition| symbol_name(definition, source)),
+        "lexical_declaration" => {
+            let mut cursor = node.walk();
+            let declarator = node
+                .named_children(&mut cursor)
+                .find(|n| n.kind() == "variable_declarator");
+            declarator.and_then(|declarator| symbol_name(declarator, source))
+        }
+        _ => text("name"),
+    }
+}
+
+// the block holding the members of the definition
+fn definition_body(node: Node) -> Option<Node> {
+    match node.kind() {
+        "export_statement" => node
+            .child_by_field_name("declaration")
+            .and_then(definition_body),
+        "decorated_definition" => node
+            .child_by_field_name("definition")
+            .and_then(definition_body),
+        _ => node.child_by_field_name("body"),
+    }
+}
+
+fn has_definitions(node: Node, language: Language) -> bool {
+    let mut cursor = node.walk();
+    let found = node
+        .named_children(&mut cursor)
+        .any(|child| language.is_definition(child.kind()));
+    found
+}
+
+// 1 based line number of the byte offset
+fn line_at(source: &str, offset: usize) -> usize {
+    source[..offset].matches('\n').count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RUST_SOURCE: &str = "use std::fmt;
+
+// Point is a position on the grid
+#[derive(Debug)]
+pub struct Point {
+    x: i32,
+    y: i32,
+}
+
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, \"({}, {})\", self.x, self.y)
+    }
+}
+
+fn origin() -> Point {
+    Point { x: 0, y: 0 }
+}
+";
+
+    // symbol & line range of every chunk
+    fn spans(chunks: &[Chunk]) -> Vec<(Option<&str>, u64, u64)> {
+        chunks
+            .iter()
+            .map(|chunk| {
+                (
+                    chunk.metadata.get(SYMBOL_KEY).and_then(|s| s.as_str()),
+                    chunk.metadata[LINE_START_KEY].as_u64().unwrap(),
+                    chunk.metadata[LINE_END_KEY].as_u64().unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn definitions_are_chunked_along_with_their_comments() {
+        let chunks = chunk_source("src/point.rs", RUST_SOURCE, Language::Rust, 1000).unwrap();
+        assert_eq!(
+            spans(&chunks),
+            [
+                (None, 1, 1),
+                (Some("Point"), 3, 8),
+                (Some("fmt::Display for Point"), 10, 14),
+                (Some("origin"), 16, 18)
+            ]
+        );
+        assert!(chunks[1].text.starts_with(
+            "File: src/point.rs\n// Point is a position on the grid\n#[derive(Debug)]"
+        ));
+        assert!(chunks
+            .iter()
+            .all(|chunk| chunk.metadata[LANGUAGE_KEY] == "rust"
+                && chunk.metadata[PATH_KEY] == "src/point.rs"));
+    }
+
+    #[test]
+    fn large_definitions_are_split_on_their_members() {
+        let chunks = chunk_source("src/point.rs", RUST_SOURCE, Language::Rust, 60).unwrap();
+        let spans = spans(&chunks);
+        // the signature of the impl, then its method scoped by the type
+        assert!(
+            spans.contains(&(Some("fmt::Display for Point"), 10, 10)),
+            "{:?}",
+            spans
+        );
+        assert!(spans.contains(&(Some("Point::fmt"), 11, 11)), "{:?}", spans);
+    }
+
+    #[test]
+    fn python_classes_and_decorated_functions_are_symbols() {
+        let source = "import os
+
+class Store:
+    def get(self, key):
+        return os.environ.get(key)
+
+@cache
+def load():
+    return Store()
+";
+        let chunks = chunk_source("store.py", source, Language::Python, 1000).unwrap();
+        assert_eq!(
+            spans(&chunks),
+            [(None, 1, 1), (Some("Store"), 3, 5), (Some("load"), 7, 9)]
+        );
+    }
+}
@@ -0,0 +1,263 @@
+use std::fs::{self, File};
+
+use anyhow::{anyhow, Context, Result};
+use mail_parser::{mailbox::mbox::MessageIterator, Address, MessageParser, MimeHeaders};
+use serde_json::Map;
+
+use super::{
+    chunk_sections, file_extension, markup::html_to_text, Extraction, Section, DATE_KEY,
+    MESSAGE_ID_KEY, SENDER_KEY, SENT_AT_KEY, SUBJECT_KEY,
+};
+
+// Email is a decoded message of the archive
+pub struct Email {
+    pub message_id: Option<String>,
+    pub from: String,
+    // lower cased address of the sender
+    pub sender: Option<String>,
+    pub to: String,
+    pub date: Option<String>,
+    // unix timestamp (seconds) of the date header
+    pub sent_at: Option<i64>,
+    pub subject: String,
+    pub body: String,
+    // (file name, text) of the text attachments
+    pub attachments: Vec<(String, String)>,
+}
+
+impl Email {
+    // headers, body & attachments as a single text
+    pub fn to_text(&self) -> String {
+        let mut text = format!("From: {}\n", self.from);
+        if !self.to.is_empty() {
+            text.push_str(&format!("To: {}\n", self.to));
+        }
+        if let Some(date) = &self.date {
+            text.push_str(&format!("Date: {}\n", date));
+        }
+        text.push_str(&format!("Subject: {}\n\n{}\n", self.subject, self.body));
+        for (name, content) in self.attachments.iter() {
+            text.push_str(&format!("\nAttachment: {}\n{}\n", name, content));
+        }
+        text
+    }
+}
+
+// extract chunks every message on its own with its sender & date in the payload
+pub fn extract(file_path: &str, chunk_size: usize) -> Result<Extraction> {
+    let sections = read_messages(file_path)?
+        .into_iter()
+        .map(|email| {
+            let mut metadata = Map::new();
+            metadata.insert(SUBJECT_KEY.to_string(), email.subject.to_string().into());
+            if let Some(sender) = &email.sender {
+                metadata.insert(SENDER_KEY.to_string(), sender.to_string().into());
+            }
+            if let Some(date) = &email.date {
+                metadata.insert(DATE_KEY.to_string(), date.to_string().into());
+            }
+            if let Some(sent_at) = email.sent_at {
+                metadata.insert(SENT_AT_KEY.to_string(), sent_at.into());
+            }
+            if let Some(message_id) = &email.message_id {
+                metadata.insert(MESSAGE_ID_KEY.to_string(), message_id.to_string().into());
+            }
+            Section {
+                text: email.to_text(),
+                metadata,
+            }
+        })
+        .collect();
+    Ok(Extraction {
+        chunks: chunk_sections(sections, chunk_size),
+        ..Default::default()
+    })
+}
+
+// extract_text lists the messages one after the other for display
+pub fn extract_text(file_path: &str) -> Result<String> {
+    let content = read_messages(file_path)?
+        .iter()
+        .map(|email| email.to_text())
+        .collect::<Vec<String>>()
+        .join("\n----------\n\n");
+    Ok(content.trim().to_string())
+}
+
+// read_messages parses the single message of an .eml file or all the messages of an mbox
+pub fn read_messages(file_path: &str) -> Result<Vec<Email>> {
+    let parser = MessageParser::default();
+    let mut emails = vec![];
+    if file_extension(file_path) == "mbox" {
+        let file = File::open(file_path).context("Failed to read the file")?;
+        for (i, message) in MessageIterator::new(file).enumerate() {
+            let message = message.map_err(|_| anyhow!("invalid mbox message {}", i + 1))?;
+            emails.extend(parse_email(&parser, message.contents()));
+        }
+    } else {
+        let raw = fs::read(file_path).context("Failed to read the file")?;
+        emails.extend(parse_email(&parser, &raw));
+    }
+    if emails.is_empty() {
+        return Err(anyhow!("no email messages were found in the file"));
+    }
+    Ok(emails)
+}
+
+fn parse_email(parser: &MessageParser, raw: &[u8]) -> Option<Email> {
+    let message = parser.parse(raw)?;
+    // the plain text bodies, else the html one
+    let texts: Vec<&str> = message
+        .text_bodies()
+        .filter(|part| !part.is_text_html())
+        .filter_map(|part| part.text_contents())
+        .collect();
+    let body = if texts.is_empty() {
+        message
+            .body_html(0)
+            .map(|html| html_to_text(&html))
+            .unwrap_or_default()
+    } else {
+        texts.join("\n")
+    };
+    let attachments = message
+        .attachments()
+        .filter(|part| part.is_text() || part.content_type().is_some_and(|ct| ct.ctype() == "text"))
+        .filter_map(|part| {
+            let name = part.attachment_name().unwrap_or("untitled").to_string();
+            let text = part.text_contents()?;
+            let text = if part.is_text_html() {
+                html_to_text(text)
+            } else {
+                text.trim().to_string()
+            };
+            Some((name, text))
+        })
+        .filter(|(_, text)| !text.is_empty())
+        .collect();
+    let date = message.date();
+    Some(Email {
+        message_id: message.message_id().map(|id| id.to_string()),
+        from: message.from().map(format_address).unwrap_or_default(),
+        sender: message
+            .from()
+            .and_then(|from| from.first())
+            .and_then(|addr| addr.address())
+            .map(|address| address.to_lowercase()),
+        to: message.to().map(format_address).unwrap_or_default(),
+        date: date.map(|date| date.to_rfc3339()),
+        sent_at: date.map(|date| date.to_timestamp()),
+        subject: message.subject().unwrap_or("(no subject)").to_string(),
+        body: body.trim().to_string(),
+        attachments,
+    })
+}
+
+// `Name <address>` of every recipient
+fn format_address(address: &Address) -> String {
+    address
+        .iter()
+        .map(|addr| match (addr.name(), addr.address()) {
+            (Some(name), Some(address)) => format!("{} <{}>", name, address),
+            (Some(name), None) => name.to_string(),
+            (None, Some(address)) => address.to_string(),
+            (None, None) => String::new(),
+        })
+        .filter(|addr| !addr.is_empty())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: &str) -> Email {
+        parse_email(&MessageParser::default(), raw.as_bytes()).expect("the message is parsed")
+    }
+
+    #[test]
+    fn encoded_headers_and_bodies_are_decoded() {
+        let email = parse(
+            "From: =?ISO-8859-1?Q?Ren=E9_Dupr=E9?= <Rene@Example.COM>
+To: Alice <alice@example.com>, bob@example.com
+Subject: =?UTF-8?B?UsOpdW5pb24gZGUgbHVuZGk=?=
+Date: Mon, 4 Mar 2024 10:30:00 +0100
+Message-ID: <123@example.com>
+MIME-Version: 1.0
+Content-Type: text/plain; charset=iso-8859-1
+Content-Transfer-Encoding: quoted-printable
+
+Le caf=E9 est pr=EAt, =E0 tout =
+de suite.
+",
+        );
+        assert_eq!(email.from, "René Dupré <Rene@Example.COM>");
+        assert_eq!(email.sender.as_deref(), Some("rene@example.com"));
+        assert_eq!(email.to, "Alice <alice@example.com>, bob@example.com");
+        assert_eq!(email.subject, "Réunion de lundi");
+        assert_eq!(email.body, "Le café est prêt, à tout de suite.");
+        assert_eq!(email.message_id.as_deref(), Some("123@example.com"));
+        assert_eq!(email.sent_at, Some(1709544600));
+    }
+
+    #[test]
+    fn multipart_messages_keep_the_plain_text_and_the_text_attachments() {
+        let email = parse(
+            "From: alice@example.com
+Subject: Notes
+MIME-Version: 1.0
+Content-Type: multipart/mixed; boundary=\"outer\"
+
+--outer
+Content-Type: multipart/alternative; boundary=\"inner\"
+
+--inner
+Content-Type: text/plain; charset=utf-8
+
+See the notes attached.
+--inner
+Content-Type: text/html; charset=utf-8
+
+<p>See the <b>notes</b> attached.</p>
+--inner--
+--outer
+Content-Type: text/plain; charset=utf-8
+Content-Disposition: attachment; filename=\"notes.txt\"
+Content-Transfer-Encoding: base64
+
+TWVldGluZyBtb3ZlZCB0byBGcmlkYXku
+--outer
+Content-Type: image/png
+Content-Disposition: attachment; filename=\"logo.png\"
+Content-Transfer-Encoding: base64
+
+iVBORw0KGgo=
+--outer--
+",
+        );
+        assert_eq!(email.body, "See the notes attached.");
+        assert_eq!(
+            email.attachments,
+            [(
+                "notes.txt".to_string(),
+                "Meeting moved to Friday.".to_string()
+            )]
+        );
+        assert_eq!(email.subject, "Notes");
+        assert!(email.date.is_none());
+    }
+
+    #[test]
+    fn html_only_messages_are_read_as_text() {
+        let email = parse(
+            "From: alice@example.com
+Content-Type: text/html; charset=utf-8
+
+<html><body><p>Hello <b>Bob</b></p></body></html>
+",
+        );
+        assert_eq!(email.body, "Hello Bob");
+        assert_eq!(email.subject, "(no subject)");
+    }
+}
@@ -0,0 +1,333 @@
+use std::{collections::HashMap, fs::File, io::Read};
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::Map;
+use zip::ZipArchive;
+
+use super::{
+    archive::{MAX_COMPRESSION_RATIO, MAX_ENTRY_SIZE},
+    chunk_sections,
+    markup::html_to_text,
+    Extraction, Section, SECTION_KEY,
+};
+
+// the compression ratio of the smaller entries isn't checked (tiny xml parts compress a lot)
+const RATIO_CHECK_SIZE: u64 = 1024 * 1024;
+
+// Chapter is the text of a spine document along with its title from the table of contents
+pub struct Chapter {
+    pub title: String,
+    pub text: String,
+}
+
+// extract chunks every chapter on its own with the chapter title as the section
+pub fn extract(file_path: &str, chunk_size: usize) -> Result<Extraction> {
+    let sections = extract_chapters(file_path)?
+        .into_iter()
+        .map(|chapter| {
+            let mut metadata = Map::new();
+            metadata.insert(SECTION_KEY.to_string(), chapter.title.into());
+            Section {
+                text: chapter.text,
+                metadata,
+            }
+        })
+        .collect();
+    Ok(Extraction {
+        chunks: chunk_sections(sections, chunk_size),
+        ..Default::default()
+    })
+}
+
+// extract_text joins the chapters under their titles for display
+pub fn extract_text(file_path: &str) -> Result<String> {
+    let mut content = String::new();
+    let mut last_title = String::new();
+    for chapter in extract_chapters(file_path)? {
+        if chapter.title != last_title {
+            content.push_str(&format!("\n\n{}\n", chapter.title));
+        }
+        content.push_str(&format!("\n{}\n", chapter.text));
+        last_title = chapter.title;
+    }
+    Ok(content.trim().to_string())
+}
+
+// extract_chapters walks the epub spine in the reading order
+pub fn extract_chapters(file_path: &str) -> Result<Vec<Chapter>> {
+    let file = File::open(file_path).context("Failed to read the file")?;
+    let mut archive = ZipArchive::new(file).context("the epub is not a valid zip archive")?;
+
+    let container = read_entry(&mut archive, "META-INF/container.xml")?;
+    let container = roxmltree::Document::parse(&container).context("invalid container.xml")?;
+    let opf_path = container
+        .descendants()
+        .find(|node| node.has_tag_name("rootfile"))
+        .and_then(|node| node.attribute("full-path"))
+        .ok_or(anyhow!("the epub has no package document"))?
+        .to_string();
+    let opf_dir = parent_dir(&opf_path);
+
+    let opf = read_entry(&mut archive, &opf_path)?;
+    let opf = roxmltree::Document::parse(&opf).context("invalid package document")?;
+    // manifest id -> (path within the archive, properties)
+    let manifest: HashMap<&str, (String, &str)> = opf
+        .descendants()
+        .filter(|node| node.has_tag_name("item"))
+        .filter_map(|node| {
+            let href = node.attribute("href")?;
+            Some((
+                node.attribute("id")?,
+                (
+                    join_path(&opf_dir, href),
+                    node.attribute("properties").unwrap_or_default(),
+                ),
+            ))
+        })
+        .collect();
+    let spine = opf
+        .descendants()
+        .find(|node| node.has_tag_name("spine"))
+        .ok_or(anyhow!("the epub has no spine"))?;
+
+    // the epub3 navigation document or the epub2 ncx
+    let toc_path = manifest
+        .values()
+        .find(|(_, properties)| properties.split_whitespace().any(|p| p == "nav"))
+        .or_else(|| spine.attribute("toc").and_then(|id| manifest.get(id)))
+        .map(|(path, _)| path.to_string());
+    let titles = match toc_path {
+        Some(toc_path) => toc_titles(&mut archive, &toc_path).unwrap_or_default(),
+        None => HashMap::new(),
+    };
+
+    let mut chapters: Vec<Chapter> = vec![];
+    for itemref in spine.children().filter(|node| node.has_tag_name("itemref")) {
+        let Some((path, _)) = itemref.attribute("idref").and_then(|id| manifest.get(id)) else {
+            continue;
+        };
+        let html = read_entry(&mut archive, path)?;
+        let text = html_to_text(&html);
+        if text.is_empty() {
+            continue;
+        }
+        // untitled spine documents continue the previous chapter
+        let title = titles
+            .get(path.as_str())
+            .cloned()
+            .or_else(|| chapters.last().map(|chapter| chapter.title.to_string()))
+            .unwrap_or_else(|| text.lines().next().unwrap_or_default().to_string());
+        chapters.push(Chapter { title, text });
+    }
+    Ok(chapters)
+}
+
+// toc_titles maps the chapter documents to their titles from the nav or the ncx document
+fn toc_titles(archive: &mut ZipArchive<File>, toc_path: &str) -> Result<HashMap<String, String>> {
+    let toc_dir = parent_dir(toc_path);
+    let toc = read_entry(archive, toc_path)?;
+    let toc = roxmltree::Document::parse(&toc).context("invalid table of contents")?;
+    let mut titles = HashMap::new();
+    // ncx: <navPoint><navLabel><text/></navLabel><content src=""/></navPoint>
+    for nav_point in toc.descendants().filter(|n| n.has_tag_name("navPoint")) {
+        let title = nav_point
+            .descendants()
+            .find(|n| n.has_tag_name("text"))
+            .and_then(|n| n.text());
+        let src = nav_point
+            .children()
+            .find(|n| n.has_tag_name("content"))
+            .and_then(|n| n.attribute("src"));
+        if let (Some(title), Some(src)) = (title, src) {
+            titles
+                .entry(join_path(&toc_dir, src))
+                .or_insert(title.trim().to_string());
+        }
+    }
+    // nav: <nav epub:type="toc"><ol><li><a href="">title</a></li></ol></nav>
+    for anchor in toc.descendants().filter(|n| n.has_tag_name("a")) {
+        let Some(href) = anchor.attribute("href") else {
+            continue;
+        };
+        let title: String = anchor
+            .descendants()
+            .filter(|n| n.is_text())
+            .filter_map(|n| n.text())
+            .collect::<Vec<&str>>()
+            .join(" ");
+        if !title.trim().is_empty() {
+            titles
+                .entry(join_path(&toc_dir, href))
+                .or_insert(title.split_whitespace().collect::<Vec<&str>>().join(" "));
+        }
+    }
+    Ok(titles)
+}
+
+// read_entry reads the entry as text within the limits of the archive entries (zip bombs),
+// the declared size being checked first and the read stopping past the limit
+pub(super) fn read_entry(archive: &mut ZipArchive<File>, path: &str) -> Result<String> {
+    let entry = archive
+        .by_name(path)
+        .with_context(|| format!("{} is missing from the archive", path))?;
+    if entry.size() > MAX_ENTRY_SIZE {
+        return Err(anyhow!("{} is larger than 200MB", path));
+    }
+    if entry.size() > RATIO_CHECK_SIZE
+        && entry.size() > entry.compressed_size().max(1) * MAX_COMPRESSION_RATIO
+    {
+        return Err(anyhow!("{} has a suspicious compression ratio", path));
+    }
+    let mut content = String::new();
+    entry
+        .take(MAX_ENTRY_SIZE + 1)
+        .read_to_string(&mut content)
+        .with_context(|| format!("unable to read {}", path))?;
+    if content.len() as u64 > MAX_ENTRY_SIZE {
+        return Err(anyhow!("{} is larger than 200MB", path));
+    }
+    Ok(content)
+}
+
+pub(super) fn parent_dir(path: &str) -> String {
+    path.rsplit_once('/')
+        .map(|(dir, _)| format!("{}/", dir))
+        .unwrap_or_default()
+}
+
+// join_path resolves the (percent encoded) href relative to the directory, dropping the fragment
+pub(super) fn join_path(dir: &str, href: &str) -> String {
+    let href = percent_decode(href.split('#').next().unwrap_or_default());
+    let path = format!("{}{}", dir, href);
+    let mut parts: Vec<&str> = vec![];
+    for part in path.split('/') {
+        match part {
+            ".." => {
+                parts.pop();
+            }
+            "." | "" => {}
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+fn percent_decode(href: &str) -> String {
+    let bytes = href.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::zip_archive;
+
+    fn open_zip(dir: &tempfile::TempDir, entries: &[(&str, &[u8])]) -> ZipArchive<File> {
+        let path = dir.path().join("book.epub");
+        std::fs::write(&path, zip_archive(entries)).unwrap();
+        ZipArchive::new(File::open(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn entries_past_the_limits_are_not_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let bomb = vec![b'a'; 2 * RATIO_CHECK_SIZE as usize];
+        let mut archive = open_zip(&dir, &[("small.xml", b"<p>hello</p>"), ("bomb.xml", &bomb)]);
+        assert_eq!(
+            read_entry(&mut archive, "small.xml").unwrap(),
+            "<p>hello</p>"
+        );
+        let error = read_entry(&mut archive, "bomb.xml").unwrap_err();
+        assert!(error.to_string().contains("compression ratio"), "{}", error);
+        assert!(read_entry(&mut archive, "missing.xml").is_err());
+    }
+
+    #[test]
+    fn chapters_follow_the_spine_with_their_toc_titles() {
+        let dir = tempfile::tempdir().unwrap();
+        let container = br#"<?xml version="1.0"?>
+<container xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles><rootfile full-path="OEBPS/content.opf"/></rootfiles>
+</container>"#;
+        // the manifest lists the chapters out of the reading order
+        let opf = br#"<?xml version="1.0"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <manifest>
+    <item id="nav" href="nav.xhtml" properties="nav"/>
+    <item id="c2" href="text/chapter%202.xhtml"/>
+    <item id="c1" href="text/one.xhtml"/>
+    <item id="c1b" href="text/one-more.xhtml"/>
+  </manifest>
+  <spine><itemref idref="c1"/><itemref idref="c1b"/><itemref idref="c2"/></spine>
+</package>"#;
+        let nav = br#"<html xmlns="http://www.w3.org/1999/xhtml"><body><nav><ol>
+  <li><a href="text/one.xhtml#start">The   Beginning</a></li>
+  <li><a href="text/chapter%202.xhtml">The End</a></li>
+</ol></nav></body></html>"#;
+        let path = dir.path().join("book.epub");
+        std::fs::write(
+            &path,
+            zip_archive(&[
+                ("META-INF/container.xml", container),
+                ("OEBPS/content.opf", opf),
+                ("OEBPS/nav.xhtml", nav),
+                (
+                    "OEBPS/text/chapter 2.xhtml",
+                    b"<html><body><p>Second chapter.</p></body></html>",
+                ),
+                (
+                    "OEBPS/text/one.xhtml",
+                    b"<html><body><p>First chapter.</p></body></html>",
+                ),
+                (
+                    "OEBPS/text/one-more.xhtml",
+                    b"<html><body><p>Still the first.</p></body></html>",
+                ),
+            ]),
+        )
+        .unwrap();
+
+        let chapters = extract_chapters(path.to_str().unwrap()).unwrap();
+        let chapters: Vec<(&str, &str)> = chapters
+            .iter()
+            .map(|chapter| (chapter.title.as_str(), chapter.text.trim()))
+            .collect();
+        // the untitled spine document continues the previous chapter
+        assert_eq!(
+            chapters,
+            [
+                ("The Beginning", "First chapter."),
+                ("The Beginning", "Still the first."),
+                ("The End", "Second chapter.")
+            ]
+        );
+    }
+
+    #[test]
+    fn hrefs_are_resolved_against_their_directory() {
+        assert_eq!(parent_dir("OEBPS/content.opf"), "OEBPS/");
+        assert_eq!(parent_dir("content.opf"), "");
+        assert_eq!(
+            join_path("OEBPS/toc/", "../text/a%20b.xhtml#p1"),
+            "OEBPS/text/a b.xhtml"
+        );
+    }
+}
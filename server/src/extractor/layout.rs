@@ -0,0 +1,138 @@
+use pdf_extract::{MediaBox, OutputDev, OutputError, Transform};
+
+// gap (relative to the font size) between two glyphs to be treated as a space
+const SPACE_GAP: f64 = 0.1;
+// gap (relative to the font size) between two glyphs to be treated as a new cell
+const CELL_GAP: f64 = 1.2;
+// vertical shift (relative to the font size) to be treated as a new line
+const LINE_SHIFT: f64 = 0.5;
+
+// Cell is the run of text on a line separated by a wide gap from its neighbours
+#[derive(Clone, Debug)]
+pub struct Cell {
+    pub x: f64,
+    pub text: String,
+}
+
+// Line is the text laid out on the same baseline
+#[derive(Clone, Debug, Default)]
+pub struct Line {
+    pub cells: Vec<Cell>,
+}
+
+impl Line {
+    pub fn text(&self) -> String {
+        self.cells
+            .iter()
+            .map(|cell| cell.text.trim())
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
+}
+
+// PageLines is the page number along with the lines laid out on it
+#[derive(Clone, Debug, Default)]
+pub struct PageLines {
+    pub number: u32,
+    pub lines: Vec<Line>,
+}
+
+// LayoutOutput collects the positioned text of every page as lines of cells
+#[derive(Default)]
+pub struct LayoutOutput {
+    pub pages: Vec<PageLines>,
+    page_height: f64,
+    last_end: f64,
+    last_y: f64,
+}
+
+impl LayoutOutput {
+    fn current_line(&mut self) -> Option<&mut Line> {
+        self.pages.last_mut().and_then(|page| page.lines.last_mut())
+    }
+}
+
+impl OutputDev for LayoutOutput {
+    fn begin_page(
+        &mut self,
+        page_num: u32,
+        media_box: &MediaBox,
+        _: Option<(f64, f64, f64, f64)>,
+    ) -> Result<(), OutputError> {
+        self.page_height = media_box.ury - media_box.lly;
+        self.pages.push(PageLines {
+            number: page_num,
+            lines: vec![],
+        });
+        Ok(())
+    }
+
+    fn end_page(&mut self) -> Result<(), OutputError> {
+        if let Some(page) = self.pages.last_mut() {
+            for line in page.lines.iter_mut() {
+                line.cells.retain(|cell| !cell.text.trim().is_empty());
+            }
+            page.lines.retain(|line| !line.cells.is_empty());
+        }
+        Ok(())
+    }
+
+    fn output_character(
+        &mut self,
+        trm: &Transform,
+        width: f64,
+        _spacing: f64,
+        font_size: f64,
+        char: &str,
+    ) -> Result<(), OutputError> {
+        // flip the y axis so that the lines are read from the top of the page
+        let (x, y) = (trm.m31, self.page_height - trm.m32);
+        let size_x = font_size * (trm.m11 + trm.m21);
+        let size_y = font_size * (trm.m12 + trm.m22);
+        let size = (size_x * size_y).abs().sqrt().max(1.0);
+        let (last_end, last_y) = (self.last_end, self.last_y);
+        self.last_end = x + width * size;
+        self.last_y = y;
+
+        let new_line = (y - last_y).abs() > size * LINE_SHIFT || x < last_end - size;
+        if new_line || self.current_line().is_none() {
+            if let Some(page) = self.pages.last_mut() {
+                page.lines.push(Line {
+                    cells: vec![Cell {
+                        x,
+                        text: char.to_string(),
+                    }],
+                });
+            }
+            return Ok(());
+        }
+        if let Some(line) = self.current_line() {
+            let gap = x - last_end;
+            match line.cells.last_mut() {
+                Some(cell) if gap <= size * CELL_GAP => {
+                    if gap > size * SPACE_GAP && !cell.text.ends_with(' ') {
+                        cell.text.push(' ');
+                    }
+                    cell.text.push_str(char);
+                }
+                _ => line.cells.push(Cell {
+                    x,
+                    text: char.to_string(),
+                }),
+            }
+        }
+        Ok(())
+    }
+
+    fn begin_word(&mut self) -> Result<(), OutputError> {
+        Ok(())
+    }
+
+    fn end_word(&mut self) -> Result<(), OutputError> {
+        Ok(())
+    }
+
+    fn end_line(&mut self) -> Result<(), OutputError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+// tags whose content is not part of the text
+const SKIPPED_TAGS: [&str; 3] = ["script", "style", "head"];
+
+// html_to_text strips the (x)html markup keeping the block elements on their own lines
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut skipping: Option<String> = None;
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        if skipping.is_none() {
+            push_text(&mut text, &rest[..start]);
+        }
+        let Some(end) = rest[start..].find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = &rest[start + 1..start + end];
+        rest = &rest[start + end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        match &skipping {
+            Some(skipped) if closing && *skipped == name => skipping = None,
+            Some(_) => {}
+            None if !closing && SKIPPED_TAGS.contains(&name.as_str()) && !tag.ends_with('/') => {
+                skipping = Some(name)
+            }
+            None if is_block_tag(&name) && !text.ends_with('\n') => text.push('\n'),
+            None => {}
+        }
+    }
+    if skipping.is_none() {
+        push_text(&mut text, rest);
+    }
+    text.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+// block tags start a new line of text
+fn is_block_tag(name: &str) -> bool {
+    matches!(
+        name,
+        "p" | "div"
+            | "br"
+            | "h1"
+            | "h2"
+            | "h3"
+            | "h4"
+            | "h5"
+            | "h6"
+            | "li"
+            | "tr"
+            | "td"
+            | "th"
+            | "section"
+            | "article"
+            | "blockquote"
+            | "pre"
+            | "title"
+            | "dt"
+            | "dd"
+            | "hr"
+            | "table"
+    )
+}
+
+// push the text collapsing the whitespaces
+fn push_text(text: &mut String, raw: &str) {
+    let decoded = decode_entities(raw);
+    let words: Vec<&str> = decoded.split_whitespace().collect();
+    if words.is_empty() {
+        if !decoded.is_empty() && !text.is_empty() && !text.ends_with(['\n', ' ']) {
+            text.push(' ');
+        }
+        return;
+    }
+    if decoded.starts_with(char::is_whitespace) && !text.is_empty() && !text.ends_with(['\n', ' '])
+    {
+        text.push(' ');
+    }
+    text.push_str(&words.join(" "));
+    if decoded.ends_with(char::is_whitespace) {
+        text.push(' ');
+    }
+}
+
+// decode_entities decodes the named entities commonly found in the documents
+// along with the numeric ones
+pub fn decode_entities(raw: &str) -> String {
+    let mut decoded = String::with_capacity(raw.len());
+    let mut rest = raw;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest
+            .find(';')
+            .filter(|end| *end <= 10)
+            .map(|end| &rest[1..end]);
+        let value = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            "ndash" => Some('–'),
+            "mdash" => Some('—'),
+            "hellip" => Some('…'),
+            "lsquo" => Some('‘'),
+            "rsquo" => Some('’'),
+            "ldquo" => Some('“'),
+            "rdquo" => Some('”'),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        });
+        match (entity, value) {
+            (Some(entity), Some(value)) => {
+                decoded.push(value);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
@@ -0,0 +1,358 @@
+mod archive;
+mod code;
+mod email;
+mod epub;
+mod layout;
+mod markup;
+#[cfg(feature = "ocr")]
+mod ocr;
+mod office;
+mod pdf;
+mod sheet;
+mod table;
+
+use std::{collections::BTreeMap, fmt};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+pub use archive::{SkippedEntry, Unpacked};
+
+// payload keys describing the location of the chunk within the document
+pub const TEXT_KEY: &str = "text";
+pub const PAGE_START_KEY: &str = "page_start";
+pub const PAGE_END_KEY: &str = "page_end";
+pub const CHAR_START_KEY: &str = "char_start";
+pub const CHAR_END_KEY: &str = "char_end";
+pub const CHUNK_TYPE_KEY: &str = "chunk_type";
+pub const CAPTION_KEY: &str = "caption";
+pub const OCR_CONFIDENCE_KEY: &str = "ocr_confidence";
+pub const SECTION_KEY: &str = "section";
+pub const SECTION_INDEX_KEY: &str = "section_index";
+pub const SHEET_KEY: &str = "sheet";
+pub const ROW_START_KEY: &str = "row_start";
+pub const ROW_END_KEY: &str = "row_end";
+pub const PATH_KEY: &str = "path";
+pub const LINE_START_KEY: &str = "line_start";
+pub const LINE_END_KEY: &str = "line_end";
+pub const LANGUAGE_KEY: &str = "language";
+pub const SYMBOL_KEY: &str = "symbol";
+pub const SUBJECT_KEY: &str = "subject";
+pub const SENDER_KEY: &str = "sender";
+pub const DATE_KEY: &str = "date";
+pub const SENT_AT_KEY: &str = "sent_at";
+pub const MESSAGE_ID_KEY: &str = "message_id";
+pub const SLIDE_KEY: &str = "slide";
+// position of the chunk within the document
+pub const CHUNK_INDEX_KEY: &str = "chunk_index";
+// the small child chunks embedded in place of their parent chunk keep its index & its text
+pub const PARENT_INDEX_KEY: &str = "parent_index";
+pub const PARENT_TEXT_KEY: &str = "parent_text";
+
+// file types accepted by the ingestion
+pub const SUPPORTED_EXTENSIONS: [&str; 11] = [
+    "pdf", "epub", "csv", "xlsx", "xls", "ods", "eml", "mbox", "pptx", "odp", "odt",
+];
+
+// uploaded archives whose supported entries are ingested
+pub const ARCHIVE_EXTENSION: &str = "zip";
+
+// chunk types stored in the payload
+pub const TEXT_CHUNK: &str = "text";
+pub const TABLE_CHUNK: &str = "table";
+pub const CODE_CHUNK: &str = "code";
+
+// Chunk is the unit of text sent for embeddings
+// along with the location metadata saved into its payload
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct Chunk {
+    pub text: String,
+    pub metadata: Map<String, Value>,
+}
+
+impl From<String> for Chunk {
+    fn from(text: String) -> Self {
+        Self {
+            text,
+            metadata: Map::new(),
+        }
+    }
+}
+
+impl Chunk {
+    // citation label for the chunk to be referred by the LLM
+    pub fn citation(&self) -> Option<String> {
+        let mut labels = vec![];
+        if let Some(Value::String(chunk_type)) = self.metadata.get(CHUNK_TYPE_KEY) {
+            if chunk_type == TABLE_CHUNK {
+                labels.push("table".to_string());
+            }
+        }
+        if let Some(section) = self.metadata.get(SECTION_KEY).and_then(|v| v.as_str()) {
+            labels.push(format!("section \"{}\"", section));
+        }
+        if let Some(subject) = self.metadata.get(SUBJECT_KEY).and_then(|v| v.as_str()) {
+            let mut label = format!("email \"{}\"", subject);
+            if let Some(sender) = self.metadata.get(SENDER_KEY).and_then(|v| v.as_str()) {
+                label.push_str(&format!(" from {}", sender));
+            }
+            if let Some(date) = self.metadata.get(DATE_KEY).and_then(|v| v.as_str()) {
+                label.push_str(&format!(" on {}", date));
+            }
+            labels.push(label);
+        }
+        if let Some(path) = self.metadata.get(PATH_KEY).and_then(|v| v.as_str()) {
+            match (
+                self.metadata.get(LINE_START_KEY).and_then(|v| v.as_u64()),
+                self.metadata.get(LINE_END_KEY).and_then(|v| v.as_u64()),
+            ) {
+                (Some(line_start), Some(line_end)) => {
+                    labels.push(format!("{}:{}-{}", path, line_start, line_end))
+                }
+                _ => labels.push(path.to_string()),
+            }
+        }
+        if let Some(symbol) = self.metadata.get(SYMBOL_KEY).and_then(|v| v.as_str()) {
+            labels.push(format!("`{}`", symbol));
+        }
+        if let Some(slide) = self.metadata.get(SLIDE_KEY).and_then(|v| v.as_u64()) {
+            labels.push(format!("slide {}", slide));
+        }
+        if let Some(sheet) = self.metadata.get(SHEET_KEY).and_then(|v| v.as_str()) {
+            labels.push(format!("sheet \"{}\"", sheet));
+        }
+        if let Some(row_start) = self.metadata.get(ROW_START_KEY).and_then(|v| v.as_u64()) {
+            let row_end = self
+                .metadata
+                .get(ROW_END_KEY)
+                .and_then(|v| v.as_u64())
+                .unwrap_or(row_start);
+            if row_start == row_end {
+                labels.push(format!("row {}", row_start));
+            } else {
+                labels.push(format!("rows {}-{}", row_start, row_end));
+            }
+        }
+        if let Some(page_start) = self.metadata.get(PAGE_START_KEY).and_then(|v| v.as_u64()) {
+            let page_end = self
+                .metadata
+                .get(PAGE_END_KEY)
+                .and_then(|v| v.as_u64())
+                .unwrap_or(page_start);
+            if page_start == page_end {
+                labels.push(format!("page {}", page_start));
+            } else {
+                labels.push(format!("pages {}-{}", page_start, page_end));
+            }
+        }
+        if labels.is_empty() {
+            None
+        } else {
+            Some(labels.join(", "))
+        }
+    }
+}
+
+// Section is a self contained part of the document (a chapter, a slide, a message..)
+// chunked on its own with its metadata copied to each of its chunks
+#[derive(Clone, Debug, Default)]
+pub struct Section {
+    pub text: String,
+    pub metadata: Map<String, Value>,
+}
+
+// PasswordError signals the client to prompt the user for the document password
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PasswordError {
+    Required,
+    Incorrect,
+}
+
+impl PasswordError {
+    // error code returned to the client
+    pub fn code(&self) -> &'static str {
+        match self {
+            PasswordError::Required => "password_required",
+            PasswordError::Incorrect => "incorrect_password",
+        }
+    }
+}
+
+impl fmt::Display for PasswordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PasswordError::Required => {
+                write!(f, "the document is encrypted, a password is required")
+            }
+            PasswordError::Incorrect => write!(f, "the password of the document is incorrect"),
+        }
+    }
+}
+
+impl std::error::Error for PasswordError {}
+
+// Extraction is the outcome of reading a document
+#[derive(Default)]
+pub struct Extraction {
+    pub chunks: Vec<Chunk>,
+    pub total_pages: usize,
+    // pages without any extractable text
+    pub empty_pages: Vec<u32>,
+    // OCR confidence (0-100) of the pages read via OCR
+    pub ocr_pages: BTreeMap<u32, f32>,
+    // source files read from the ingested directory
+    pub total_files: usize,
+    // files skipped while reading the directory
+    pub warnings: Vec<String>,
+}
+
+impl Extraction {
+    // a scanned document has no text layer on any of its pages
+    pub fn is_scanned(&self) -> bool {
+        self.total_pages > 0 && self.empty_pages.len() == self.total_pages
+    }
+}
+
+// extract_file_content extracts the whole text of the uploaded file for display
+pub fn extract_file_content(file_name: &str, password: Option<&str>) -> Result<String> {
+    let file_name = resolve_upload(file_name);
+    let file_path = format!("./uploads/{}", file_name);
+    match file_extension(file_name.as_str()).as_str() {
+        "pdf" => pdf::extract_text(file_path.as_str(), password),
+        "epub" => epub::extract_text(file_path.as_str()),
+        "csv" | "xlsx" | "xls" | "ods" => sheet::extract_text(file_path.as_str()),
+        "eml" | "mbox" => email::extract_text(file_path.as_str()),
+        "pptx" | "odp" | "odt" => office::extract_text(file_path.as_str()),
+        ext => Err(anyhow!("unsupported file type: {}", ext)),
+    }
+}
+
+// extract_chunks reads the uploaded file and splits its content into chunks
+// keeping the track of where every chunk comes from.
+// The password is only used to decrypt the document and never stored.
+pub fn extract_chunks(
+    file_name: &str,
+    chunk_size: usize,
+    password: Option<&str>,
+) -> Result<Extraction> {
+    let file_path = format!("./uploads/{}", file_name);
+    match file_extension(file_name).as_str() {
+        "pdf" => pdf::extract(file_path.as_str(), chunk_size, password),
+        "epub" => epub::extract(file_path.as_str(), chunk_size),
+        "csv" | "xlsx" | "xls" | "ods" => sheet::extract(file_path.as_str(), chunk_size),
+        "eml" | "mbox" => email::extract(file_path.as_str(), chunk_size),
+        "pptx" | "odp" | "odt" => office::extract(file_path.as_str(), chunk_size),
+        ext => Err(anyhow!("unsupported file type: {}", ext)),
+    }
+}
+
+// extract_directory reads the source code files of the local directory
+// and splits them on their function, impl & class boundaries
+pub fn extract_directory(dir: &str, chunk_size: usize) -> Result<Extraction> {
+    code::extract(dir, chunk_size)
+}
+
+// unpack_archive extracts the supported entries of the uploaded zip archive
+pub fn unpack_archive(zip_path: &str, dest_dir: &str) -> Result<Unpacked> {
+    archive::unpack(zip_path, dest_dir)
+}
+
+// is_supported checks the file extension against the supported file types
+pub fn is_supported(file_name: &str) -> bool {
+    SUPPORTED_EXTENSIONS.contains(&file_extension(file_name).as_str())
+}
+
+// resolve_upload finds the uploaded file of the document
+// when it is referred with another extension (e.g. `book.pdf` for `book.epub`)
+pub fn resolve_upload(file_name: &str) -> String {
+    if std::path::Path::new("./uploads").join(file_name).exists() {
+        return file_name.to_string();
+    }
+    SUPPORTED_EXTENSIONS
+        .iter()
+        .map(|ext| format!("{}.{}", doc_name(file_name), ext))
+        .find(|candidate| std::path::Path::new("./uploads").join(candidate).exists())
+        .unwrap_or(file_name.to_string())
+}
+
+// doc_name is the name of the document (and its collection) without the extension
+pub fn doc_name(file_name: &str) -> &str {
+    std::path::Path::new(file_name)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(file_name)
+}
+
+// extract the lower cased extension of the file
+pub fn file_extension(file_name: &str) -> String {
+    std::path::Path::new(file_name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .unwrap_or_default()
+}
+
+// chunk_pages splits the text of all pages into fixed size chunks
+// and records the page range and character offsets covered by each chunk
+pub fn chunk_pages(pages: &[String], chunk_size: usize) -> Vec<Chunk> {
+    // start offset of every page within the whole document text
+    let mut page_offsets = vec![];
+    let mut content: Vec<char> = vec![];
+    for page in pages.iter() {
+        page_offsets.push(content.len());
+        content.extend(page.chars());
+        content.push('\n');
+    }
+    let page_at = |offset: usize| page_offsets.partition_point(|start| *start <= offset);
+
+    content
+        .chunks(chunk_size.max(1))
+        .enumerate()
+        .filter(|(_, chunk)| chunk.iter().any(|c| !c.is_whitespace()))
+        .map(|(i, chunk)| {
+            let char_start = i * chunk_size.max(1);
+            let char_end = char_start + chunk.len();
+            let mut metadata = Map::new();
+            metadata.insert(PAGE_START_KEY.to_string(), page_at(char_start).into());
+            metadata.insert(PAGE_END_KEY.to_string(), page_at(char_end - 1).into());
+            metadata.insert(CHAR_START_KEY.to_string(), char_start.into());
+            metadata.insert(CHAR_END_KEY.to_string(), char_end.into());
+            metadata.insert(CHUNK_TYPE_KEY.to_string(), TEXT_CHUNK.into());
+            Chunk {
+                text: chunk.iter().collect(),
+                metadata,
+            }
+        })
+        .collect()
+}
+
+// chunk_sections splits every section into fixed size chunks on its own
+// and records the character offsets of each chunk within its section
+pub fn chunk_sections(sections: Vec<Section>, chunk_size: usize) -> Vec<Chunk> {
+    let chunk_size = chunk_size.max(1);
+    sections
+        .into_iter()
+        .enumerate()
+        .flat_map(|(index, section)| {
+            let content: Vec<char> = section.text.chars().collect();
+            content
+                .chunks(chunk_size)
+                .enumerate()
+                .filter(|(_, chunk)| chunk.iter().any(|c| !c.is_whitespace()))
+                .map(|(i, chunk)| {
+                    let char_start = i * chunk_size;
+                    let mut metadata = section.metadata.clone();
+                    metadata.insert(SECTION_INDEX_KEY.to_string(), index.into());
+                    metadata.insert(CHAR_START_KEY.to_string(), char_start.into());
+                    metadata.insert(CHAR_END_KEY.to_string(), (char_start + chunk.len()).into());
+                    metadata.entry(CHUNK_TYPE_KEY).or_insert(TEXT_CHUNK.into());
+                    Chunk {
+                        text: chunk.iter().collect(),
+                        metadata,
+                    }
+                })
+                .collect::<Vec<Chunk>>()
+        })
+        .collect()
+}
@@ -0,0 +1,83 @@
+use std::{collections::BTreeMap, fs, process::Command};
+
+use anyhow::{anyhow, Context, Result};
+use uuid::Uuid;
+
+// resolution used to rasterize the scanned pages
+const OCR_DPI: &str = "300";
+const OCR_LANGUAGE: &str = "eng";
+
+// OcrPage is the text recognized on a page along with the mean word confidence (0-100)
+pub struct OcrPage {
+    pub text: String,
+    pub confidence: f32,
+}
+
+// ocr_page rasterizes the pdf page (poppler's pdftoppm) and runs the local tesseract on it.
+// The encrypted pdf is given decrypted: the password never goes on the command line.
+pub fn ocr_page(file_path: &str, page: u32) -> Result<OcrPage> {
+    let prefix = std::env::temp_dir().join(format!("documind-{}", Uuid::new_v4()));
+    let image = prefix.with_extension("png");
+    let page = page.to_string();
+    let status = Command::new("pdftoppm")
+        .args(["-f", page.as_str(), "-l", page.as_str(), "-r", OCR_DPI])
+        .args(["-png", "-singlefile", file_path])
+        .arg(&prefix)
+        .status()
+        .context("pdftoppm is required to rasterize the scanned pages")?;
+    if !status.success() {
+        return Err(anyhow!("pdftoppm failed to rasterize page {}", page));
+    }
+
+    let output = Command::new("tesseract")
+        .arg(&image)
+        .args(["stdout", "-l", OCR_LANGUAGE, "tsv"])
+        .output()
+        .context("tesseract is required to read the scanned pages");
+    let _ = fs::remove_file(&image);
+    let output = output?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "tesseract failed on page {}: {}",
+            page,
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    Ok(parse_tsv(String::from_utf8_lossy(&output.stdout).as_ref()))
+}
+
+// parse_tsv rebuilds the lines from the tesseract word boxes
+// and averages the confidence of the recognized words
+fn parse_tsv(tsv: &str) -> OcrPage {
+    let mut lines: BTreeMap<(u32, u32, u32), Vec<String>> = BTreeMap::new();
+    let mut confidences = vec![];
+    for row in tsv.lines().skip(1) {
+        let cols: Vec<&str> = row.split('\t').collect();
+        if cols.len() < 12 || cols[11].trim().is_empty() {
+            continue;
+        }
+        let Ok(confidence) = cols[10].parse::<f32>() else {
+            continue;
+        };
+        if confidence < 0.0 {
+            continue;
+        }
+        let key = |i: usize| cols[i].parse::<u32>().unwrap_or_default();
+        lines
+            .entry((key(2), key(3), key(4)))
+            .or_default()
+            .push(cols[11].trim().to_string());
+        confidences.push(confidence);
+    }
+    let text = lines
+        .into_values()
+        .map(|words| words.join(" "))
+        .collect::<Vec<String>>()
+        .join("\n");
+    let confidence = if confidences.is_empty() {
+        0.0
+    } else {
+        confidences.iter().sum::<f32>() / confidences.len() as f32
+    };
+    OcrPage { text, confidence }
+}
@@ -0,0 +1,489 @@
+use std::{collections::HashMap, fs::File};
+
+use anyhow::{anyhow, Context, Result};
+use roxmltree::Node;
+use serde_json::Map;
+use zip::ZipArchive;
+
+use super::{
+    chunk_sections,
+    epub::{join_path, parent_dir, read_entry},
+    file_extension, Extraction, Section, SECTION_KEY, SLIDE_KEY,
+};
+
+// separator of the nested headings of the section, e.g. `Design > Storage`
+const HEADING_SEPARATOR: &str = " > ";
+
+// Part is a slide of the deck or a heading section of the text document
+pub struct Part {
+    // slide number
+    pub slide: Option<usize>,
+    // slide title or the heading path
+    pub title: Option<String>,
+    pub text: String,
+}
+
+// extract chunks every slide (pptx, odp) or heading section (odt) on its own
+pub fn extract(file_path: &str, chunk_size: usize) -> Result<Extraction> {
+    let sections = read_parts(file_path)?
+        .into_iter()
+        .map(|part| {
+            let mut metadata = Map::new();
+            if let Some(slide) = part.slide {
+                metadata.insert(SLIDE_KEY.to_string(), slide.into());
+            }
+            if let Some(title) = part.title {
+                metadata.insert(SECTION_KEY.to_string(), title.into());
+            }
+            Section {
+                text: part.text,
+                metadata,
+            }
+        })
+        .collect();
+    Ok(Extraction {
+        chunks: chunk_sections(sections, chunk_size),
+        ..Default::default()
+    })
+}
+
+// extract_text joins the slides (or the sections) under their numbers & titles for display
+pub fn extract_text(file_path: &str) -> Result<String> {
+    let mut content = String::new();
+    for part in read_parts(file_path)? {
+        if let Some(slide) = part.slide {
+            content.push_str(&format!("\n\nSlide {}\n", slide));
+        }
+        content.push_str(&format!("\n{}\n", part.text));
+    }
+    Ok(content.trim().to_string())
+}
+
+pub fn read_parts(file_path: &str) -> Result<Vec<Part>> {
+    let file = File::open(file_path).context("Failed to read the file")?;
+    let mut archive = ZipArchive::new(file).context("the document is not a valid zip archive")?;
+    let parts = match file_extension(file_path).as_str() {
+        "pptx" => pptx_slides(&mut archive)?,
+        "odp" => {
+            let content = read_entry(&mut archive, "content.xml")?;
+            odp_slides(&content)?
+        }
+        "odt" => {
+            let content = read_entry(&mut archive, "content.xml")?;
+            odt_sections(&content)?
+        }
+        ext => return Err(anyhow!("unsupported file type: {}", ext)),
+    };
+    let parts: Vec<Part> = parts
+        .into_iter()
+        .filter(|part| !part.text.trim().is_empty())
+        .collect();
+    if parts.is_empty() {
+        return Err(anyhow!("no text was found in the document"));
+    }
+    Ok(parts)
+}
+
+// pptx_slides reads the slides in the order of the presentation along with their speaker notes
+fn pptx_slides(archive: &mut ZipArchive<File>) -> Result<Vec<Part>> {
+    let presentation = read_entry(archive, "ppt/presentation.xml")?;
+    let presentation =
+        roxmltree::Document::parse(&presentation).context("invalid presentation.xml")?;
+    let targets = relationships(archive, "ppt/presentation.xml")?;
+    let slide_paths: Vec<String> = presentation
+        .descendants()
+        .filter(|node| node.has_tag_name("sldId"))
+        // the relationship id (`r:id`) rather than the numeric slide id
+        .filter_map(|node| {
+            node.attributes()
+                .find(|attr| attr.name() == "id" && attr.namespace().is_some())
+                .map(|attr| attr.value())
+        })
+        .filter_map(|id| targets.get(id).cloned())
+        .collect();
+
+    let mut slides = vec![];
+    for (i, slide_path) in slide_paths.iter().enumerate() {
+        let slide = read_entry(archive, slide_path)?;
+        let slide = roxmltree::Document::parse(&slide)
+            .with_context(|| format!("invalid slide {}", slide_path))?;
+        let mut title = None;
+        let mut lines = vec![];
+        for shape in slide.descendants().filter(|node| node.has_tag_name("sp")) {
+            let is_title = shape
+                .descendants()
+                .find(|node| node.has_tag_name("ph"))
+                .and_then(|ph| attribute(ph, "type"))
+                .is_some_and(|kind| kind == "title" || kind == "ctrTitle");
+            let text = drawing_text(shape);
+            if is_title && title.is_none() && !text.is_empty() {
+                title = Some(text.replace('\n', " "));
+            }
+            lines.extend(text.lines().map(|line| line.to_string()));
+        }
+        // the table rows as tab separated cells
+        for row in slide.descendants().filter(|node| node.has_tag_name("tr")) {
+            let cells: Vec<String> = row
+                .children()
+                .filter(|cell| cell.has_tag_name("tc"))
+                .map(|cell| drawing_text(cell).replace('\n', " "))
+                .collect();
+            if cells.iter().any(|cell| !cell.is_empty()) {
+                lines.push(cells.join("\t"));
+            }
+        }
+        // the speaker notes of the slide
+        let notes_path = relationships(archive, slide_path)?
+            .into_values()
+            .find(|target| target.contains("notesSlide"));
+        if let Some(notes_path) = notes_path {
+            let notes = read_entry(archive, &notes_path)?;
+            let notes = roxmltree::Document::parse(&notes).context("invalid notes slide")?;
+            let text: Vec<String> = notes
+                .descendants()
+                .filter(|node| node.has_tag_name("sp"))
+                // skip the slide image & the slide number placeholders
+                .filter(|shape| {
+                    shape
+                        .descendants()
+                        .find(|node| node.has_tag_name("ph"))
+                        .and_then(|ph| attribute(ph, "type"))
+                        .is_some_and(|kind| kind == "body")
+                })
+                .map(drawing_text)
+                .filter(|text| !text.is_empty())
+                .collect();
+            if !text.is_empty() {
+                lines.push(format!("Notes: {}", text.join("\n")));
+            }
+        }
+        slides.push(Part {
+            slide: Some(i + 1),
+            title,
+            text: lines.join("\n"),
+        });
+    }
+    Ok(slides)
+}
+
+// relationships maps the relationship ids of the part to the paths of their targets
+fn relationships(archive: &mut ZipArchive<File>, part: &str) -> Result<HashMap<String, String>> {
+    let dir = parent_dir(part);
+    let file_name = part.rsplit('/').next().unwrap_or(part);
+    let rels_path = format!("{}_rels/{}.rels", dir, file_name);
+    if archive.by_name(&rels_path).is_err() {
+        return Ok(HashMap::new());
+    }
+    let rels = read_entry(archive, &rels_path)?;
+    let rels = roxmltree::Document::parse(&rels).context("invalid relationships")?;
+    Ok(rels
+        .descendants()
+        .filter(|node| node.has_tag_name("Relationship"))
+        .filter_map(|node| {
+            Some((
+                node.attribute("Id")?.to_string(),
+                join_path(&dir, node.attribute("Target")?),
+            ))
+        })
+        .collect())
+}
+
+// the paragraphs (`a:p`) of the drawing shape, one per line
+fn drawing_text(shape: Node) -> String {
+    shape
+        .descendants()
+        .filter(|node| node.has_tag_name("p"))
+        .map(|paragraph| {
+            paragraph
+                .descendants()
+                .filter(|node| node.has_tag_name("t"))
+                .filter_map(|node| node.text())
+                .collect::<String>()
+        })
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// odp_slides reads the pages of the presentation along with their notes
+fn odp_slides(content: &str) -> Result<Vec<Part>> {
+    let content = roxmltree::Document::parse(content).context("invalid content.xml")?;
+    let slides = content
+        .descendants()
+        .filter(|node| node.has_tag_name("page"))
+        .enumerate()
+        .map(|(i, page)| {
+            let mut title = None;
+            let mut lines = vec![];
+            let mut notes = vec![];
+            for frame in page.descendants().filter(|node| node.has_tag_name("frame")) {
+                let in_notes = frame.ancestors().any(|node| node.has_tag_name("notes"));
+                let text = odf_lines(frame).join("\n");
+                if text.is_empty() {
+                    continue;
+                }
+                if in_notes {
+                    notes.push(text);
+                    continue;
+                }
+                if attribute(frame, "class") == Some("title") && title.is_none() {
+                    title = Some(text.replace('\n', " "));
+                }
+                lines.push(text);
+            }
+            if !notes.is_empty() {
+                lines.push(format!("Notes: {}", notes.join("\n")));
+            }
+            Part {
+                slide: Some(i + 1),
+                title,
+                text: lines.join("\n"),
+            }
+        })
+        .collect();
+    Ok(slides)
+}
+
+// odt_sections splits the text document on its headings keeping the heading path
+fn odt_sections(content: &str) -> Result<Vec<Part>> {
+    let content = roxmltree::Document::parse(content).context("invalid content.xml")?;
+    let body = content
+        .descendants()
+        .find(|node| {
+            node.has_tag_name("text") && node.parent().is_some_and(|p| p.has_tag_name("body"))
+        })
+        .ok_or(anyhow!("the document has no text body"))?;
+
+    let mut sections = vec![];
+    // the headings (level, title) enclosing the current section
+    let mut headings: Vec<(usize, String)> = vec![];
+    let mut lines: Vec<String> = vec![];
+    let title = |headings: &[(usize, String)]| {
+        (!headings.is_empty()).then(|| {
+            headings
+                .iter()
+                .map(|(_, title)| title.as_str())
+                .collect::<Vec<&str>>()
+                .join(HEADING_SEPARATOR)
+        })
+    };
+    for node in body.children().filter(|node| node.is_element()) {
+        if node.has_tag_name("h") {
+            let heading = odf_text(node);
+            if heading.is_empty() {
+                continue;
+            }
+            sections.push(Part {
+                slide: None,
+                title: title(&headings),
+                text: lines.join("\n"),
+            });
+            lines = vec![];
+            let level = attribute(node, "outline-level")
+                .and_then(|level| level.parse().ok())
+                .unwrap_or(1);
+            headings.retain(|(l, _)| *l < level);
+            headings.push((level, heading.to_string()));
+            lines.push(heading);
+        } else {
+            lines.extend(odf_lines(node));
+        }
+    }
+    sections.push(Part {
+        slide: None,
+        title: title(&headings),
+        text: lines.join("\n"),
+    });
+    Ok(sections)
+}
+
+// odf_lines reads the paragraphs (`text:p`, `text:h`) under the node one per line
+// and the table rows as tab separated cells
+fn odf_lines(node: Node) -> Vec<String> {
+    if node.has_tag_name("p") || node.has_tag_name("h") {
+        let text = odf_text(node);
+        return if text.is_empty() { vec![] } else { vec![text] };
+    }
+    if node.has_tag_name("table-row") {
+        let cells: Vec<String> = node
+            .children()
+            .filter(|cell| cell.has_tag_name("table-cell"))
+            .map(|cell| odf_lines(cell).join(" "))
+            .collect();
+        return if cells.iter().all(|cell| cell.is_empty()) {
+            vec![]
+        } else {
+            vec![cells.join("\t")]
+        };
+    }
+    node.children()
+        .filter(|child| child.is_element())
+        .flat_map(odf_lines)
+        .collect()
+}
+
+// odf_text reads the text of the paragraph expanding the spaces, tabs & line breaks
+fn odf_text(node: Node) -> String {
+    let mut text = String::new();
+    push_odf_text(&mut text, node);
+    text.trim().to_string()
+}
+
+fn push_odf_text(text: &mut String, node: Node) {
+    for child in node.children() {
+        if child.is_text() {
+            text.push_str(child.text().unwrap_or_default());
+        } else if child.has_tag_name("s") {
+            let count = attribute(child, "c")
+                .and_then(|c| c.parse().ok())
+                .unwrap_or(1);
+            text.push_str(&" ".repeat(count));
+        } else if child.has_tag_name("tab") {
+            text.push('\t');
+        } else if child.has_tag_name("line-break") {
+            text.push('\n');
+        } else if !child.has_tag_name("note") && !child.has_tag_name("annotation") {
+            push_odf_text(text, child);
+        }
+    }
+}
+
+// the attribute by its local name whatever its namespace (`r:id`, `text:outline-level`..)
+fn attribute<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.attributes()
+        .find(|attr| attr.name() == name)
+        .map(|attr| attr.value())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::zip_archive;
+
+    const P: &str = r#"xmlns:p="http://schemas.openxmlformats.org/presentationml/2006/main" xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships""#;
+
+    // slide with a title and a body shape
+    fn slide(title: &str, body: &[&str]) -> String {
+        let paragraphs: String = body
+            .iter()
+            .map(|line| format!("<a:p><a:r><a:t>{}</a:t></a:r></a:p>", line))
+            .collect();
+        format!(
+            r#"<p:sld {P}><p:cSld><p:spTree>
+<p:sp><p:nvSpPr><p:nvPr><p:ph type="title"/></p:nvPr></p:nvSpPr><p:txBody><a:p><a:r><a:t>{title}</a:t></a:r></a:p></p:txBody></p:sp>
+<p:sp><p:nvSpPr><p:nvPr><p:ph idx="1"/></p:nvPr></p:nvSpPr><p:txBody>{paragraphs}</p:txBody></p:sp>
+</p:spTree></p:cSld></p:sld>"#
+        )
+    }
+
+    fn write(dir: &tempfile::TempDir, file_name: &str, entries: &[(&str, &[u8])]) -> String {
+        let path = dir.path().join(file_name);
+        std::fs::write(&path, zip_archive(entries)).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn pptx_slides_follow_the_presentation_with_their_notes() {
+        let dir = tempfile::tempdir().unwrap();
+        // the second slide file is shown first
+        let presentation = format!(
+            r#"<p:presentation {P}><p:sldIdLst><p:sldId id="256" r:id="rId3"/><p:sldId id="257" r:id="rId2"/></p:sldIdLst></p:presentation>"#
+        );
+        let presentation_rels = r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+<Relationship Id="rId2" Target="slides/slide1.xml"/>
+<Relationship Id="rId3" Target="slides/slide2.xml"/>
+</Relationships>"#;
+        let slide_rels = r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+<Relationship Id="rId1" Target="../notesSlides/notesSlide1.xml"/>
+</Relationships>"#;
+        let notes = format!(
+            r#"<p:notes {P}><p:cSld><p:spTree>
+<p:sp><p:nvSpPr><p:nvPr><p:ph type="sldImg"/></p:nvPr></p:nvSpPr></p:sp>
+<p:sp><p:nvSpPr><p:nvPr><p:ph type="body" idx="1"/></p:nvPr></p:nvSpPr><p:txBody><a:p><a:r><a:t>Mention the </a:t></a:r><a:r><a:t>budget.</a:t></a:r></a:p></p:txBody></p:sp>
+<p:sp><p:nvSpPr><p:nvPr><p:ph type="sldNum"/></p:nvPr></p:nvSpPr><p:txBody><a:p><a:r><a:t>1</a:t></a:r></a:p></p:txBody></p:sp>
+</p:spTree></p:cSld></p:notes>"#
+        );
+        let slide1 = slide("Results", &["Revenue grew 10%", "Costs were flat"]);
+        let slide2 = slide("Agenda", &["Results"]);
+        let path = write(
+            &dir,
+            "deck.pptx",
+            &[
+                ("ppt/presentation.xml", presentation.as_bytes()),
+                (
+                    "ppt/_rels/presentation.xml.rels",
+                    presentation_rels.as_bytes(),
+                ),
+                ("ppt/slides/slide1.xml", slide1.as_bytes()),
+                ("ppt/slides/slide2.xml", slide2.as_bytes()),
+                ("ppt/slides/_rels/slide1.xml.rels", slide_rels.as_bytes()),
+                ("ppt/notesSlides/notesSlide1.xml", notes.as_bytes()),
+            ],
+        );
+
+        let parts = read_parts(&path).unwrap();
+        let parts: Vec<(Option<usize>, Option<&str>, &str)> = parts
+            .iter()
+            .map(|part| (part.slide, part.title.as_deref(), part.text.as_str()))
+            .collect();
+        assert_eq!(
+            parts,
+            [
+                (Some(1), Some("Agenda"), "Agenda\nResults"),
+                (
+                    Some(2),
+                    Some("Results"),
+                    "Results\nRevenue grew 10%\nCosts were flat\nNotes: Mention the budget."
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn odt_sections_keep_their_heading_path() {
+        let content = r#"<office:document-content xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0">
+<office:body><office:text>
+<text:p>Draft, do not share.</text:p>
+<text:h text:outline-level="1">Design</text:h>
+<text:p>The service has<text:s text:c="2"/>two parts.<text:note><text:note-body><text:p>left out</text:p></text:note-body></text:note></text:p>
+<text:h text:outline-level="2">Storage</text:h>
+<text:p>Rows are kept<text:line-break/>in sqlite.</text:p>
+<text:h text:outline-level="1">Rollout</text:h>
+<text:list><text:list-item><text:p>Beta in May</text:p></text:list-item></text:list>
+</office:text></office:body></office:document-content>"#;
+        let sections = odt_sections(content).unwrap();
+        let sections: Vec<(Option<&str>, &str)> = sections
+            .iter()
+            .map(|part| (part.title.as_deref(), part.text.as_str()))
+            .collect();
+        assert_eq!(
+            sections,
+            [
+                (None, "Draft, do not share."),
+                (Some("Design"), "Design\nThe service has  two parts."),
+                (
+                    Some("Design > Storage"),
+                    "Storage\nRows are kept\nin sqlite."
+                ),
+                (Some("Rollout"), "Rollout\nBeta in May")
+            ]
+        );
+    }
+
+    #[test]
+    fn odp_notes_are_kept_apart_from_the_slide() {
+        let content = r#"<office:document-content xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:draw="urn:oasis:names:tc:opendocument:xmlns:drawing:1.0" xmlns:presentation="urn:oasis:names:tc:opendocument:xmlns:presentation:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0">
+<office:body><office:presentation>
+<draw:page>
+<draw:frame presentation:class="title"><draw:text-box><text:p>Roadmap</text:p></draw:text-box></draw:frame>
+<draw:frame presentation:class="outline"><draw:text-box><text:p>Ship v2</text:p></draw:text-box></draw:frame>
+<presentation:notes><draw:frame><draw:text-box><text:p>Keep it short</text:p></draw:text-box></draw:frame></presentation:notes>
+</draw:page>
+</office:presentation></office:body></office:document-content>"#;
+        let slides = odp_slides(content).unwrap();
+        assert_eq!(slides.len(), 1);
+        assert_eq!(slides[0].title.as_deref(), Some("Roadmap"));
+        assert_eq!(slides[0].text, "Roadmap\nShip v2\nNotes: Keep it short");
+    }
+}
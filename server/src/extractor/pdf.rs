@@ -0,0 +1,470 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{Context, Result};
+use pdf_extract::{encryption::DecryptionError, Document, PlainTextOutput};
+use serde_json::{Map, Value};
+use tracing::debug;
+
+#[cfg(feature = "ocr")]
+use super::ocr;
+use super::{
+    chunk_pages,
+    layout::LayoutOutput,
+    table::{detect_tables, Table},
+    Chunk, Extraction, PasswordError, CAPTION_KEY, CHUNK_TYPE_KEY, OCR_CONFIDENCE_KEY,
+    PAGE_END_KEY, PAGE_START_KEY, TABLE_CHUNK,
+};
+
+// number of lines at the top and the bottom of a page checked for headers & footers
+const EDGE_LINES: usize = 2;
+
+// PdfPage is the running text of the page along with the tables found on it
+pub struct PdfPage {
+    pub number: u32,
+    pub text: String,
+    pub tables: Vec<Table>,
+}
+
+impl PdfPage {
+    // a page without a text layer is most likely a scanned image
+    pub fn is_empty(&self) -> bool {
+        self.text.trim().is_empty() && self.tables.is_empty()
+    }
+}
+
+// extract reads the pdf page by page into the text & the table chunks,
+// the pages without a text layer are read via OCR when enabled
+pub fn extract(file_path: &str, chunk_size: usize, password: Option<&str>) -> Result<Extraction> {
+    let mut pages = extract_pages(file_path, password)?;
+    let empty_pages: Vec<u32> = pages
+        .iter()
+        .filter(|page| page.is_empty())
+        .map(|page| page.number)
+        .collect();
+    let ocr_pages = ocr_empty_pages(file_path, password, &mut pages);
+    let texts: Vec<String> = pages.iter().map(|page| page.text.to_string()).collect();
+    let mut chunks = chunk_pages(&texts, chunk_size);
+    attach_ocr_confidence(&mut chunks, &ocr_pages);
+    chunks.extend(table_chunks(&pages, chunk_size));
+    sort_by_page(&mut chunks);
+    Ok(Extraction {
+        chunks,
+        total_pages: pages.len(),
+        empty_pages,
+        ocr_pages,
+        ..Default::default()
+    })
+}
+
+// ocr_empty_pages reads the pages without a text layer via the local tesseract
+#[cfg(feature = "ocr")]
+fn ocr_empty_pages(
+    file_path: &str,
+    password: Option<&str>,
+    pages: &mut [PdfPage],
+) -> BTreeMap<u32, f32> {
+    let mut confidences = BTreeMap::new();
+    if !pages.iter().any(|page| page.is_empty()) {
+        return confidences;
+    }
+    // the encrypted pdf is rasterized from a decrypted copy readable by the server only
+    let decrypted = match password {
+        Some(_) => match decrypted_copy(file_path, password) {
+            std::result::Result::Ok(path) => Some(path),
+            Err(e) => {
+                tracing::warn!("OCR skipped, unable to decrypt the file: {:#}", e);
+                return confidences;
+            }
+        },
+        None => None,
+    };
+    let ocr_path = decrypted
+        .as_ref()
+        .and_then(|path| path.to_str())
+        .unwrap_or(file_path);
+    for page in pages.iter_mut().filter(|page| page.is_empty()) {
+        match ocr::ocr_page(ocr_path, page.number) {
+            std::result::Result::Ok(ocr_page) => {
+                page.text = ocr_page.text;
+                confidences.insert(page.number, ocr_page.confidence);
+            }
+            Err(e) => tracing::warn!("OCR failed for page {}: {}", page.number, e),
+        }
+    }
+    if let Some(path) = decrypted {
+        let _ = std::fs::remove_file(path);
+    }
+    confidences
+}
+
+// decrypted_copy saves the decrypted pdf into a temp file only the server user can read
+#[cfg(feature = "ocr")]
+fn decrypted_copy(file_path: &str, password: Option<&str>) -> Result<std::path::PathBuf> {
+    let mut doc = load_document(file_path, password)?;
+    let path = std::env::temp_dir().join(format!("documind-{}.pdf", uuid::Uuid::new_v4()));
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options
+        .open(&path)
+        .context("unable to create the decrypted copy")?;
+    if let Err(e) = doc.save_to(&mut file) {
+        let _ = std::fs::remove_file(&path);
+        return Err(anyhow::Error::new(e).context("unable to save the decrypted copy"));
+    }
+    Ok(path)
+}
+
+// ocr_empty_pages is a no-op unless the server is built with the `ocr` feature
+#[cfg(not(feature = "ocr"))]
+fn ocr_empty_pages(
+    _file_path: &str,
+    _password: Option<&str>,
+    _pages: &mut [PdfPage],
+) -> BTreeMap<u32, f32> {
+    BTreeMap::new()
+}
+
+// attach_ocr_confidence records the OCR confidence of the pages covered by the chunk
+fn attach_ocr_confidence(chunks: &mut [Chunk], ocr_pages: &BTreeMap<u32, f32>) {
+    if ocr_pages.is_empty() {
+        return;
+    }
+    for chunk in chunks.iter_mut() {
+        let page_of = |key: &str| chunk.metadata.get(key).and_then(|v| v.as_u64());
+        let (Some(start), Some(end)) = (page_of(PAGE_START_KEY), page_of(PAGE_END_KEY)) else {
+            continue;
+        };
+        let confidences: Map<String, Value> = ocr_pages
+            .range(start as u32..=end as u32)
+            .map(|(page, confidence)| (page.to_string(), Value::from(*confidence)))
+            .collect();
+        if !confidences.is_empty() {
+            chunk
+                .metadata
+                .insert(OCR_CONFIDENCE_KEY.to_string(), Value::Object(confidences));
+        }
+    }
+}
+
+// table_chunks renders the tables found on the pages as markdown chunks
+fn table_chunks(pages: &[PdfPage], chunk_size: usize) -> Vec<Chunk> {
+    pages
+        .iter()
+        .flat_map(|page| {
+            page.tables.iter().flat_map(move |table| {
+                table.to_markdown(chunk_size).into_iter().map(move |text| {
+                    let mut metadata = Map::new();
+                    metadata.insert(PAGE_START_KEY.to_string(), page.number.into());
+                    metadata.insert(PAGE_END_KEY.to_string(), page.number.into());
+                    metadata.insert(CHUNK_TYPE_KEY.to_string(), TABLE_CHUNK.into());
+                    if let Some(caption) = &table.caption {
+                        metadata.insert(CAPTION_KEY.to_string(), caption.as_str().into());
+                    }
+                    Chunk { text, metadata }
+                })
+            })
+        })
+        .collect()
+}
+
+// sort_by_page places the table chunks of every page after the text chunks starting on the page,
+// the chunks being indexed in the order of the document.
+// The sort is stable: the text chunks & the tables of a page keep their order
+fn sort_by_page(chunks: &mut [Chunk]) {
+    chunks.sort_by_key(|chunk| chunk.metadata.get(PAGE_START_KEY).and_then(Value::as_u64));
+}
+
+// extract_pages extracts the pdf text page by page
+// with the tables pulled out of the running text and
+// the repeated headers, footers & page numbers removed
+pub fn extract_pages(file_path: &str, password: Option<&str>) -> Result<Vec<PdfPage>> {
+    let doc = load_document(file_path, password)?;
+    let mut output = LayoutOutput::default();
+    pdf_extract::output_doc(&doc, &mut output)
+        .inspect_err(|e| debug!("failed to read the file: {}", e))
+        .context("Failed to read the file")?;
+
+    let mut numbers = vec![];
+    let mut texts = vec![];
+    let mut tables = vec![];
+    for page in output.pages.into_iter() {
+        let (page_tables, consumed) = detect_tables(&page.lines);
+        let text = page
+            .lines
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !consumed.contains(i))
+            .map(|(_, line)| line.text())
+            .collect::<Vec<String>>()
+            .join("\n");
+        numbers.push(page.number);
+        texts.push(text);
+        tables.push(page_tables);
+    }
+    let pages = numbers
+        .into_iter()
+        .zip(clean_pages(texts))
+        .zip(tables)
+        .map(|((number, text), tables)| PdfPage {
+            number,
+            text,
+            tables,
+        })
+        .collect();
+    Ok(pages)
+}
+
+// extract_text extracts the whole pdf text as laid out by pdf_extract
+pub fn extract_text(file_path: &str, password: Option<&str>) -> Result<String> {
+    let doc = load_document(file_path, password)?;
+    let mut content = String::new();
+    pdf_extract::output_doc(&doc, &mut PlainTextOutput::new(&mut content))
+        .inspect_err(|e| debug!("failed to read the file: {}", e))
+        .context("Failed to read the file")?;
+    Ok(content)
+}
+
+// load_document loads the pdf, decrypting it with the password if it is encrypted.
+// Documents only protected by an owner password open with an empty password.
+fn load_document(file_path: &str, password: Option<&str>) -> Result<Document> {
+    let mut doc = Document::load(file_path)
+        .inspect_err(|e| debug!("failed to read the file: {}", e))
+        .context("Failed to read the file")?;
+    if !doc.is_encrypted() {
+        return Ok(doc);
+    }
+    match doc.decrypt(password.unwrap_or_default()) {
+        std::result::Result::Ok(()) => Ok(doc),
+        Err(pdf_extract::Error::Decryption(DecryptionError::IncorrectPassword)) => match password {
+            Some(_) => Err(PasswordError::Incorrect.into()),
+            None => Err(PasswordError::Required.into()),
+        },
+        Err(e) => Err(anyhow::Error::new(e).context("Failed to decrypt the file")),
+    }
+}
+
+// clean_pages strips the page furniture and joins the line broken words
+pub fn clean_pages(pages: Vec<String>) -> Vec<String> {
+    let repeated = repeated_edge_lines(&pages);
+    pages
+        .iter()
+        .map(|page| {
+            let lines: Vec<&str> = page.lines().filter(|l| !l.trim().is_empty()).collect();
+            let total = lines.len();
+            let kept: Vec<&str> = lines
+                .into_iter()
+                .enumerate()
+                .filter(|(i, line)| {
+                    let at_edge = *i < EDGE_LINES || *i + EDGE_LINES >= total;
+                    !(at_edge && (is_page_number(line) || repeated.contains_key(&normalize(line))))
+                })
+                .map(|(_, line)| line)
+                .collect();
+            dehyphenate(&kept)
+        })
+        .collect()
+}
+
+// normalize the line so that the running headers with page numbers compare equal
+fn normalize(line: &str) -> String {
+    line.trim()
+        .chars()
+        .map(|c| if c.is_ascii_digit() { '#' } else { c })
+        .collect::<String>()
+        .to_lowercase()
+}
+
+// repeated_edge_lines finds the lines repeating at the top or the bottom
+// of at least half of the pages
+fn repeated_edge_lines(pages: &[String]) -> HashMap<String, usize> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    if pages.len() < 3 {
+        return counts;
+    }
+    for page in pages.iter() {
+        let lines: Vec<&str> = page.lines().filter(|l| !l.trim().is_empty()).collect();
+        let mut edges: Vec<String> = lines
+            .iter()
+            .take(EDGE_LINES)
+            .chain(lines.iter().rev().take(EDGE_LINES))
+            .map(|line| normalize(line))
+            .collect();
+        edges.sort();
+        edges.dedup();
+        for edge in edges {
+            *counts.entry(edge).or_default() += 1;
+        }
+    }
+    let threshold = (pages.len() / 2).max(2);
+    counts.retain(|_, count| *count >= threshold);
+    counts
+}
+
+// is_page_number matches the lines like `12`, `- 12 -`, `Page 12` or `12 of 40`
+fn is_page_number(line: &str) -> bool {
+    let line = line.trim().to_lowercase();
+    if line.is_empty() || line.len() > 20 || !line.chars().any(|c| c.is_ascii_digit()) {
+        return false;
+    }
+    line.replace("page", "")
+        .replace("of", "")
+        .chars()
+        .all(|c| c.is_ascii_digit() || c.is_whitespace() || c == '-' || c == '/')
+}
+
+// dehyphenate joins the words broken across the lines with a hyphen
+fn dehyphenate(lines: &[&str]) -> String {
+    let mut text = String::new();
+    for line in lines.iter() {
+        let line = line.trim_end();
+        let continues_word = text.ends_with('-')
+            && text
+                .chars()
+                .rev()
+                .nth(1)
+                .map(|c| c.is_alphabetic())
+                .unwrap_or(false)
+            && line
+                .trim_start()
+                .chars()
+                .next()
+                .map(|c| c.is_lowercase())
+                .unwrap_or(false);
+        if continues_word {
+            text.pop();
+            text.push_str(line.trim_start());
+        } else {
+            if !text.is_empty() {
+                text.push('\n');
+            }
+            text.push_str(line);
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(lines: &[&str]) -> String {
+        lines.join("\n")
+    }
+
+    #[test]
+    fn running_headers_footers_and_page_numbers_are_stripped() {
+        let pages = vec![
+            page(&[
+                "ACME Annual Report 2024",
+                "Revenue grew in every region.",
+                "Page 1 of 3",
+            ]),
+            page(&[
+                "ACME Annual Report 2024",
+                "Costs were kept flat.",
+                "Page 2 of 3",
+            ]),
+            page(&["ACME Annual Report 2024", "The outlook is stable.", "- 3 -"]),
+        ];
+        let cleaned = clean_pages(pages);
+        assert_eq!(
+            cleaned,
+            [
+                "Revenue grew in every region.",
+                "Costs were kept flat.",
+                "The outlook is stable."
+            ]
+        );
+    }
+
+    #[test]
+    fn lines_of_few_pages_are_kept() {
+        // a heading repeated on 2 pages out of 2 is no page furniture
+        let pages = vec![
+            page(&["Summary", "first page"]),
+            page(&["Summary", "second page"]),
+        ];
+        assert_eq!(
+            clean_pages(pages),
+            ["Summary\nfirst page", "Summary\nsecond page"]
+        );
+    }
+
+    #[test]
+    fn words_broken_across_lines_are_joined() {
+        let text = dehyphenate(&[
+            "the agree-",
+            "ment is valid for a long-",
+            "Term partner",
+            "see 2024-",
+            "2025",
+        ]);
+        assert_eq!(
+            text,
+            "the agreement is valid for a long-\nTerm partner\nsee 2024-\n2025"
+        );
+    }
+
+    #[test]
+    fn page_numbers_are_recognized() {
+        for line in ["12", "- 12 -", "Page 12", "12 of 40", "page 3/10"] {
+            assert!(is_page_number(line), "{}", line);
+        }
+        for line in ["", "Chapter 12", "2024 revenue", "Page"] {
+            assert!(!is_page_number(line), "{}", line);
+        }
+    }
+
+    #[test]
+    fn chunks_record_their_page_range() {
+        let pages = vec!["a".repeat(8), "b".repeat(8)];
+        let chunks = chunk_pages(&pages, 6);
+        let page_range = |chunk: &Chunk| {
+            (
+                chunk.metadata[PAGE_START_KEY].as_u64().unwrap(),
+                chunk.metadata[PAGE_END_KEY].as_u64().unwrap(),
+            )
+        };
+        let ranges: Vec<(u64, u64)> = chunks.iter().map(page_range).collect();
+        assert_eq!(ranges, [(1, 1), (1, 2), (2, 2)]);
+        assert_eq!(chunks[1].text, "aa\nbbb");
+    }
+
+    #[test]
+    fn tables_are_merged_in_the_page_order() {
+        let pages: Vec<PdfPage> = (1..=3)
+            .map(|number| PdfPage {
+                number,
+                text: format!("text of page {}", number),
+                tables: match number {
+                    1 | 2 => vec![Table {
+                        caption: None,
+                        rows: vec![vec![format!("table of page {}", number)]],
+                    }],
+                    _ => vec![],
+                },
+            })
+            .collect();
+        let texts: Vec<String> = pages.iter().map(|page| page.text.to_string()).collect();
+        let mut chunks = chunk_pages(&texts, 20);
+        chunks.extend(table_chunks(&pages, 100));
+        sort_by_page(&mut chunks);
+        // (page, table) of every chunk in the order of the document
+        let order: Vec<(u64, bool)> = chunks
+            .iter()
+            .map(|chunk| {
+                (
+                    chunk.metadata[PAGE_START_KEY].as_u64().unwrap(),
+                    chunk.metadata[CHUNK_TYPE_KEY] == TABLE_CHUNK,
+                )
+            })
+            .collect();
+        assert!(order.windows(2).all(|w| w[0].0 <= w[1].0), "{:?}", order);
+        let table_at = |page: u64| order.iter().position(|c| *c == (page, true)).unwrap();
+        assert!(order[..table_at(1)].iter().all(|(page, _)| *page == 1));
+        assert_eq!(order[table_at(1) + 1], (2, false));
+        assert_eq!(order[table_at(2) + 1], (3, false));
+    }
+}
@@ -0,0 +1,245 @@
+use anyhow::{anyhow, Context, Result};
+use calamine::{open_workbook_auto, Data, Reader};
+use serde_json::Map;
+
+use super::{
+    doc_name, file_extension, table::markdown_row, Chunk, Extraction, CHUNK_TYPE_KEY, ROW_END_KEY,
+    ROW_START_KEY, SHEET_KEY, TABLE_CHUNK,
+};
+
+// Sheet is a table read from a csv file or a workbook sheet.
+// The first row is the header and every row keeps its 1 based row number within the sheet
+pub struct Sheet {
+    pub name: String,
+    pub header: Vec<String>,
+    pub rows: Vec<(usize, Vec<String>)>,
+}
+
+// extract chunks the rows of every sheet in groups repeating the header row in each chunk
+pub fn extract(file_path: &str, chunk_size: usize) -> Result<Extraction> {
+    let chunks = read_sheets(file_path)?
+        .iter()
+        .flat_map(|sheet| chunk_rows(sheet, chunk_size))
+        .collect();
+    Ok(Extraction {
+        chunks,
+        ..Default::default()
+    })
+}
+
+// extract_text renders every sheet as a markdown table for display
+pub fn extract_text(file_path: &str) -> Result<String> {
+    let content = read_sheets(file_path)?
+        .iter()
+        .map(|sheet| {
+            let mut table = head(sheet);
+            for (_, row) in sheet.rows.iter() {
+                table.push_str(&markdown_row(row));
+            }
+            table
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    Ok(content.trim().to_string())
+}
+
+// read_sheets reads the csv file or all the sheets of the workbook (xlsx, xls, ods)
+pub fn read_sheets(file_path: &str) -> Result<Vec<Sheet>> {
+    let sheets = if file_extension(file_path) == "csv" {
+        vec![read_csv(file_path)?]
+    } else {
+        read_workbook(file_path)?
+    };
+    if sheets.is_empty() {
+        return Err(anyhow!("no rows were found in the file"));
+    }
+    Ok(sheets)
+}
+
+fn read_csv(file_path: &str) -> Result<Sheet> {
+    let content = std::fs::read(file_path).context("Failed to read the file")?;
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(content.as_slice());
+    let mut rows = vec![];
+    for (i, record) in reader.records().enumerate() {
+        let record = record.with_context(|| format!("invalid csv row {}", i + 1))?;
+        // the row number is the line the record starts on,
+        // the position of the record being before the blank lines skipped
+        let number = record.position().map_or(i + 1, |p| {
+            let blank_lines = content[p.byte() as usize..]
+                .iter()
+                .take_while(|b| matches!(b, b'\r' | b'\n'))
+                .filter(|b| **b == b'\n')
+                .count();
+            p.line() as usize + blank_lines
+        });
+        rows.push((number, record.iter().map(clean_cell).collect()));
+    }
+    let file_name = file_path.rsplit('/').next().unwrap_or(file_path);
+    to_sheet(doc_name(file_name).to_string(), rows).ok_or(anyhow!("no rows were found in the file"))
+}
+
+fn read_workbook(file_path: &str) -> Result<Vec<Sheet>> {
+    let mut workbook = open_workbook_auto(file_path).context("Failed to read the workbook")?;
+    let mut sheets = vec![];
+    for name in workbook.sheet_names() {
+        let range = workbook
+            .worksheet_range(&name)
+            .with_context(|| format!("unable to read the sheet {}", name))?;
+        // the range starts at the first used cell of the sheet
+        let first_row = range
+            .start()
+            .map(|(row, _)| row as usize)
+            .unwrap_or_default();
+        let rows = range
+            .rows()
+            .enumerate()
+            .map(|(i, row)| (first_row + i + 1, row.iter().map(cell_text).collect()))
+            .collect();
+        sheets.extend(to_sheet(name, rows));
+    }
+    Ok(sheets)
+}
+
+// to_sheet takes the first non empty row as the header and drops the empty rows
+fn to_sheet(name: String, rows: Vec<(usize, Vec<String>)>) -> Option<Sheet> {
+    let mut rows = rows
+        .into_iter()
+        .filter(|(_, row)| row.iter().any(|cell| !cell.is_empty()))
+        .map(|(number, mut row)| {
+            while row.last().is_some_and(|cell| cell.is_empty()) {
+                row.pop();
+            }
+            (number, row)
+        });
+    let (_, header) = rows.next()?;
+    let rows = rows
+        .map(|(number, mut row)| {
+            // align the row to the header so that every value stays under its column
+            if row.len() < header.len() {
+                row.resize(header.len(), String::new());
+            }
+            (number, row)
+        })
+        .collect();
+    Some(Sheet { name, header, rows })
+}
+
+// chunk_rows groups the consecutive rows up to the chunk size
+// and records the sheet and the row range covered by each chunk
+fn chunk_rows(sheet: &Sheet, chunk_size: usize) -> Vec<Chunk> {
+    let head = head(sheet);
+    let mut chunks = vec![];
+    let mut text = head.clone();
+    let mut range: Option<(usize, usize)> = None;
+    for (number, row) in sheet.rows.iter() {
+        let row = markdown_row(row);
+        if let Some((start, end)) = range {
+            if text.len() + row.len() > chunk_size {
+                chunks.push(row_chunk(sheet, text, start, end));
+                text = head.clone();
+                range = None;
+            }
+        }
+        text.push_str(&row);
+        range = Some((range.map(|(start, _)| start).unwrap_or(*number), *number));
+    }
+    if let Some((start, end)) = range {
+        chunks.push(row_chunk(sheet, text, start, end));
+    }
+    chunks
+}
+
+fn row_chunk(sheet: &Sheet, text: String, row_start: usize, row_end: usize) -> Chunk {
+    let mut metadata = Map::new();
+    metadata.insert(SHEET_KEY.to_string(), sheet.name.to_string().into());
+    metadata.insert(ROW_START_KEY.to_string(), row_start.into());
+    metadata.insert(ROW_END_KEY.to_string(), row_end.into());
+    metadata.insert(CHUNK_TYPE_KEY.to_string(), TABLE_CHUNK.into());
+    Chunk { text, metadata }
+}
+
+// the sheet name followed by the markdown header, repeated in every chunk
+fn head(sheet: &Sheet) -> String {
+    let mut head = format!("Sheet: {}\n", sheet.name);
+    head.push_str(&markdown_row(&sheet.header));
+    head.push_str(&markdown_row(&vec!["---".to_string(); sheet.header.len()]));
+    head
+}
+
+fn cell_text(cell: &Data) -> String {
+    match cell {
+        Data::DateTime(date) => date
+            .as_datetime()
+            .map(|date| date.to_string())
+            .unwrap_or_else(|| date.to_string()),
+        Data::Error(_) => String::new(),
+        cell => clean_cell(&cell.to_string()),
+    }
+}
+
+// multi line values are kept on a single table row
+fn clean_cell(cell: &str) -> String {
+    cell.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_chunk_repeats_the_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sales.csv");
+        // the blank lines are no rows & the short rows are padded, the multi line cells kept on their row
+        let csv = "region,product,revenue\n\nnorth,widgets,1200\nsouth,\"big\ngadgets\",800\neast,gizmos\n";
+        std::fs::write(&path, csv).unwrap();
+
+        let extraction = extract(path.to_str().unwrap(), 80).unwrap();
+        let head = "Sheet: sales\n| region | product | revenue |\n| --- | --- | --- |\n";
+        let chunks: Vec<(String, u64, u64)> = extraction
+            .chunks
+            .iter()
+            .map(|chunk| {
+                (
+                    chunk.text.replace(head, ""),
+                    chunk.metadata[ROW_START_KEY].as_u64().unwrap(),
+                    chunk.metadata[ROW_END_KEY].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert!(extraction
+            .chunks
+            .iter()
+            .all(|chunk| chunk.text.starts_with(head)));
+        assert_eq!(
+            chunks,
+            [
+                ("| north | widgets | 1200 |\n".to_string(), 3, 3),
+                ("| south | big gadgets | 800 |\n".to_string(), 4, 4),
+                ("| east | gizmos |  |\n".to_string(), 6, 6)
+            ]
+        );
+        assert!(extraction
+            .chunks
+            .iter()
+            .all(|chunk| chunk.metadata[SHEET_KEY] == "sales"
+                && chunk.metadata[CHUNK_TYPE_KEY] == TABLE_CHUNK));
+
+        // the rows fitting in the chunk share it
+        let extraction = extract(path.to_str().unwrap(), 1000).unwrap();
+        assert_eq!(extraction.chunks.len(), 1);
+        assert_eq!(extraction.chunks[0].metadata[ROW_START_KEY], 3);
+        assert_eq!(extraction.chunks[0].metadata[ROW_END_KEY], 6);
+    }
+
+    #[test]
+    fn empty_files_have_no_sheet() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("empty.csv");
+        std::fs::write(&path, ",,\n\n").unwrap();
+        assert!(read_sheets(path.to_str().unwrap()).is_err());
+    }
+}
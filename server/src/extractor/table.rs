@@ -0,0 +1,228 @@
+use super::layout::Line;
+
+// minimum number of consecutive multi cell lines to be treated as a table
+const MIN_TABLE_ROWS: usize = 3;
+
+// Table is the grid of cells detected on a page along with its caption
+#[derive(Clone, Debug, Default)]
+pub struct Table {
+    pub caption: Option<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    // render the table as markdown, splitting its rows into groups
+    // of at most `max_len` characters with the header row repeated in each group
+    pub fn to_markdown(&self, max_len: usize) -> Vec<String> {
+        let Some((header, body)) = self.rows.split_first() else {
+            return vec![];
+        };
+        let mut head = String::new();
+        if let Some(caption) = &self.caption {
+            head.push_str(caption);
+            head.push('\n');
+        }
+        head.push_str(&markdown_row(header));
+        head.push_str(&markdown_row(&vec!["---".to_string(); header.len()]));
+
+        let mut parts = vec![];
+        let mut part = head.clone();
+        for row in body.iter() {
+            let row = markdown_row(row);
+            if part.len() + row.len() > max_len && part.len() > head.len() {
+                parts.push(part);
+                part = head.clone();
+            }
+            part.push_str(&row);
+        }
+        parts.push(part);
+        parts
+    }
+}
+
+pub(super) fn markdown_row(cells: &[String]) -> String {
+    let cells: Vec<String> = cells
+        .iter()
+        .map(|cell| cell.trim().replace('|', "\\|"))
+        .collect();
+    format!("| {} |\n", cells.join(" | "))
+}
+
+// is_caption matches the lines like `Table 3: Quarterly revenue`
+fn is_caption(line: &Line) -> bool {
+    line.cells.len() == 1 && line.text().to_lowercase().starts_with("table")
+}
+
+// is_table tells whether the lines start with a run of multi cell lines long enough for a table
+fn is_table(lines: &[Line]) -> bool {
+    lines
+        .iter()
+        .take_while(|line| line.cells.len() >= 2)
+        .count()
+        >= MIN_TABLE_ROWS
+}
+
+// detect_tables finds the runs of lines laid out in columns.
+// It returns the tables along with the indexes of the lines consumed by them
+pub fn detect_tables(lines: &[Line]) -> (Vec<Table>, Vec<usize>) {
+    let mut tables = vec![];
+    let mut consumed = vec![];
+    let mut start = 0;
+    while start < lines.len() {
+        let mut end = start;
+        while end < lines.len() && lines[end].cells.len() >= 2 {
+            end += 1;
+        }
+        if is_table(&lines[start..]) {
+            // the caption above the table, else the one below unless it is above the next table
+            let caption_index = if start > 0
+                && is_caption(&lines[start - 1])
+                && !consumed.contains(&(start - 1))
+            {
+                Some(start - 1)
+            } else if end < lines.len() && is_caption(&lines[end]) && !is_table(&lines[end + 1..]) {
+                Some(end)
+            } else {
+                None
+            };
+            tables.push(Table {
+                caption: caption_index.map(|i| lines[i].text()),
+                rows: align_rows(&lines[start..end]),
+            });
+            consumed.extend(start..end);
+            consumed.extend(caption_index);
+        }
+        start = end.max(start + 1);
+    }
+    (tables, consumed)
+}
+
+// align_rows places the cells of every row under the nearest column,
+// the columns being taken from the row having the most cells
+fn align_rows(lines: &[Line]) -> Vec<Vec<String>> {
+    let columns: Vec<f64> = lines
+        .iter()
+        .max_by_key(|line| line.cells.len())
+        .map(|line| line.cells.iter().map(|cell| cell.x).collect())
+        .unwrap_or_default();
+    lines
+        .iter()
+        .map(|line| {
+            let mut row = vec![String::new(); columns.len()];
+            for cell in line.cells.iter() {
+                let column = columns
+                    .iter()
+                    .enumerate()
+                    .min_by(|(_, a), (_, b)| (*a - cell.x).abs().total_cmp(&(*b - cell.x).abs()))
+                    .map(|(i, _)| i)
+                    .unwrap_or_default();
+                if !row[column].is_empty() {
+                    row[column].push(' ');
+                }
+                row[column].push_str(cell.text.trim());
+            }
+            row
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::layout::Cell;
+    use super::*;
+
+    // line lays the cells out at the given x positions
+    fn line(cells: &[(f64, &str)]) -> Line {
+        Line {
+            cells: cells
+                .iter()
+                .map(|(x, text)| Cell {
+                    x: *x,
+                    text: text.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn columns_are_detected_along_with_the_caption() {
+        let lines = vec![
+            line(&[(10.0, "Revenue grew in every region.")]),
+            line(&[(10.0, "Table 2: Revenue by region")]),
+            line(&[(10.0, "Region"), (120.0, "Q1"), (200.0, "Q2")]),
+            line(&[(10.0, "North"), (120.0, "1,200"), (200.0, "1,350")]),
+            // the missing cell is left empty & the others keep their column
+            line(&[(10.0, "South"), (205.0, "900")]),
+            line(&[(10.0, "See the appendix.")]),
+        ];
+        let (tables, consumed) = detect_tables(&lines);
+        assert_eq!(tables.len(), 1);
+        assert_eq!(
+            tables[0].caption.as_deref(),
+            Some("Table 2: Revenue by region")
+        );
+        assert_eq!(
+            tables[0].rows,
+            [
+                ["Region", "Q1", "Q2"],
+                ["North", "1,200", "1,350"],
+                ["South", "", "900"]
+            ]
+        );
+        assert_eq!(consumed, [2, 3, 4, 1]);
+    }
+
+    #[test]
+    fn a_caption_between_two_tables_goes_to_the_table_below() {
+        let row = |a: &str, b: &str| line(&[(10.0, a), (120.0, b)]);
+        let lines = vec![
+            row("Region", "Q1"),
+            row("North", "1,200"),
+            row("South", "900"),
+            line(&[(10.0, "Table 2: Headcount")]),
+            row("Team", "People"),
+            row("Sales", "12"),
+            row("Support", "8"),
+        ];
+        let (tables, consumed) = detect_tables(&lines);
+        let captions: Vec<Option<&str>> = tables
+            .iter()
+            .map(|table| table.caption.as_deref())
+            .collect();
+        assert_eq!(captions, [None, Some("Table 2: Headcount")]);
+        assert_eq!(consumed, [0, 1, 2, 4, 5, 6, 3]);
+    }
+
+    #[test]
+    fn short_runs_of_columns_are_no_table() {
+        let lines = vec![
+            line(&[(10.0, "Name:"), (120.0, "ACME")]),
+            line(&[(10.0, "Date:"), (120.0, "2024-01-01")]),
+            line(&[(10.0, "Dear customer,")]),
+        ];
+        let (tables, consumed) = detect_tables(&lines);
+        assert!(tables.is_empty());
+        assert!(consumed.is_empty());
+    }
+
+    #[test]
+    fn long_tables_repeat_their_header() {
+        let table = Table {
+            caption: Some("Table 1: Staff".to_string()),
+            rows: vec![
+                vec!["name".to_string(), "role".to_string()],
+                vec!["alice".to_string(), "engineer".to_string()],
+                vec!["bob".to_string(), "a | b".to_string()],
+            ],
+        };
+        let head = "Table 1: Staff\n| name | role |\n| --- | --- |\n";
+        assert_eq!(
+            table.to_markdown(50),
+            [
+                format!("{}| alice | engineer |\n", head),
+                format!("{}| bob | a \\| b |\n", head)
+            ]
+        );
+        assert_eq!(table.to_markdown(1000).len(), 1);
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::warn;
+
+use crate::{
+    extractor::Chunk,
+    utils::{cosine_similarity, generate_json, get_content_embeddings, ConfigVar},
+};
+
+// sentences scoring below are flagged as unsupported by the context
+const SUPPORT_THRESHOLD: f32 = 0.5;
+// sentences checked per answer at most, the rest of the answer is left out of the report
+const MAX_SENTENCES: usize = 30;
+
+// Verdict of the entailment check of a sentence against the context
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Supported,
+    Partial,
+    Unsupported,
+    // the sentence states no fact (e.g. "I don't know")
+    NoClaim,
+}
+
+impl Verdict {
+    fn score(&self) -> f32 {
+        match self {
+            Verdict::Supported | Verdict::NoClaim => 1.0,
+            Verdict::Partial => 0.5,
+            Verdict::Unsupported => 0.0,
+        }
+    }
+}
+
+// SentenceSupport tells how far a sentence of the answer is backed by the context
+#[derive(Serialize, Debug)]
+pub struct SentenceSupport {
+    pub text: String,
+    // highest cosine similarity to a context chunk
+    pub similarity: f32,
+    // citation of the most similar chunk
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    // none when the entailment check failed (the similarity is used instead)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verdict: Option<Verdict>,
+    // from 0 (unsupported) to 1 (supported)
+    pub support: f32,
+    pub supported: bool,
+}
+
+// Grounding reports the support of every sentence of the answer,
+// the confidence being their mean support
+#[derive(Serialize, Debug)]
+pub struct Grounding {
+    pub confidence: f32,
+    pub unsupported: usize,
+    pub sentences: Vec<SentenceSupport>,
+}
+
+// GroundedAnswer is the answer along with its grounding
+#[derive(Serialize, Debug)]
+pub struct GroundedAnswer {
+    pub answer: String,
+    #[serde(flatten)]
+    pub grounding: Grounding,
+}
+
+#[derive(Deserialize)]
+struct Entailment {
+    verdicts: Vec<SentenceVerdict>,
+}
+
+#[derive(Deserialize)]
+struct SentenceVerdict {
+    // 1-based number of the sentence
+    sentence: usize,
+    verdict: Verdict,
+}
+
+// verify splits the answer into sentences and checks every sentence against the context chunks:
+// by the embedding similarity and by asking the generation model whether the context entails it
+pub async fn verify(settings: &ConfigVar, answer: &str, sources: &[Chunk]) -> Result<Grounding> {
+    let sentences: Vec<String> = split_sentences(answer)
+        .into_iter()
+        .take(MAX_SENTENCES)
+        .collect();
+    let mut chunk_embeddings = vec![];
+    for chunk in sources.iter() {
+        let embedding = get_content_embeddings(settings.clone(), &chunk.text)
+            .await
+            .context("unable to embed the context")?;
+        chunk_embeddings.push(embedding);
+    }
+    let verdicts = match entailment(settings, &sentences, sources).await {
+        Ok(verdicts) => verdicts,
+        Err(e) => {
+            warn!("the entailment check failed: {:#}", e);
+            HashMap::new()
+        }
+    };
+
+    let mut checked = vec![];
+    for (i, text) in sentences.into_iter().enumerate() {
+        let embedding = get_content_embeddings(settings.clone(), &text)
+            .await
+            .context("unable to embed the answer")?;
+        let closest = chunk_embeddings
+            .iter()
+            .map(|chunk| cosine_similarity(&embedding, chunk))
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        let similarity = closest.map(|(_, similarity)| similarity).unwrap_or(0.0);
+        let verdict = verdicts.get(&(i + 1)).copied();
+        let support = verdict
+            .map(|verdict| verdict.score())
+            .unwrap_or(similarity.clamp(0.0, 1.0));
+        checked.push(SentenceSupport {
+            text,
+            similarity,
+            source: closest.and_then(|(index, _)| sources[index].citation()),
+            verdict,
+            support,
+            supported: support >= SUPPORT_THRESHOLD,
+        });
+    }
+    // the sentences stating no fact don't weigh on the confidence
+    let claims: Vec<f32> = checked
+        .iter()
+        .filter(|sentence| sentence.verdict != Some(Verdict::NoClaim))
+        .map(|sentence| sentence.support)
+        .collect();
+    let confidence = if claims.is_empty() {
+        0.0
+    } else {
+        claims.iter().sum::<f32>() / claims.len() as f32
+    };
+    Ok(Grounding {
+        confidence,
+        unsupported: checked
+            .iter()
+            .filter(|sentence| !sentence.supported)
+            .count(),
+        sentences: checked,
+    })
+}
+
+// entailment asks the generation model for the verdict of every sentence keyed by its number
+async fn entailment(
+    settings: &ConfigVar,
+    sentences: &[String],
+    sources: &[Chunk],
+) -> Result<HashMap<usize, Verdict>> {
+    if sentences.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let context = sources
+        .iter()
+        .map(|chunk| match chunk.citation() {
+            Some(citation) => format!("[{}] {}", citation, chunk.text),
+            None => chunk.text.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join("\n\n");
+    let numbered = sentences
+        .iter()
+        .enumerate()
+        .map(|(i, sentence)| format!("{}. {}", i + 1, sentence))
+        .collect::<Vec<String>>()
+        .join("\n");
+    let prompt = format!(
+        "You are checking whether the sentences of an answer are backed by its context.
+        For every numbered sentence give a verdict:
+        supported: the context states it, partial: the context states only part of it,
+        unsupported: the context doesn't state it or contradicts it,
+        no_claim: the sentence states no fact (e.g. it says the answer is unknown).
+        Judge only against the context, not against your own knowledge.
+        Context: {context}
+        Sentences:
+        {numbered}"
+    );
+    let format = json!({
+        "type": "object",
+        "properties": {
+            "verdicts": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "sentence": { "type": "integer" },
+                        "verdict": {
+                            "type": "string",
+                            "enum": ["supported", "partial", "unsupported", "no_claim"]
+                        }
+                    },
+                    "required": ["sentence", "verdict"]
+                }
+            }
+        },
+        "required": ["verdicts"]
+    });
+    let output = generate_json(settings, &prompt, &format).await?;
+    let entailment: Entailment =
+        serde_json::from_str(&output).context("the entailment answer is not valid")?;
+    Ok(entailment
+        .verdicts
+        .into_iter()
+        .map(|verdict| (verdict.sentence, verdict.verdict))
+        .collect())
+}
+
+// split_sentences splits the answer into its sentences (and its list items),
+// the markdown list markers are left out
+pub fn split_sentences(answer: &str) -> Vec<String> {
+    let mut sentences = vec![];
+    for line in answer.lines() {
+        let line = line
+            .trim()
+            .trim_start_matches(['-', '*', '•', '#', '>'])
+            .trim_start();
+        let mut current = String::new();
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            current.push(c);
+            let ends = matches!(c, '.' | '!' | '?')
+                && chars.peek().is_none_or(|next| next.is_whitespace());
+            if ends {
+                sentences.push(std::mem::take(&mut current));
+            }
+        }
+        sentences.push(current);
+    }
+    sentences
+        .into_iter()
+        .map(|sentence| sentence.trim().to_string())
+        // numbered list markers (`1.`) end up as sentences on their own
+        .filter(|sentence| sentence.chars().any(|c| c.is_alphabetic()))
+        .collect()
+}
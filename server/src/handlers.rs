@@ -1,21 +1,111 @@
+use std::{convert::Infallible, fs, path::PathBuf};
+
 use axum::{
-    extract::{Multipart, Path, State},
-    http::StatusCode,
-    response::IntoResponse,
+    body::Bytes,
+    extract::{Multipart, Path, Query, State},
+    http::{header, HeaderMap, HeaderName, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
     Json,
 };
+use futures::stream;
 use serde::{Deserialize, Serialize};
-use tracing::debug;
+use serde_json::json;
+use tokio::sync::mpsc;
+use tracing::{debug, info};
+use uuid::Uuid;
 
 use crate::{
-    utils::{extract_file_content, read_file},
+    agent::{self, DEFAULT_STEPS},
+    batch,
+    compare::{compare, TooManySections},
+    extractor::{self, extract_file_content, PasswordError},
+    metadata::{DocMetadata, SearchFilter},
+    openai::{self, ChatRequest},
+    processor::{answer_prompt, DocumentNotFound, IngestionReport},
+    query::RetrievalOptions,
+    structured::{InvalidOutput, SchemaError},
+    summarizer::{summarize, Progress, SummaryOptions},
+    utils::{generate_stream, generate_text, read_file},
     AppState,
 };
 
+// header carrying the password of the encrypted document to be displayed
+const PASSWORD_HEADER: &str = "x-document-password";
+// header carrying the id of the batch of questions
+const BATCH_JOB_HEADER: &str = "x-batch-job";
+// results of the batches of questions
+const BATCH_DIR: &str = "./uploads/batches";
+
 #[derive(Deserialize)]
 pub struct InputPrompt {
     user_query: String,
     doc_name: String,
+    #[serde(default)]
+    filter: Option<SearchFilter>,
+    // answer with the support of every sentence by the context (json) rather than the plain text
+    #[serde(default)]
+    verify: bool,
+    // query strategy & context diversity, the configured ones by default
+    #[serde(flatten)]
+    retrieval: RetrievalOptions,
+}
+
+// AgentInput is the question answered by the agent searching the documents step by step
+#[derive(Deserialize)]
+pub struct AgentInput {
+    user_query: String,
+    // the documents the agent can search
+    doc_names: Vec<String>,
+    #[serde(default)]
+    filter: Option<SearchFilter>,
+    // steps of the agent, the configured ones by default
+    #[serde(default)]
+    max_steps: Option<usize>,
+}
+
+// CompareInput is the pair of documents compared, on the focus topic only if given
+#[derive(Deserialize)]
+pub struct CompareInput {
+    doc_a: String,
+    doc_b: String,
+    #[serde(default)]
+    focus: Option<String>,
+}
+
+// ExtractInput is the json schema to be filled from the document
+#[derive(Deserialize)]
+pub struct ExtractInput {
+    doc_name: String,
+    schema: serde_json::Value,
+    #[serde(default)]
+    filter: Option<SearchFilter>,
+}
+
+// BatchParams are the query parameters of the batch of questions
+#[derive(Deserialize)]
+pub struct BatchParams {
+    // document asked by the questions naming none
+    #[serde(default)]
+    doc_name: Option<String>,
+    // id of the batch to be resumed, a new batch is started when missing
+    #[serde(default)]
+    job: Option<String>,
+    #[serde(default)]
+    concurrency: Option<usize>,
+}
+
+// DirectoryInput points to a local source code directory to be ingested
+#[derive(Deserialize)]
+pub struct DirectoryInput {
+    path: String,
+    // name of the document, defaults to the directory name
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(flatten)]
+    metadata: DocMetadata,
 }
 
 #[derive(Serialize)]
@@ -23,6 +113,37 @@ pub struct DocInfo {
     name: String,
 }
 
+// PasswordFailure tells the client which file needs a (correct) password
+#[derive(Serialize)]
+pub struct PasswordFailure {
+    code: &'static str,
+    file_name: String,
+    message: String,
+}
+
+// EntryReport is the outcome of every entry of the uploaded archives:
+// `ingested` along with its report, `failed` or `skipped` along with the reason
+#[derive(Serialize)]
+pub struct EntryReport {
+    file_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    archive: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entry: Option<String>,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    report: Option<IngestionReport>,
+}
+
+// find the password error in the error chain
+fn password_error(e: &anyhow::Error) -> Option<PasswordError> {
+    e.chain()
+        .find_map(|cause| cause.downcast_ref::<PasswordError>())
+        .copied()
+}
+
 pub async fn doc_names(State(state): State<AppState>) -> impl IntoResponse {
     match state.processor.vec_store.list_collections().await {
         Ok(collection_names) => {
@@ -38,38 +159,208 @@ pub async fn doc_names(State(state): State<AppState>) -> impl IntoResponse {
     }
 }
 
-pub async fn file_handler(Path(file_name): Path<String>) -> impl IntoResponse {
-    match extract_file_content(file_name.as_str()) {
+pub async fn file_handler(Path(file_name): Path<String>, headers: HeaderMap) -> impl IntoResponse {
+    let password = headers
+        .get(PASSWORD_HEADER)
+        .and_then(|value| value.to_str().ok());
+    match extract_file_content(file_name.as_str(), password) {
         Ok(data) => data.into_response(),
-        Err(_) => {
-            return (StatusCode::NOT_FOUND, "File not found or cannot be read.").into_response()
+        Err(e) => match password_error(&e) {
+            Some(password_error) => (
+                StatusCode::UNAUTHORIZED,
+                Json(PasswordFailure {
+                    code: password_error.code(),
+                    file_name,
+                    message: password_error.to_string(),
+                }),
+            )
+                .into_response(),
+            None => (StatusCode::NOT_FOUND, "File not found or cannot be read.").into_response(),
+        },
+    }
+}
+
+// document_handler returns the catalog entry of the document
+// along with its summary & suggested questions once generated
+pub async fn document_handler(
+    State(state): State<AppState>,
+    Path(doc_name): Path<String>,
+) -> impl IntoResponse {
+    match state
+        .processor
+        .catalog
+        .get(&catalog_name(&state, &doc_name))
+    {
+        Some(entry) => (StatusCode::OK, Json(entry)).into_response(),
+        None => (StatusCode::NOT_FOUND, "Document not found.").into_response(),
+    }
+}
+
+// summarize_handler summarizes the whole document (rather than its chunks similar to a question)
+// streaming the progress of the map-reduce as server sent events:
+// `progress` events followed by the `summary` (or the `error`) event
+pub async fn summarize_handler(
+    State(state): State<AppState>,
+    Path(doc_name): Path<String>,
+    body: Bytes,
+) -> impl IntoResponse {
+    // every option has a default, the body is optional
+    let options: SummaryOptions = if body.iter().all(|b| b.is_ascii_whitespace()) {
+        SummaryOptions::default()
+    } else {
+        match serde_json::from_slice(&body) {
+            Ok(options) => options,
+            Err(e) => {
+                return (StatusCode::BAD_REQUEST, format!("invalid options: {}", e)).into_response()
+            }
         }
+    };
+    let doc_name = catalog_name(&state, &doc_name);
+    let chunks = match state.processor.document_chunks(&doc_name).await {
+        Ok(chunks) if !chunks.is_empty() => chunks,
+        Ok(_) => return (StatusCode::NOT_FOUND, "Document not found.").into_response(),
+        Err(e) => {
+            debug!("unable to read the document: {:#}", e);
+            return (StatusCode::NOT_FOUND, "Document not found.").into_response();
+        }
+    };
+
+    let (sender, receiver) = mpsc::unbounded_channel::<Event>();
+    let settings = state.processor.settings.clone();
+    tokio::spawn(async move {
+        let progress = |progress: Progress| {
+            let event = Event::default().event("progress").json_data(progress);
+            let _ = sender.send(event.unwrap_or_default());
+        };
+        let event = match summarize(&settings, &chunks, &options, progress).await {
+            Ok(summary) => Event::default()
+                .event("summary")
+                .json_data(json!({ "doc_name": doc_name, "summary": summary }))
+                .unwrap_or_default(),
+            Err(e) => Event::default().event("error").data(format!("{:#}", e)),
+        };
+        let _ = sender.send(event);
+    });
+    let events = stream::unfold(receiver, |mut receiver| async move {
+        let event = receiver.recv().await?;
+        Some((Ok::<Event, Infallible>(event), receiver))
+    });
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+// catalog_name is the name of the document given its name or its file name
+fn catalog_name(state: &AppState, name: &str) -> String {
+    if state.processor.catalog.get(name).is_some() {
+        name.to_string()
+    } else {
+        extractor::doc_name(name).to_string()
     }
 }
 
 pub async fn upload_file(State(state): State<AppState>, multipart: Multipart) -> impl IntoResponse {
-    let file_names = match read_file(multipart).await {
-        Ok(file_names) => file_names,
+    let uploaded = match read_file(multipart).await {
+        Ok(uploaded) => uploaded,
         Err(e) => {
-            debug!("unable to read the file: {}", e);
-            vec![]
+            debug!("unable to read the file: {:#}", e);
+            return (StatusCode::BAD_REQUEST, format!("{:#}", e)).into_response();
         }
     };
-    if file_names.len() == 0 {
-        return (StatusCode::BAD_REQUEST, "no pdf files were uploaded").into_response();
+    let file_names = &uploaded.file_names;
+    let mut results = vec![];
+    for file_name in file_names.iter() {
+        let metadata = uploaded.metadata.for_file(file_name);
+        let result = state
+            .processor
+            .process_file(
+                file_name.as_str(),
+                &metadata,
+                uploaded.password_for(file_name),
+                uploaded.workspace.as_deref(),
+            )
+            .await;
+        if let Err(e) = &result {
+            eprintln!("error occurred:: {}", e);
+        }
+        results.push((file_name.to_string(), result));
+    }
+
+    // the archives are reported entry by entry
+    if !uploaded.sources.is_empty() || !uploaded.skipped.is_empty() {
+        let mut entries: Vec<EntryReport> = results
+            .into_iter()
+            .map(|(file_name, result)| {
+                let (archive, entry) = uploaded
+                    .sources
+                    .get(&file_name)
+                    .cloned()
+                    .map(|(archive, entry)| (Some(archive), Some(entry)))
+                    .unwrap_or_default();
+                let (status, error, report) = match result {
+                    Ok(report) => ("ingested", None, Some(report)),
+                    Err(e) => (
+                        "failed",
+                        Some(
+                            password_error(&e)
+                                .map(|error| error.code().to_string())
+                                .unwrap_or(format!("{:#}", e)),
+                        ),
+                        None,
+                    ),
+                };
+                EntryReport {
+                    file_name,
+                    archive,
+                    entry,
+                    status,
+                    error,
+                    report,
+                }
+            })
+            .collect();
+        entries.extend(
+            uploaded
+                .skipped
+                .iter()
+                .map(|(archive, skipped)| EntryReport {
+                    file_name: skipped.entry.to_string(),
+                    archive: Some(archive.to_string()),
+                    entry: Some(skipped.entry.to_string()),
+                    status: "skipped",
+                    error: Some(skipped.reason.to_string()),
+                    report: None,
+                }),
+        );
+        let status = if entries.iter().all(|entry| entry.status == "ingested") {
+            StatusCode::OK
+        } else {
+            StatusCode::MULTI_STATUS
+        };
+        return (status, Json(entries)).into_response();
     }
+
     let mut processed_files = vec![];
-    for file_name in file_names.iter() {
-        match state.processor.process_file(file_name.as_str()).await {
-            Ok(()) => processed_files.push(file_name),
+    let mut password_failures = vec![];
+    for (file_name, result) in results {
+        match result {
+            Ok(report) => processed_files.push(report),
             Err(e) => {
-                eprintln!("error occurred:: {}", e.to_string());
+                if let Some(password_error) = password_error(&e) {
+                    password_failures.push(PasswordFailure {
+                        code: password_error.code(),
+                        file_name,
+                        message: password_error.to_string(),
+                    });
+                }
             }
-        };
+        }
     }
 
     if processed_files.len() == file_names.len() {
-        (StatusCode::OK, "files uploaded successfully").into_response()
+        (StatusCode::OK, Json(processed_files)).into_response()
+    } else if !password_failures.is_empty() {
+        (StatusCode::UNAUTHORIZED, Json(password_failures)).into_response()
     } else {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -79,6 +370,62 @@ pub async fn upload_file(State(state): State<AppState>, multipart: Multipart) ->
     }
 }
 
+pub async fn directory_handler(
+    State(state): State<AppState>,
+    Json(data): Json<DirectoryInput>,
+) -> impl IntoResponse {
+    // only the directories under the configured source roots are read
+    let roots = state
+        .processor
+        .settings
+        .source_roots
+        .as_deref()
+        .unwrap_or_default();
+    let Some(path) = fs::canonicalize(&data.path).ok().filter(|path| {
+        roots
+            .iter()
+            .filter_map(|root| fs::canonicalize(root).ok())
+            .any(|root| path.starts_with(root))
+    }) else {
+        return (
+            StatusCode::FORBIDDEN,
+            format!("{} is not under the source roots", data.path),
+        )
+            .into_response();
+    };
+    if !path.is_dir() {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("{} is not a directory", data.path),
+        )
+            .into_response();
+    }
+    let path = path.to_string_lossy();
+    let mut metadata = data.metadata;
+    metadata.doc_type = metadata.doc_type.or(Some("code".to_string()));
+    let metadata = metadata.for_file(&path);
+    match state
+        .processor
+        .process_directory(&path, data.name.as_deref(), &metadata)
+        .await
+    {
+        Ok(report) => (StatusCode::OK, Json(report)).into_response(),
+        Err(e) => {
+            eprintln!("error occurred:: {:#}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)).into_response()
+        }
+    }
+}
+
+pub async fn sync_status(State(state): State<AppState>) -> impl IntoResponse {
+    let status = state
+        .watcher
+        .as_ref()
+        .map(|watcher| watcher.status())
+        .unwrap_or_default();
+    (StatusCode::OK, Json(status)).into_response()
+}
+
 pub async fn prompt_handler(
     State(state): State<AppState>,
     Json(data): Json<InputPrompt>,
@@ -86,14 +433,298 @@ pub async fn prompt_handler(
     let user_query = data.user_query;
     let doc_name = data.doc_name;
     let processor = state.processor;
+    if data.verify {
+        return match processor
+            .answer_verified(
+                user_query.as_str(),
+                doc_name.as_str(),
+                data.filter.as_ref(),
+                &data.retrieval,
+            )
+            .await
+        {
+            Ok(answer) => (StatusCode::OK, Json(answer)).into_response(),
+            Err(e) if e.is::<DocumentNotFound>() => {
+                (StatusCode::NOT_FOUND, e.to_string()).into_response()
+            }
+            Err(e) => {
+                eprintln!("error occurred:: {}", e);
+                (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+            }
+        };
+    }
     match processor
-        .process_prompt(user_query.as_str(), doc_name.as_str())
+        .process_prompt(
+            user_query.as_str(),
+            doc_name.as_str(),
+            data.filter.as_ref(),
+            &data.retrieval,
+        )
         .await
     {
         Ok(response) => (StatusCode::OK, response).into_response(),
+        Err(e) if e.is::<DocumentNotFound>() => {
+            (StatusCode::NOT_FOUND, e.to_string()).into_response()
+        }
         Err(e) => {
-            eprintln!("error occurred:: {}", e.to_string());
+            eprintln!("error occurred:: {}", e);
             (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
         }
     }
 }
+
+// agent_handler answers the (comparative or multi-hop) question by letting the model search the documents
+// for its sub-questions, the answer being returned along with the trace of the steps
+pub async fn agent_handler(
+    State(state): State<AppState>,
+    Json(data): Json<AgentInput>,
+) -> impl IntoResponse {
+    let processor = state.processor;
+    if data.doc_names.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            "bad request - doc_names is missing",
+        )
+            .into_response();
+    }
+    let max_steps = data
+        .max_steps
+        .or(processor.settings.agent_max_steps)
+        .unwrap_or(DEFAULT_STEPS);
+    match agent::run(
+        &processor,
+        &data.user_query,
+        &data.doc_names,
+        data.filter.as_ref(),
+        max_steps,
+    )
+    .await
+    {
+        Ok(answer) => (StatusCode::OK, Json(answer)).into_response(),
+        Err(e) if e.is::<DocumentNotFound>() => {
+            (StatusCode::NOT_FOUND, e.to_string()).into_response()
+        }
+        Err(e) => {
+            debug!("the agent failed: {:#}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)).into_response()
+        }
+    }
+}
+
+// compare_handler aligns the similar sections of both documents and reports
+// the same, the changed & the unmatched ones with their citations
+pub async fn compare_handler(
+    State(state): State<AppState>,
+    Json(data): Json<CompareInput>,
+) -> impl IntoResponse {
+    let doc_a = catalog_name(&state, &data.doc_a);
+    let doc_b = catalog_name(&state, &data.doc_b);
+    for doc_name in [&doc_a, &doc_b] {
+        if state.processor.catalog.get(doc_name).is_none() {
+            return (
+                StatusCode::NOT_FOUND,
+                format!("Document not found: {}", doc_name),
+            )
+                .into_response();
+        }
+    }
+    let focus = data
+        .focus
+        .as_deref()
+        .filter(|focus| !focus.trim().is_empty());
+    match compare(&state.processor, &doc_a, &doc_b, focus).await {
+        Ok(comparison) => (StatusCode::OK, Json(comparison)).into_response(),
+        Err(e) if e.is::<TooManySections>() => {
+            (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()).into_response()
+        }
+        Err(e) => {
+            debug!("unable to compare the documents: {:#}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)).into_response()
+        }
+    }
+}
+
+// extract_handler fills the json schema from the document along with the sources of every field
+pub async fn extract_handler(
+    State(state): State<AppState>,
+    Json(data): Json<ExtractInput>,
+) -> impl IntoResponse {
+    let doc_name = catalog_name(&state, &data.doc_name);
+    match state
+        .processor
+        .extract_structured(&doc_name, &data.schema, data.filter.as_ref())
+        .await
+    {
+        Ok(result) => (StatusCode::OK, Json(result)).into_response(),
+        Err(e) => {
+            if let Some(schema_error) = e.downcast_ref::<SchemaError>() {
+                return (StatusCode::BAD_REQUEST, schema_error.to_string()).into_response();
+            }
+            if let Some(invalid) = e.downcast_ref::<InvalidOutput>() {
+                return (StatusCode::UNPROCESSABLE_ENTITY, Json(invalid)).into_response();
+            }
+            debug!("unable to extract the data: {:#}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)).into_response()
+        }
+    }
+}
+
+// batch_handler answers the jsonl questions of the body and returns the jsonl results.
+// The results are saved under the job id (sent back in the `x-batch-job` header)
+// so that the batch can be resumed by posting it again with the job id.
+pub async fn batch_handler(
+    State(state): State<AppState>,
+    Query(params): Query<BatchParams>,
+    body: String,
+) -> impl IntoResponse {
+    let job = params.job.unwrap_or(Uuid::new_v4().to_string());
+    let Some(output) = batch_path(&job) else {
+        return (StatusCode::BAD_REQUEST, "invalid job id").into_response();
+    };
+    let questions = match batch::parse_questions(&body, params.doc_name.as_deref()) {
+        Ok(questions) => questions,
+        Err(e) => return (StatusCode::BAD_REQUEST, format!("{:#}", e)).into_response(),
+    };
+    if let Err(e) = fs::create_dir_all(BATCH_DIR) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+    let concurrency = params.concurrency.unwrap_or(batch::DEFAULT_CONCURRENCY);
+    match batch::run(&state.processor, questions, &output, concurrency).await {
+        Ok(summary) => {
+            info!("batch {}: {:?}", job, summary);
+            batch_results(Path(job)).await.into_response()
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)).into_response(),
+    }
+}
+
+// batch_results returns the jsonl results of the batch answered so far
+pub async fn batch_results(Path(job): Path<String>) -> impl IntoResponse {
+    let results = batch_path(&job)
+        .filter(|output| output.exists())
+        .map(|output| batch::read_results(&output));
+    match results {
+        Some(Ok(results)) => {
+            let lines: Vec<String> = results
+                .iter()
+                .filter_map(|result| serde_json::to_string(result).ok())
+                .collect();
+            (
+                StatusCode::OK,
+                [
+                    (header::CONTENT_TYPE, "application/x-ndjson".to_string()),
+                    (HeaderName::from_static(BATCH_JOB_HEADER), job),
+                ],
+                lines.join("\n") + "\n",
+            )
+                .into_response()
+        }
+        Some(Err(e)) => (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)).into_response(),
+        None => (StatusCode::NOT_FOUND, "Batch not found.").into_response(),
+    }
+}
+
+// the results file of the batch, the job id being part of the file name
+fn batch_path(job: &str) -> Option<PathBuf> {
+    let valid = !job.is_empty()
+        && job
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then(|| PathBuf::from(BATCH_DIR).join(format!("{}.jsonl", job)))
+}
+
+// models_handler lists the documents & the workspaces as the OpenAI models
+pub async fn models_handler(State(state): State<AppState>) -> impl IntoResponse {
+    let models = openai::models(&state.processor.catalog);
+    Json(json!({ "object": "list", "data": models })).into_response()
+}
+
+// chat_completions_handler answers the last user message of the OpenAI chat completion request
+// from the document (or the workspace) named by the model, streamed as server sent events if asked
+pub async fn chat_completions_handler(
+    State(state): State<AppState>,
+    Json(request): Json<ChatRequest>,
+) -> impl IntoResponse {
+    let processor = state.processor;
+    let Some((collection, filter)) = openai::resolve_model(&processor.catalog, &request.model)
+    else {
+        let message = format!("The model `{}` does not exist", request.model);
+        let error = openai::error(&message, "invalid_request_error", "model_not_found");
+        return (StatusCode::NOT_FOUND, Json(error)).into_response();
+    };
+    let Some((query, question)) = openai::question(&request.messages) else {
+        let error = openai::error(
+            "a user message is required",
+            "invalid_request_error",
+            "invalid_messages",
+        );
+        return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+    };
+    let sources = match processor
+        .retrieve_context(
+            &collection,
+            &query,
+            filter.as_ref(),
+            &RetrievalOptions::default(),
+        )
+        .await
+    {
+        Ok(sources) => sources,
+        Err(e) => {
+            debug!("unable to retrieve the context: {:#}", e);
+            let error = openai::error(&format!("{:#}", e), "server_error", "retrieval_failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response();
+        }
+    };
+    let prompt = answer_prompt(&question, &sources);
+    let id = format!("chatcmpl-{}", Uuid::new_v4());
+    let created = openai::now();
+    let model = request.model;
+
+    if !request.stream {
+        return match generate_text(&processor.settings, &prompt).await {
+            Ok(answer) => {
+                let citations: Vec<String> = sources
+                    .iter()
+                    .filter_map(|chunk| chunk.citation())
+                    .collect();
+                let completion = openai::completion(&id, created, &model, &answer, &citations);
+                (StatusCode::OK, Json(completion)).into_response()
+            }
+            Err(e) => {
+                let error = openai::error(&format!("{:#}", e), "server_error", "generation_failed");
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response()
+            }
+        };
+    }
+
+    let (sender, receiver) = mpsc::unbounded_channel::<Event>();
+    let settings = processor.settings.clone();
+    tokio::spawn(async move {
+        let chunk = |delta: serde_json::Value, finish_reason: Option<&str>| {
+            let chunk = openai::completion_chunk(&id, created, &model, delta, finish_reason);
+            Event::default().data(chunk.to_string())
+        };
+        let _ = sender.send(chunk(json!({ "role": "assistant" }), None));
+        let streamed = generate_stream(&settings, &prompt, |text| {
+            let _ = sender.send(chunk(json!({ "content": text }), None));
+        })
+        .await;
+        let event = match streamed {
+            Ok(_) => chunk(json!({}), Some("stop")),
+            Err(e) => {
+                let error = openai::error(&format!("{:#}", e), "server_error", "generation_failed");
+                Event::default().data(error.to_string())
+            }
+        };
+        let _ = sender.send(event);
+        let _ = sender.send(Event::default().data("[DONE]"));
+    });
+    let events = stream::unfold(receiver, |mut receiver| async move {
+        let event = receiver.recv().await?;
+        Some((Ok::<Event, Infallible>(event), receiver))
+    });
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
@@ -1,29 +1,111 @@
+mod agent;
+mod batch;
+mod catalog;
+mod compare;
+mod evaluation;
+mod extractor;
+mod grounding;
 mod handlers;
+mod memory_store;
+mod metadata;
+mod openai;
+mod parents;
 mod processor;
+mod query;
+mod structured;
+mod summarizer;
+#[cfg(test)]
+mod tests;
 mod utils;
 mod vector_db;
+mod watcher;
 
-use std::sync::Arc;
+use std::{path::PathBuf, process, sync::Arc};
 
 use axum::{
     middleware,
     routing::{get, post},
     Router,
 };
-use handlers::{doc_names, file_handler, prompt_handler, upload_file};
+use catalog::Catalog;
+use clap::{Parser, Subcommand};
+use handlers::{
+    agent_handler, batch_handler, batch_results, chat_completions_handler, compare_handler,
+    directory_handler, doc_names, document_handler, extract_handler, file_handler, models_handler,
+    prompt_handler, summarize_handler, sync_status, upload_file,
+};
+use memory_store::MemoryStore;
 use processor::Processor;
-use tracing::info;
-use tracing_subscriber;
+use tracing::{error, info, warn};
 use utils::{get_settings, log_request, ConfigVar};
-use vector_db::VectorStore;
+use vector_db::{QdrantStore, VectorStore};
+use watcher::FolderWatcher;
+
+// db url keeping the documents in memory instead of qdrant
+const MEMORY_DB_URL: &str = "memory";
 
 #[derive(Clone)]
 struct AppState {
     processor: Arc<Processor>,
+    watcher: Option<Arc<FolderWatcher>>,
+}
+
+// the server is started unless a command is given
+#[derive(Parser)]
+#[command(version, about = "DocuMind server")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(
+        about = "Answer the questions of a jsonl file, resuming from the answers already in the output file"
+    )]
+    Batch {
+        #[arg(
+            long,
+            help = "jsonl file of the questions, one {\"question\": ..} per line"
+        )]
+        input: PathBuf,
+        #[arg(long, help = "jsonl file the answers are appended to")]
+        output: PathBuf,
+        #[arg(
+            long,
+            help = "document asked by the questions naming none, e.g. report.pdf"
+        )]
+        doc_name: Option<String>,
+        #[arg(long, default_value_t = batch::DEFAULT_CONCURRENCY, help = "questions answered at once")]
+        concurrency: usize,
+    },
+    #[command(
+        about = "Evaluate the retrieval & the answers against a golden dataset, reporting recall@k, MRR, the LLM judge scores & the latencies"
+    )]
+    Eval {
+        #[arg(
+            long,
+            help = "jsonl file of {\"question\", \"expected_answer\", \"expected_pages\"} per line"
+        )]
+        dataset: PathBuf,
+        #[arg(
+            long,
+            help = "document asked by the questions naming none, e.g. report.pdf"
+        )]
+        doc_name: Option<String>,
+        #[arg(long, default_value_t = evaluation::DEFAULT_K, help = "chunks ranked per question")]
+        k: usize,
+        #[arg(
+            long,
+            help = "json report file, defaults to evals/<embedding model>-<chunk size>-<generate model>-<timestamp>.json"
+        )]
+        output: Option<PathBuf>,
+    },
 }
 
 #[tokio::main]
 async fn main() {
+    let cli = Cli::parse();
     tracing_subscriber::fmt()
         .with_max_level(tracing::Level::INFO)
         .init();
@@ -35,23 +117,110 @@ async fn main() {
         .as_ref()
         .expect("db url connection string is required");
 
+    let processor = Arc::new(Processor::new(
+        settings.clone(),
+        vector_store(db_url),
+        Catalog::load(),
+    ));
+
+    if let Some(Command::Eval {
+        dataset,
+        doc_name,
+        k,
+        output,
+    }) = cli.command
+    {
+        let result = async {
+            let report = evaluation::evaluate(&processor, &dataset, doc_name.as_deref(), k).await?;
+            let path = evaluation::save(&report, output.as_deref())?;
+            Ok::<_, anyhow::Error>((report, path))
+        }
+        .await;
+        match result {
+            Ok((report, path)) => info!(
+                "{} questions ({} failed): {} saved to {}",
+                report.questions,
+                report.failed,
+                serde_json::to_string(&report.metrics).unwrap_or_default(),
+                path.display()
+            ),
+            Err(e) => {
+                error!("evaluation failed: {:#}", e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+    if let Some(Command::Batch {
+        input,
+        output,
+        doc_name,
+        concurrency,
+    }) = cli.command
+    {
+        let result = async {
+            let input = std::fs::read_to_string(&input)?;
+            let questions = batch::parse_questions(&input, doc_name.as_deref())?;
+            batch::run(&processor, questions, &output, concurrency).await
+        }
+        .await;
+        match result {
+            Ok(summary) => info!(
+                "{} questions: {} answered, {} failed, {} answered before",
+                summary.total, summary.answered, summary.failed, summary.resumed
+            ),
+            Err(e) => {
+                error!("batch failed: {:#}", e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // keep the documents in sync with the watched folder
+    let watcher = settings.watch_dir.as_ref().and_then(|watch_dir| {
+        FolderWatcher::start(processor.clone(), watch_dir, settings.watch_debounce_ms)
+            .inspect_err(|e| warn!("folder sync is disabled: {:#}", e))
+            .ok()
+            .map(Arc::new)
+    });
+
     // shared app state for handlers
-    let state = AppState {
-        processor: Arc::new(Processor::new(settings.clone(), VectorStore::new(&db_url))),
-    };
+    let state = AppState { processor, watcher };
+
+    // start the app server
+    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
+    info!("Starting server at port: 3000");
+    axum::serve(listener, app(state)).await.unwrap();
+}
+
+fn vector_store(db_url: &str) -> Box<dyn VectorStore> {
+    if db_url == MEMORY_DB_URL {
+        warn!("the documents are kept in memory and lost on restart");
+        return Box::new(MemoryStore::new());
+    }
+    Box::new(QdrantStore::new(db_url))
+}
 
-    // the routes configuration
-    let app = Router::new()
+// the routes configuration
+fn app(state: AppState) -> Router {
+    Router::new()
         .route("/", get(doc_names))
         .route("/file/{fileName}", get(file_handler))
+        .route("/documents/{docName}", get(document_handler))
+        .route("/documents/{docName}/summarize", post(summarize_handler))
         .route("/upload", post(upload_file))
         .layer(axum::extract::DefaultBodyLimit::max(500 * 1024 * 1024))
+        .route("/directory", post(directory_handler))
         .route("/prompt", post(prompt_handler))
+        .route("/agent", post(agent_handler))
+        .route("/compare", post(compare_handler))
+        .route("/extract", post(extract_handler))
+        .route("/batch", post(batch_handler))
+        .route("/batch/{job}", get(batch_results))
+        .route("/sync/status", get(sync_status))
+        .route("/v1/models", get(models_handler))
+        .route("/v1/chat/completions", post(chat_completions_handler))
         .layer(middleware::from_fn(log_request))
-        .with_state(state.clone());
-
-    // start the app server
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    info!("Starting server at port: 3000");
-    axum::serve(listener, app).await.unwrap();
+        .with_state(state)
 }
@@ -0,0 +1,251 @@
+use std::{collections::BTreeMap, sync::RwLock};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use qdrant_client::Payload;
+use serde_json::{Map, Value};
+
+use crate::{
+    extractor::{Chunk, CHUNK_TYPE_KEY, SENDER_KEY, SENT_AT_KEY, TEXT_KEY},
+    metadata::{
+        DocMetadata, SearchFilter, CUSTOM_KEY, DOC_NAME_KEY, DOC_TYPE_KEY, TAGS_KEY,
+        UPLOADED_AT_KEY,
+    },
+    utils::cosine_similarity,
+    vector_db::{doc_payload, order_key, point_payload, sort_chunks, VectorStore, EMBEDDING_SIZE},
+};
+
+// MemoryStore keeps the collections in memory: used by the tests and to try out the server
+// without a qdrant instance (`db_url: "memory"`), the documents are lost on restart
+#[derive(Default)]
+pub struct MemoryStore {
+    collections: RwLock<BTreeMap<String, Vec<Point>>>,
+}
+
+struct Point {
+    id: String,
+    vector: Vec<f32>,
+    payload: Map<String, Value>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl VectorStore for MemoryStore {
+    async fn store_embeddings(
+        &self,
+        collection_name: &str,
+        doc_name: &str,
+        embeddings: Vec<(String, Vec<f32>, Chunk)>,
+        metadata: &DocMetadata,
+    ) -> Result<()> {
+        if let Some((_, vector, _)) = embeddings
+            .iter()
+            .find(|(_, vector, _)| vector.len() as u64 != EMBEDDING_SIZE)
+        {
+            return Err(anyhow!(
+                "wrong vector dimension: expected {}, got {}",
+                EMBEDDING_SIZE,
+                vector.len()
+            ));
+        }
+        let mut collections = self.collections.write().unwrap();
+        // a workspace collection is shared by many documents and created by the first of them
+        if collections.contains_key(collection_name) && collection_name == doc_name {
+            return Err(anyhow!("collection already exists"));
+        }
+        let points = collections.entry(collection_name.to_string()).or_default();
+        let doc_payload = doc_payload(doc_name, metadata);
+        for (index, (id, vector, chunk)) in embeddings.into_iter().enumerate() {
+            let payload = Map::from(Payload::from(point_payload(&doc_payload, index, chunk)));
+            points.retain(|point| point.id != id);
+            points.push(Point {
+                id,
+                vector,
+                payload,
+            });
+        }
+        Ok(())
+    }
+
+    async fn search_ranked(
+        &self,
+        collection_name: &str,
+        query: Vec<f32>,
+        filter: Option<&SearchFilter>,
+        limit: u64,
+    ) -> Result<Vec<Chunk>> {
+        let candidates = self
+            .search_candidates(collection_name, query, filter, limit)
+            .await?;
+        Ok(candidates.into_iter().map(|(chunk, _)| chunk).collect())
+    }
+
+    async fn search_candidates(
+        &self,
+        collection_name: &str,
+        query: Vec<f32>,
+        filter: Option<&SearchFilter>,
+        limit: u64,
+    ) -> Result<Vec<(Chunk, Vec<f32>)>> {
+        let collections = self.collections.read().unwrap();
+        let points = collections
+            .get(collection_name)
+            .ok_or(anyhow!("collection {} not found", collection_name))?;
+        let mut scored: Vec<(f32, &Point)> = points
+            .iter()
+            .filter(|point| filter.is_none_or(|filter| matches(&point.payload, filter)))
+            .map(|point| (cosine_similarity(&query, &point.vector), point))
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        Ok(scored
+            .into_iter()
+            .take(limit as usize)
+            .filter_map(|(_, point)| Some((to_chunk(&point.payload)?, point.vector.clone())))
+            .collect())
+    }
+
+    async fn document_chunks(&self, collection_name: &str, doc_name: &str) -> Result<Vec<Chunk>> {
+        let collections = self.collections.read().unwrap();
+        let Some(points) = collections.get(collection_name) else {
+            return Ok(vec![]);
+        };
+        let mut chunks: Vec<Chunk> = points
+            .iter()
+            .filter(|point| {
+                point.payload.get(DOC_NAME_KEY).and_then(|v| v.as_str()) == Some(doc_name)
+            })
+            .filter_map(|point| to_chunk(&point.payload))
+            .collect();
+        sort_chunks(&mut chunks);
+        Ok(chunks)
+    }
+
+    async fn document_vectors(
+        &self,
+        collection_name: &str,
+        doc_name: &str,
+    ) -> Result<Vec<(Chunk, Vec<f32>)>> {
+        let collections = self.collections.read().unwrap();
+        let Some(points) = collections.get(collection_name) else {
+            return Ok(vec![]);
+        };
+        let mut chunks: Vec<(Chunk, Vec<f32>)> = points
+            .iter()
+            .filter(|point| {
+                point.payload.get(DOC_NAME_KEY).and_then(|v| v.as_str()) == Some(doc_name)
+            })
+            .filter_map(|point| Some((to_chunk(&point.payload)?, point.vector.clone())))
+            .collect();
+        chunks.sort_by_key(|(chunk, _)| order_key(chunk));
+        Ok(chunks)
+    }
+
+    async fn chunks_at(
+        &self,
+        collection_name: &str,
+        doc_name: &str,
+        key: &str,
+        indexes: &[u64],
+    ) -> Result<Vec<Chunk>> {
+        let collections = self.collections.read().unwrap();
+        let Some(points) = collections.get(collection_name) else {
+            return Ok(vec![]);
+        };
+        let mut chunks: Vec<Chunk> = points
+            .iter()
+            .filter(|point| {
+                point.payload.get(DOC_NAME_KEY).and_then(|v| v.as_str()) == Some(doc_name)
+                    && point
+                        .payload
+                        .get(key)
+                        .and_then(|v| v.as_u64())
+                        .is_some_and(|index| indexes.contains(&index))
+            })
+            .filter_map(|point| to_chunk(&point.payload))
+            .collect();
+        sort_chunks(&mut chunks);
+        Ok(chunks)
+    }
+
+    async fn delete_document(&self, collection_name: &str, doc_name: &str) -> Result<()> {
+        let mut collections = self.collections.write().unwrap();
+        if collection_name == doc_name {
+            collections.remove(collection_name);
+        } else if let Some(points) = collections.get_mut(collection_name) {
+            points.retain(|point| {
+                point.payload.get(DOC_NAME_KEY).and_then(|v| v.as_str()) != Some(doc_name)
+            });
+        }
+        Ok(())
+    }
+
+    async fn list_collections(&self) -> Result<Vec<String>> {
+        Ok(self.collections.read().unwrap().keys().cloned().collect())
+    }
+}
+
+fn to_chunk(payload: &Map<String, Value>) -> Option<Chunk> {
+    let mut metadata = payload.clone();
+    let text = metadata.remove(TEXT_KEY)?.as_str()?.to_string();
+    Some(Chunk { text, metadata })
+}
+
+// matches applies the search filter the way the qdrant filter does
+fn matches(payload: &Map<String, Value>, filter: &SearchFilter) -> bool {
+    let text = |key: &str| payload.get(key).and_then(|v| v.as_str());
+    let number = |key: &str| payload.get(key).and_then(|v| v.as_i64());
+    let in_range = |value: Option<i64>, after: Option<i64>, before: Option<i64>| {
+        if after.is_none() && before.is_none() {
+            return true;
+        }
+        value.is_some_and(|value| {
+            after.is_none_or(|after| value >= after) && before.is_none_or(|before| value <= before)
+        })
+    };
+    if let Some(tags) = filter.tags.as_ref().filter(|t| !t.is_empty()) {
+        let doc_tags = payload.get(TAGS_KEY).and_then(|v| v.as_array());
+        let any = doc_tags.is_some_and(|doc_tags| {
+            doc_tags.iter().any(|tag| {
+                tag.as_str()
+                    .is_some_and(|tag| tags.iter().any(|t| t == tag))
+            })
+        });
+        if !any {
+            return false;
+        }
+    }
+    let custom = payload.get(CUSTOM_KEY).and_then(|v| v.as_object());
+    filter
+        .doc_type
+        .as_deref()
+        .is_none_or(|doc_type| text(DOC_TYPE_KEY) == Some(doc_type))
+        && filter
+            .chunk_type
+            .as_deref()
+            .is_none_or(|chunk_type| text(CHUNK_TYPE_KEY) == Some(chunk_type))
+        && in_range(
+            number(UPLOADED_AT_KEY),
+            filter.uploaded_after,
+            filter.uploaded_before,
+        )
+        && filter
+            .sender
+            .as_ref()
+            .is_none_or(|sender| text(SENDER_KEY) == Some(sender.to_lowercase().as_str()))
+        && in_range(number(SENT_AT_KEY), filter.sent_after, filter.sent_before)
+        && filter
+            .doc_name
+            .as_deref()
+            .is_none_or(|doc_name| text(DOC_NAME_KEY) == Some(doc_name))
+        && filter.metadata.iter().all(|(key, value)| {
+            custom
+                .and_then(|custom| custom.get(key))
+                .and_then(|v| v.as_str())
+                == Some(value.as_str())
+        })
+}
@@ -0,0 +1,103 @@
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+// payload keys shared by the ingestion and the retrieval side
+pub const DOC_NAME_KEY: &str = "doc_name";
+pub const DOC_TYPE_KEY: &str = "doc_type";
+pub const TAGS_KEY: &str = "tags";
+pub const UPLOADED_AT_KEY: &str = "uploaded_at";
+pub const CUSTOM_KEY: &str = "metadata";
+
+// DocMetadata is attached by the user at upload time
+// and saved along with every chunk of the document
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct DocMetadata {
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub doc_type: Option<String>,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    // unix timestamp (seconds) of the upload
+    #[serde(default)]
+    pub uploaded_at: i64,
+}
+
+impl DocMetadata {
+    // stamp the upload time and fall back to the file extension as doc type
+    pub fn for_file(&self, file_name: &str) -> Self {
+        let mut metadata = self.clone();
+        if metadata.doc_type.is_none() {
+            metadata.doc_type = std::path::Path::new(file_name)
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .map(|ext| ext.to_lowercase());
+        }
+        metadata.uploaded_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+        metadata
+    }
+
+    // parse the comma separated tags sent along with the upload form
+    pub fn set_tags(&mut self, tags: &str) {
+        self.tags = tags
+            .split(',')
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+    }
+}
+
+// SearchFilter is the backend agnostic filter expression sent with the prompt.
+// Every vector backend translates it into its own filter type.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SearchFilter {
+    // match chunks having any of the tags
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub doc_type: Option<String>,
+    // restrict the context to the `text` or the `table` chunks
+    #[serde(default)]
+    pub chunk_type: Option<String>,
+    // unix timestamps (seconds), both bounds inclusive
+    #[serde(default)]
+    pub uploaded_after: Option<i64>,
+    #[serde(default)]
+    pub uploaded_before: Option<i64>,
+    // the email messages sent by the address
+    #[serde(default)]
+    pub sender: Option<String>,
+    // unix timestamps (seconds) of the email date, both bounds inclusive
+    #[serde(default)]
+    pub sent_after: Option<i64>,
+    #[serde(default)]
+    pub sent_before: Option<i64>,
+    // exact match on the custom key/value metadata
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    // restrict the context to a document of the workspace
+    #[serde(default)]
+    pub doc_name: Option<String>,
+}
+
+impl SearchFilter {
+    pub fn is_empty(&self) -> bool {
+        self.tags.as_ref().map(|t| t.is_empty()).unwrap_or(true)
+            && self.doc_type.is_none()
+            && self.chunk_type.is_none()
+            && self.uploaded_after.is_none()
+            && self.uploaded_before.is_none()
+            && self.sender.is_none()
+            && self.sent_after.is_none()
+            && self.sent_before.is_none()
+            && self.metadata.is_empty()
+            && self.doc_name.is_none()
+    }
+}
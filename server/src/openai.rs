@@ -0,0 +1,189 @@
+use std::{
+    collections::BTreeSet,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{catalog::Catalog, extractor, metadata::SearchFilter};
+
+// owner of the models listed, every model being a document or a workspace
+const OWNER: &str = "documind";
+// previous messages of the conversation given along with the question at most
+const HISTORY_MESSAGES: usize = 6;
+
+// ChatRequest is the OpenAI chat completion request, the model naming the document (or the workspace) asked.
+// The other OpenAI parameters (temperature...) are accepted and left out.
+#[derive(Deserialize)]
+pub struct ChatRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    #[serde(default)]
+    pub stream: bool,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ChatMessage {
+    pub role: String,
+    // a text or an array of parts (only the text parts are read)
+    #[serde(default)]
+    pub content: Value,
+}
+
+impl ChatMessage {
+    fn text(&self) -> String {
+        match &self.content {
+            Value::String(text) => text.to_string(),
+            Value::Array(parts) => parts
+                .iter()
+                .filter_map(|part| part["text"].as_str())
+                .collect::<Vec<&str>>()
+                .join("\n"),
+            _ => String::new(),
+        }
+    }
+}
+
+// Model is a document or a workspace as listed by `/v1/models`
+#[derive(Serialize, Debug)]
+pub struct Model {
+    pub id: String,
+    pub object: &'static str,
+    pub created: i64,
+    pub owned_by: &'static str,
+}
+
+// models lists the documents & the workspaces of the catalog
+pub fn models(catalog: &Catalog) -> Vec<Model> {
+    let entries = catalog.list();
+    let mut seen = BTreeSet::new();
+    let mut models = vec![];
+    for entry in entries.iter() {
+        // the workspace is listed once along with its documents
+        for (id, created) in [
+            (&entry.collection, entry.ingested_at),
+            (&entry.name, entry.ingested_at),
+        ] {
+            if seen.insert(id.to_string()) {
+                models.push(Model {
+                    id: id.to_string(),
+                    object: "model",
+                    created,
+                    owned_by: OWNER,
+                });
+            }
+        }
+    }
+    models
+}
+
+// resolve_model gives the collection searched for the model along with the filter on the document
+// when the document belongs to a workspace (the file name of the document being accepted too)
+pub fn resolve_model(catalog: &Catalog, model: &str) -> Option<(String, Option<SearchFilter>)> {
+    let entry = catalog
+        .get(model)
+        .or_else(|| catalog.get(extractor::doc_name(model)));
+    if let Some(entry) = entry {
+        let filter = (entry.collection != entry.name).then(|| SearchFilter {
+            doc_name: Some(entry.name.to_string()),
+            ..Default::default()
+        });
+        return Some((entry.collection, filter));
+    }
+    catalog
+        .list()
+        .into_iter()
+        .find(|entry| entry.collection == model)
+        .map(|entry| (entry.collection, None))
+}
+
+// question is the last user message, searched for in the document.
+// The previous messages are given along with it so that the follow-up questions can be answered.
+pub fn question(messages: &[ChatMessage]) -> Option<(String, String)> {
+    let last = messages
+        .iter()
+        .rposition(|message| message.role == "user")?;
+    let query = messages[last].text();
+    if query.trim().is_empty() {
+        return None;
+    }
+    let mut history: Vec<String> = messages[..last]
+        .iter()
+        .filter(|message| message.role == "user" || message.role == "assistant")
+        .map(|message| format!("{}: {}", message.role, message.text()))
+        .collect();
+    history.drain(..history.len().saturating_sub(HISTORY_MESSAGES));
+    if history.is_empty() {
+        return Some((query.to_string(), query));
+    }
+    let with_history = format!(
+        "{} (the conversation so far: {})",
+        query,
+        history.join(" / ")
+    );
+    Some((query, with_history))
+}
+
+// completion is the (non streamed) chat completion answering the question
+pub fn completion(
+    id: &str,
+    created: i64,
+    model: &str,
+    answer: &str,
+    citations: &[String],
+) -> Value {
+    json!({
+        "id": id,
+        "object": "chat.completion",
+        "created": created,
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": answer },
+            "finish_reason": "stop"
+        }],
+        "citations": citations
+    })
+}
+
+// completion_chunk is a piece of the streamed chat completion,
+// the last one having an empty delta & the finish reason
+pub fn completion_chunk(
+    id: &str,
+    created: i64,
+    model: &str,
+    delta: Value,
+    finish_reason: Option<&str>,
+) -> Value {
+    json!({
+        "id": id,
+        "object": "chat.completion.chunk",
+        "created": created,
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "delta": delta,
+            "finish_reason": finish_reason
+        }]
+    })
+}
+
+// error is the body of the OpenAI errors
+pub fn error(message: &str, kind: &str, code: &str) -> Value {
+    json!({
+        "error": {
+            "message": message,
+            "type": kind,
+            "code": code
+        }
+    })
+}
+
+// unix timestamp (seconds) of the completion
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
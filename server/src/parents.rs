@@ -0,0 +1,97 @@
+use std::collections::HashSet;
+
+use serde_json::Value;
+
+use crate::{
+    extractor::{Chunk, CHUNK_INDEX_KEY, PARENT_INDEX_KEY, PARENT_TEXT_KEY},
+    metadata::DOC_NAME_KEY,
+};
+
+// neighbor chunks added on each side of a retrieved chunk at most
+pub const MAX_NEIGHBORS: usize = 3;
+
+// split_children splits every (parent) chunk into the small child chunks embedded in its place,
+// the children keep the metadata of their parent along with its text & its index
+pub fn split_children(parents: Vec<Chunk>, child_size: usize) -> Vec<Chunk> {
+    let mut children = vec![];
+    for (index, parent) in parents.into_iter().enumerate() {
+        let texts = split_words(&parent.text, child_size);
+        // the parent small enough is embedded as is
+        if texts.len() < 2 {
+            let mut chunk = parent;
+            chunk
+                .metadata
+                .insert(PARENT_INDEX_KEY.to_string(), index.into());
+            children.push(chunk);
+            continue;
+        }
+        for text in texts {
+            let mut metadata = parent.metadata.clone();
+            metadata.insert(PARENT_INDEX_KEY.to_string(), index.into());
+            metadata.insert(PARENT_TEXT_KEY.to_string(), parent.text.to_string().into());
+            children.push(Chunk { text, metadata });
+        }
+    }
+    children
+}
+
+// to_parents replaces the child chunks by their parent, the parents being kept once
+pub fn to_parents(chunks: Vec<Chunk>) -> Vec<Chunk> {
+    let mut seen = HashSet::new();
+    let mut parents = vec![];
+    for mut chunk in chunks {
+        if let Some(Value::String(text)) = chunk.metadata.remove(PARENT_TEXT_KEY) {
+            chunk.text = text;
+        }
+        let key = unit_index(&chunk)
+            .map(|index| (chunk_doc_name(&chunk), index.to_string()))
+            .unwrap_or((chunk_doc_name(&chunk), chunk.text.to_string()));
+        if seen.insert(key) {
+            parents.push(chunk);
+        }
+    }
+    parents
+}
+
+// unit_key is the payload key of the index of the chunks fed to the prompt:
+// the parent index of the child chunks, else the chunk index
+pub fn unit_key(chunk: &Chunk) -> &'static str {
+    if chunk.metadata.contains_key(PARENT_INDEX_KEY) {
+        PARENT_INDEX_KEY
+    } else {
+        CHUNK_INDEX_KEY
+    }
+}
+
+pub fn unit_index(chunk: &Chunk) -> Option<u64> {
+    chunk.metadata.get(unit_key(chunk)).and_then(|v| v.as_u64())
+}
+
+pub fn chunk_doc_name(chunk: &Chunk) -> String {
+    chunk
+        .metadata
+        .get(DOC_NAME_KEY)
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
+// split_words splits the text on the word boundaries into parts of the size at most
+// (a longer word making a part on its own)
+fn split_words(text: &str, size: usize) -> Vec<String> {
+    let mut parts: Vec<String> = vec![];
+    let mut current = String::new();
+    for word in text.split_whitespace() {
+        if !current.is_empty() && current.chars().count() + word.chars().count() + 1 > size {
+            parts.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    if !current.is_empty() {
+        parts.push(current);
+    }
+    parts
+}
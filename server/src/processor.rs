@@ -1,138 +1,543 @@
+use std::{fmt, sync::Arc};
+
+use serde::Serialize;
 use tokio::task;
-use tracing::debug;
+use tracing::{debug, info, warn};
 use uuid::Uuid;
 
 use crate::{
-    utils::{
-        chunk_text, extract_file_content, get_content_embeddings, send_request, ConfigVar,
-        ModelKind,
-    },
-    vector_db::VectorStore,
+    catalog::{Catalog, DocumentEntry},
+    extractor::{self, doc_name, extract_chunks, extract_directory, Chunk, Extraction},
+    grounding::{verify, GroundedAnswer},
+    metadata::{DocMetadata, SearchFilter},
+    parents::{chunk_doc_name, split_children, to_parents, unit_index, unit_key, MAX_NEIGHBORS},
+    query::{fuse, transform_query, RetrievalOptions},
+    structured::{field_queries, fill, StructuredData},
+    summarizer::{suggest_questions, summarize, SummaryOptions},
+    utils::{generate_text, get_content_embeddings, ConfigVar},
+    vector_db::{VectorStore, CONTEXT_LIMIT},
 };
 
 use anyhow::{anyhow, Context, Ok, Result};
 
+// chunks retrieved per field of the extraction schema
+const FIELD_CHUNKS: usize = 3;
+
 pub struct Processor {
     pub settings: ConfigVar,
-    pub vec_store: VectorStore,
+    pub vec_store: Box<dyn VectorStore>,
+    pub catalog: Arc<Catalog>,
+}
+
+// IngestionReport summarizes how the document was read and stored
+#[derive(Serialize, Debug)]
+pub struct IngestionReport {
+    pub file_name: String,
+    pub chunks: usize,
+    pub total_pages: usize,
+    // source files read from the directory
+    pub total_files: usize,
+    // no page of the document had a text layer
+    pub scanned: bool,
+    // pages read via OCR along with the mean OCR confidence (0-100)
+    pub ocr_pages: Vec<(u32, f32)>,
+    pub warnings: Vec<String>,
 }
 
+// DocumentNotFound reports a name matching no document nor workspace of the catalog
+#[derive(Debug)]
+pub struct DocumentNotFound(pub String);
+
+impl fmt::Display for DocumentNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "document not found: {}", self.0)
+    }
+}
+
+impl std::error::Error for DocumentNotFound {}
+
 impl Processor {
-    pub fn new(settings: ConfigVar, vec_store: VectorStore) -> Self {
+    pub fn new(settings: ConfigVar, vec_store: Box<dyn VectorStore>, catalog: Catalog) -> Self {
         Self {
             settings,
             vec_store,
+            catalog: Arc::new(catalog),
         }
     }
 
     // process_file splits the text into chunks so to generate the embeddings
-    // for proper context length and saves them to the db
-    pub async fn process_file(&self, file_name: &str) -> Result<()> {
-        let chunks = self.process_chunks(file_name)?;
-        let embeddings = self.process_embeddings(chunks.to_owned()).await.unwrap();
-        let coll_name = file_name.split_once(".pdf").unwrap().0;
-        self.save_embeddings(coll_name, embeddings.to_owned())
-            .await?;
-        Ok(())
+    // for proper context length and saves them to the db along with the document metadata.
+    // The password decrypts the encrypted pdf and is never stored.
+    // The document is saved into its own collection unless a workspace is given.
+    pub async fn process_file(
+        &self,
+        file_name: &str,
+        metadata: &DocMetadata,
+        password: Option<&str>,
+        workspace: Option<&str>,
+    ) -> Result<IngestionReport> {
+        let extraction = self.process_chunks(file_name, password)?;
+        let doc_name = doc_name(file_name);
+        self.ingest(
+            file_name,
+            doc_name,
+            workspace.unwrap_or(doc_name),
+            extraction,
+            metadata,
+        )
+        .await
     }
 
-    // process_prompt gets the similar cosine embeddings for the user prompt
-    // and sets the context for LLM to get the result generated as per the context
-    pub async fn process_prompt(&self, user_query: &str, doc_name: &str) -> Result<String> {
-        // split user query into chunks
+    // process_directory chunks the source code of the local directory along its definitions
+    // and saves it as a single document named after the directory (unless a name is given)
+    pub async fn process_directory(
+        &self,
+        dir: &str,
+        name: Option<&str>,
+        metadata: &DocMetadata,
+    ) -> Result<IngestionReport> {
         let chunk_size = self
             .settings
             .embedding_model_chunk_size
             .as_ref()
             .expect("required chunk size");
-        let chunks = chunk_text(&user_query, *chunk_size);
-        let embeddings = self
-            .process_embeddings(chunks.to_owned())
+        let extraction =
+            extract_directory(dir, *chunk_size).context("failed to read the source directory")?;
+        let coll_name = match name {
+            Some(name) => name.to_string(),
+            None => std::path::Path::new(dir)
+                .canonicalize()
+                .ok()
+                .and_then(|path| path.file_name().map(|n| n.to_string_lossy().to_string()))
+                .ok_or(anyhow!("unable to name the directory {}", dir))?,
+        };
+        self.ingest(dir, &coll_name, &coll_name, extraction, metadata)
             .await
-            .context("unable to process the embeddings")?;
+    }
 
-        // get all the payloads similar to prompt embedding
-        let mut all_payloads = vec![];
-        if let Some(split_name) = doc_name.split_once(".pdf") {
-            let coll_name = split_name.0;
-            for embedding in embeddings {
-                let payloads = self
-                    .vec_store
-                    .search_result(coll_name, embedding.1)
-                    .await
-                    .with_context(|| format!("unable to fetch the result for {}", coll_name))?;
-                debug!("Payloads:: {:?}", payloads);
-                all_payloads.extend(payloads);
+    // ingest reports how the document was read, generates the embeddings of its chunks
+    // and saves them into the collection
+    async fn ingest(
+        &self,
+        file_name: &str,
+        doc_name: &str,
+        coll_name: &str,
+        extraction: Extraction,
+        metadata: &DocMetadata,
+    ) -> Result<IngestionReport> {
+        let mut report = IngestionReport {
+            file_name: file_name.to_string(),
+            chunks: extraction.chunks.len(),
+            total_pages: extraction.total_pages,
+            total_files: extraction.total_files,
+            scanned: extraction.is_scanned(),
+            ocr_pages: extraction.ocr_pages.clone().into_iter().collect(),
+            warnings: extraction.warnings.clone(),
+        };
+        if report.scanned {
+            report
+                .warnings
+                .push("scanned document: no page has a text layer".to_string());
+        }
+        let unread_pages: Vec<String> = extraction
+            .empty_pages
+            .iter()
+            .filter(|page| !extraction.ocr_pages.contains_key(page))
+            .map(|page| page.to_string())
+            .collect();
+        if !unread_pages.is_empty() {
+            report.warnings.push(format!(
+                "no text extracted from pages {} (OCR needs the server built with the `ocr` feature)",
+                unread_pages.join(", ")
+            ));
+        }
+        for warning in report.warnings.iter() {
+            warn!("{}: {}", file_name, warning);
+        }
+        if extraction.chunks.is_empty() {
+            return Err(anyhow!(
+                "no text could be extracted from {}: {}",
+                file_name,
+                report.warnings.join("; ")
+            ));
+        }
+
+        let chunks = extraction.chunks.clone();
+        // the small child chunks are embedded in place of the chunks when configured
+        let embedded = match self.settings.child_chunk_size {
+            Some(child_size) => split_children(extraction.chunks, child_size),
+            None => extraction.chunks,
+        };
+        let embeddings = self.process_embeddings(embedded).await.unwrap();
+        self.save_embeddings(coll_name, doc_name, embeddings.to_owned(), metadata)
+            .await?;
+        let insights = self.settings.generate_insights.unwrap_or(true);
+        let entry = DocumentEntry {
+            name: doc_name.to_string(),
+            collection: coll_name.to_string(),
+            file_name: file_name.to_string(),
+            chunks: report.chunks,
+            ingested_at: metadata.uploaded_at,
+            insights: insights.then(|| "pending".to_string()),
+            ..Default::default()
+        };
+        if let Err(e) = self.catalog.upsert(entry) {
+            warn!("{}: {:#}", file_name, e);
+        } else if insights {
+            self.spawn_insights(doc_name, metadata.uploaded_at, chunks);
+        }
+        Ok(report)
+    }
+
+    // spawn_insights generates the summary & the suggested questions of the ingested document
+    // in the background and saves them into its catalog entry
+    fn spawn_insights(&self, doc_name: &str, ingested_at: i64, chunks: Vec<Chunk>) {
+        let settings = self.settings.clone();
+        let catalog = self.catalog.clone();
+        let doc_name = doc_name.to_string();
+        task::spawn(async move {
+            let result = async {
+                let summary =
+                    summarize(&settings, &chunks, &SummaryOptions::default(), |_| {}).await?;
+                let questions = suggest_questions(&settings, &summary).await?;
+                Ok((summary, questions))
+            }
+            .await;
+            let result = catalog.update(&doc_name, |entry| {
+                // the document was re-ingested meanwhile
+                if entry.ingested_at != ingested_at || entry.insights.as_deref() != Some("pending")
+                {
+                    return;
+                }
+                match result {
+                    Err(e) => {
+                        entry.insights = Some("failed".to_string());
+                        warn!("{}: unable to summarize the document: {:#}", doc_name, e);
+                    }
+                    std::result::Result::Ok((summary, questions)) => {
+                        entry.insights = Some("ready".to_string());
+                        entry.summary = Some(summary);
+                        entry.questions = questions;
+                        info!("{}: summary generated", doc_name);
+                    }
+                }
+            });
+            if let Err(e) = result {
+                warn!("{}: {:#}", doc_name, e);
+            }
+        });
+    }
+
+    // remove_document deletes the chunks of the document and its catalog entry
+    pub async fn remove_document(&self, doc_name: &str) -> Result<()> {
+        let collection = self.collection_of(doc_name);
+        self.vec_store
+            .delete_document(collection.as_str(), doc_name)
+            .await
+            .with_context(|| format!("unable to delete the document {}", doc_name))?;
+        self.catalog.remove(doc_name)?;
+        Ok(())
+    }
+
+    // collection_of is the collection holding the chunks of the document
+    fn collection_of(&self, doc_name: &str) -> String {
+        self.catalog
+            .get(doc_name)
+            .map(|entry| entry.collection)
+            .unwrap_or(doc_name.to_string())
+    }
+
+    // resolve_document is the catalog name of the document (or the name of the workspace)
+    // given as is or as a file name, the source directories having no extension
+    pub fn resolve_document(&self, name: &str) -> Result<String> {
+        let entries = self.catalog.list();
+        for name in [name, extractor::doc_name(name)] {
+            if entries
+                .iter()
+                .any(|entry| entry.name == name || entry.collection == name)
+            {
+                return Ok(name.to_string());
             }
-        } else {
-            debug!("error handling fileName ...");
-            return Err(anyhow!("bad request - doc type is incorrect..."));
         }
+        Err(DocumentNotFound(name.to_string()).into())
+    }
 
-        // set the LLM context
-        let context = all_payloads.join(",");
+    // document_scope is the collection of the document along with the filter restricted to the document
+    // when it shares the collection (e.g. a workspace)
+    pub fn document_scope(
+        &self,
+        doc_name: &str,
+        filter: Option<&SearchFilter>,
+    ) -> (String, SearchFilter) {
+        let collection = self.collection_of(doc_name);
+        let mut filter = filter.cloned().unwrap_or_default();
+        if collection != doc_name {
+            filter.doc_name = Some(doc_name.to_string());
+        }
+        (collection, filter)
+    }
 
-        let (generate_model_url, generate_model_name) = self
-            .settings
-            .get_model_details(ModelKind::Generate)
-            .context("unable to fetch the model details")?;
-
-        // final prompt to the LLM
-        let prompt = format!(
-            "You are an expert providing factually accurate answers.
-            Use only the information from the context to generate your answer.
-            If the context doesn't contain relevant information say I don't know as context doesn't have much info.
-            Context: {context} Question: {user_query} Answer(only use the context for your answer)"
+    // search_document returns the chunks of the document most similar to the query
+    pub async fn search_document(
+        &self,
+        doc_name: &str,
+        query: &str,
+        filter: Option<&SearchFilter>,
+        limit: usize,
+    ) -> Result<Vec<Chunk>> {
+        let (collection, filter) = self.document_scope(doc_name, filter);
+        let embedding = get_content_embeddings(self.settings.clone(), query)
+            .await
+            .context("unable to process the embeddings")?;
+        // the context is diversified as configured
+        let search = match self.settings.mmr_lambda {
+            Some(lambda) => {
+                self.vec_store
+                    .search_diverse(&collection, embedding, Some(&filter), limit as u64, lambda)
+                    .await
+            }
+            None => {
+                self.vec_store
+                    .search_ranked(&collection, embedding, Some(&filter), limit as u64)
+                    .await
+            }
+        };
+        search
+            .map(to_parents)
+            .with_context(|| format!("unable to fetch the result for {}", doc_name))
+    }
+
+    // extract_structured fills the json schema from the chunks of the document
+    // retrieved for every field of the schema
+    pub async fn extract_structured(
+        &self,
+        doc_name: &str,
+        schema: &serde_json::Value,
+        filter: Option<&SearchFilter>,
+    ) -> Result<StructuredData> {
+        let queries = field_queries(schema)?;
+        let mut chunks: Vec<Chunk> = vec![];
+        for query in queries.iter() {
+            let found = self
+                .search_document(doc_name, query, filter, FIELD_CHUNKS)
+                .await?;
+            for chunk in found {
+                if !chunks.iter().any(|c| c.text == chunk.text) {
+                    chunks.push(chunk);
+                }
+            }
+        }
+        if chunks.is_empty() {
+            return Err(anyhow!(
+                "no content of {} matches the schema fields",
+                doc_name
+            ));
+        }
+        debug!(
+            "{} chunks retrieved for {} fields",
+            chunks.len(),
+            queries.len()
         );
-        let res = send_request(
-            generate_model_url.as_str(),
-            generate_model_name.as_str(),
-            prompt.as_str(),
-        )
-        .await
-        .context("send request to LLM operation failed")?;
-        let res_json: serde_json::Value = serde_json::from_str(res.as_str())
-            .context("parsing response into value type failed")?;
-        let response: String = serde_json::from_value(res_json["response"].clone())
-            .context("parsing string from value type failed")?;
-        Ok(response)
+        fill(&self.settings, schema, &chunks).await
+    }
+
+    // document_chunks reads all the chunks of the document in their order within the document
+    pub async fn document_chunks(&self, doc_name: &str) -> Result<Vec<Chunk>> {
+        let collection = self.collection_of(doc_name);
+        self.vec_store
+            .document_chunks(collection.as_str(), doc_name)
+            .await
+            .map(to_parents)
+            .with_context(|| format!("unable to read the chunks of {}", doc_name))
+    }
+
+    // process_prompt gets the similar cosine embeddings for the user prompt
+    // and sets the context for LLM to get the result generated as per the context.
+    // The optional filter restricts the context to the matching chunks.
+    pub async fn process_prompt(
+        &self,
+        user_query: &str,
+        doc_name: &str,
+        filter: Option<&SearchFilter>,
+        options: &RetrievalOptions,
+    ) -> Result<String> {
+        let (answer, _) = self
+            .answer_with_sources(user_query, doc_name, filter, options)
+            .await?;
+        Ok(answer)
+    }
+
+    // expand_neighbors adds the chunks preceding & following every chunk within its document,
+    // every chunk along with its neighbors in the document order
+    async fn expand_neighbors(
+        &self,
+        coll_name: &str,
+        chunks: Vec<Chunk>,
+        neighbors: usize,
+    ) -> Result<Vec<Chunk>> {
+        let neighbors = neighbors.min(MAX_NEIGHBORS) as u64;
+        let mut expanded = vec![];
+        for chunk in chunks {
+            let Some(index) = unit_index(&chunk) else {
+                expanded.push(chunk);
+                continue;
+            };
+            let indexes: Vec<u64> = (index.saturating_sub(neighbors)..=index + neighbors)
+                .filter(|i| *i != index)
+                .collect();
+            let around = self
+                .vec_store
+                .chunks_at(
+                    coll_name,
+                    &chunk_doc_name(&chunk),
+                    unit_key(&chunk),
+                    &indexes,
+                )
+                .await?;
+            let mut window = to_parents(around);
+            window.push(chunk);
+            window.sort_by_key(unit_index);
+            expanded.extend(window);
+        }
+        Ok(to_parents(expanded))
     }
 
-    // process_chunks splits the large text into chunks
-    pub fn process_chunks(&self, file_name: &str) -> Result<Vec<String>> {
+    // answer_verified answers the prompt and checks every sentence of the answer against the context
+    pub async fn answer_verified(
+        &self,
+        user_query: &str,
+        doc_name: &str,
+        filter: Option<&SearchFilter>,
+        options: &RetrievalOptions,
+    ) -> Result<GroundedAnswer> {
+        let (answer, sources) = self
+            .answer_with_sources(user_query, doc_name, filter, options)
+            .await?;
+        let grounding = verify(&self.settings, &answer, &sources)
+            .await
+            .context("unable to verify the answer")?;
+        Ok(GroundedAnswer { answer, grounding })
+    }
+
+    // answer_with_sources answers the prompt like process_prompt
+    // along with the chunks given to the LLM as the context
+    pub async fn answer_with_sources(
+        &self,
+        user_query: &str,
+        doc_name: &str,
+        filter: Option<&SearchFilter>,
+        options: &RetrievalOptions,
+    ) -> Result<(String, Vec<Chunk>)> {
+        let doc_name = self.resolve_document(doc_name)?;
+        let (coll_name, filter) = self.document_scope(&doc_name, filter);
+        let sources = self
+            .retrieve_context(&coll_name, user_query, Some(&filter), options)
+            .await?;
+        let prompt = answer_prompt(user_query, &sources);
+        let answer = generate_text(&self.settings, &prompt).await?;
+        Ok((answer, sources))
+    }
+
+    // retrieve_context gets the chunks of the collection given to the LLM as the context of the query
+    pub async fn retrieve_context(
+        &self,
+        coll_name: &str,
+        user_query: &str,
+        filter: Option<&SearchFilter>,
+        options: &RetrievalOptions,
+    ) -> Result<Vec<Chunk>> {
+        // the query searched for as per the strategy of the request (else the configured one)
+        let strategy = options
+            .strategy
+            .or(self.settings.query_strategy)
+            .unwrap_or_default();
+        let queries = transform_query(&self.settings, strategy, user_query)
+            .await
+            .context("unable to transform the query")?;
+        debug!("Search queries ({:?}):: {:?}", strategy, queries);
+
+        // get all the payloads similar to the embedding of every query
+        let mmr_lambda = options.mmr_lambda.or(self.settings.mmr_lambda);
+        let mut results = vec![];
+        for query in queries.iter() {
+            let embedding = get_content_embeddings(self.settings.clone(), query)
+                .await
+                .context("unable to process the embeddings")?;
+            let payloads = self
+                .vec_store
+                .search_result(coll_name, embedding, filter, mmr_lambda)
+                .await
+                .with_context(|| format!("unable to fetch the result for {}", coll_name))?;
+            debug!("Payloads:: {:?}", payloads);
+            results.push(payloads);
+        }
+        // the results of the query variants are fused
+        let all_payloads = if results.len() > 1 {
+            fuse(results, CONTEXT_LIMIT as usize)
+        } else {
+            results.pop().unwrap_or_default()
+        };
+        // the child chunks matched are replaced by their parent, along with the chunks around if asked
+        let mut all_payloads = to_parents(all_payloads);
+        let neighbors = options
+            .neighbors
+            .or(self.settings.context_neighbors)
+            .unwrap_or_default();
+        if neighbors > 0 {
+            all_payloads = self
+                .expand_neighbors(coll_name, all_payloads, neighbors)
+                .await
+                .context("unable to read the neighbor chunks")?;
+        }
+        Ok(all_payloads)
+    }
+
+    // search_tool is the search of the agent: the chunks of the document most similar to the query
+    // (diversified as configured), the child chunks being replaced by their parent
+    pub async fn search_tool(
+        &self,
+        doc_name: &str,
+        query: &str,
+        filter: Option<&SearchFilter>,
+    ) -> Result<Vec<Chunk>> {
+        let doc_name = self.resolve_document(doc_name)?;
+        self.search_document(&doc_name, query, filter, CONTEXT_LIMIT as usize)
+            .await
+    }
+
+    // process_chunks splits the large text into chunks along with their page ranges
+    pub fn process_chunks(&self, file_name: &str, password: Option<&str>) -> Result<Extraction> {
         let chunk_size = self
             .settings
             .embedding_model_chunk_size
             .as_ref()
             .expect("required chunk size");
-        let content =
-            extract_file_content(file_name).context("failed to extract the file content")?;
-        let chunks = chunk_text(&content, *chunk_size);
-        Ok(chunks)
+        let extraction = extract_chunks(file_name, *chunk_size, password)
+            .context("failed to extract the file content")?;
+        Ok(extraction)
     }
 
     // process_embedding generates the embeddings for different chunk texts parallely
     pub async fn process_embeddings(
         &self,
-        chunks: Vec<String>,
-    ) -> Result<Vec<(String, Vec<f32>, String)>> {
+        chunks: Vec<Chunk>,
+    ) -> Result<Vec<(String, Vec<f32>, Chunk)>> {
         let mut tasks = vec![];
-        for (_i, chunk) in chunks.into_iter().enumerate() {
+        for chunk in chunks.into_iter() {
             let settings = self.settings.clone();
             tasks.push(task::spawn(async move {
-                let chunk_clone = chunk.clone();
-                let embedding = get_content_embeddings(settings, chunk_clone.as_str())
+                let embedding = get_content_embeddings(settings, chunk.text.as_str())
                     .await
                     .unwrap_or_else(|e| {
                         debug!("Error: {}", e);
                         vec![]
                     });
-                (Uuid::new_v4().to_string(), embedding.clone(), chunk_clone)
+                (Uuid::new_v4().to_string(), embedding, chunk)
             }))
         }
 
         let results = futures::future::join_all(tasks).await;
-        let embeddings: Vec<(String, Vec<f32>, String)> =
+        let embeddings: Vec<(String, Vec<f32>, Chunk)> =
             results.into_iter().map(|res| res.unwrap()).collect();
         Ok(embeddings)
     }
@@ -141,11 +546,36 @@ impl Processor {
     pub async fn save_embeddings(
         &self,
         coll_name: &str,
-        embeddings: Vec<(String, Vec<f32>, String)>,
+        doc_name: &str,
+        embeddings: Vec<(String, Vec<f32>, Chunk)>,
+        metadata: &DocMetadata,
     ) -> Result<()> {
         self.vec_store
-            .store_embeddings(coll_name, embeddings)
+            .store_embeddings(coll_name, doc_name, embeddings, metadata)
             .await?;
         Ok(())
     }
 }
+
+// answer_prompt is the final prompt to the LLM answering the query from its context
+pub fn answer_prompt(user_query: &str, sources: &[Chunk]) -> String {
+    // set the LLM context, every chunk is labelled with its pages for the citations
+    let context = sources
+        .iter()
+        .map(|chunk| match chunk.citation() {
+            Some(citation) => format!("[{}] {}", citation, chunk.text),
+            None => chunk.text.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join(",");
+
+    // final prompt to the LLM
+    format!(
+        "You are an expert providing factually accurate answers.
+        Use only the information from the context to generate your answer.
+        If the context doesn't contain relevant information say I don't know as context doesn't have much info.
+        Cite the labels given in the context (e.g. page 12, sheet \"Sales\", rows 2-40, src/main.rs:10-42) for the facts you use.
+        For the markdown tables read every value under the column named in the header row.
+        Context: {context} Question: {user_query} Answer(only use the context for your answer)"
+    )
+}
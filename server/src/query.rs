@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    extractor::Chunk,
+    metadata::DOC_NAME_KEY,
+    utils::{generate_text, ConfigVar},
+};
+
+// alternative queries generated by the multi-query expansion
+const QUERY_VARIANTS: usize = 3;
+// rank constant of the reciprocal rank fusion, damping the weight of the top ranks
+const RRF_K: f64 = 60.0;
+
+// QueryStrategy is the transformation of the user query before the retrieval,
+// the answer is still generated for the user query as asked
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryStrategy {
+    // the user query as is
+    #[default]
+    Raw,
+    // the query rewritten by the model into a precise search query
+    Rewrite,
+    // the query along with its variants, the results being fused
+    MultiQuery,
+    // a hypothetical answer generated by the model (HyDE) rather than the query
+    Hyde,
+}
+
+// RetrievalOptions tune the retrieval of the context per request, the configured ones by default
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+pub struct RetrievalOptions {
+    // transformation of the query before the retrieval
+    #[serde(default)]
+    pub strategy: Option<QueryStrategy>,
+    // diversity of the context by maximal marginal relevance,
+    // from 0 (the most diverse) to 1 (the most similar only)
+    #[serde(default)]
+    pub mmr_lambda: Option<f32>,
+    // chunks added on each side of every retrieved chunk (up to 3)
+    #[serde(default)]
+    pub neighbors: Option<usize>,
+}
+
+// transform_query gives the texts to be searched for the user query
+pub async fn transform_query(
+    settings: &ConfigVar,
+    strategy: QueryStrategy,
+    query: &str,
+) -> Result<Vec<String>> {
+    match strategy {
+        QueryStrategy::Raw => Ok(vec![query.to_string()]),
+        QueryStrategy::Rewrite => {
+            let prompt = format!(
+                "Rewrite the question below into a precise standalone query for a document search engine.
+                Spell out the abbreviations and add the key terms the answer is likely to contain, keep the meaning of the question.
+                Answer with the query only.
+                Question: {query} Query:"
+            );
+            let rewritten = generate_text(settings, &prompt).await?;
+            let rewritten = rewritten.trim().trim_matches('"').trim();
+            if rewritten.is_empty() {
+                return Ok(vec![query.to_string()]);
+            }
+            Ok(vec![rewritten.to_string()])
+        }
+        QueryStrategy::MultiQuery => {
+            let prompt = format!(
+                "Write {QUERY_VARIANTS} different search queries for a document search engine to find the answer to the question below.
+                Phrase them differently and cover the different aspects of the question.
+                Write one query per line without numbering or any other text.
+                Question: {query} Queries:"
+            );
+            let response = generate_text(settings, &prompt).await?;
+            let mut queries = vec![query.to_string()];
+            for line in response.lines() {
+                let variant = line
+                    .trim()
+                    .trim_start_matches(|c: char| {
+                        c.is_ascii_digit() || matches!(c, '.' | ')' | '-' | '*' | '•' | ' ')
+                    })
+                    .trim()
+                    .trim_matches('"');
+                if !variant.is_empty() && !queries.iter().any(|q| q.eq_ignore_ascii_case(variant)) {
+                    queries.push(variant.to_string());
+                }
+            }
+            queries.truncate(QUERY_VARIANTS + 1);
+            Ok(queries)
+        }
+        QueryStrategy::Hyde => {
+            let prompt = format!(
+                "Write a short passage (one paragraph) answering the question below, as it could be written in a document.
+                Make up plausible details if you don't know them, the passage is only used to search for similar text.
+                Question: {query} Passage:"
+            );
+            let passage = generate_text(settings, &prompt).await?;
+            if passage.trim().is_empty() {
+                return Ok(vec![query.to_string()]);
+            }
+            Ok(vec![passage.trim().to_string()])
+        }
+    }
+}
+
+// fuse merges the ranked results of the queries by reciprocal rank fusion:
+// every chunk scores the sum of 1 / (RRF_K + rank) over the results it appears in
+pub fn fuse(results: Vec<Vec<Chunk>>, limit: usize) -> Vec<Chunk> {
+    let mut fused: Vec<(f64, Chunk)> = vec![];
+    let mut positions: HashMap<(String, String), usize> = HashMap::new();
+    for ranked in results {
+        for (rank, chunk) in ranked.into_iter().enumerate() {
+            let score = 1.0 / (RRF_K + rank as f64 + 1.0);
+            let doc_name = chunk
+                .metadata
+                .get(DOC_NAME_KEY)
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string();
+            let key = (doc_name, chunk.text.to_string());
+            match positions.get(&key) {
+                Some(&position) => fused[position].0 += score,
+                None => {
+                    positions.insert(key, fused.len());
+                    fused.push((score, chunk));
+                }
+            }
+        }
+    }
+    // the sort is stable: the ties keep the order of the first query
+    fused.sort_by(|a, b| b.0.total_cmp(&a.0));
+    fused
+        .into_iter()
+        .take(limit)
+        .map(|(_, chunk)| chunk)
+        .collect()
+}
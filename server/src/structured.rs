@@ -0,0 +1,209 @@
+use std::{collections::BTreeMap, fmt};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::debug;
+
+use crate::{
+    extractor::Chunk,
+    utils::{generate_json, ConfigVar},
+};
+
+// the invalid answers are sent back to the model along with the validation errors
+const MAX_ATTEMPTS: usize = 3;
+// validation errors reported to the model (and to the user) at most
+const MAX_ERRORS: usize = 10;
+// characters of the chunk quoted along with its citation
+const EXCERPT_CHARS: usize = 200;
+
+// Source is a chunk of the document a field was read from
+#[derive(Serialize, Debug, Clone)]
+pub struct Source {
+    // id of the chunk within the context (C1, C2..)
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub citation: Option<String>,
+    pub excerpt: String,
+}
+
+// StructuredData is the object filled as per the schema along with the sources of its fields
+#[derive(Serialize, Debug)]
+pub struct StructuredData {
+    pub data: Value,
+    pub citations: BTreeMap<String, Vec<Source>>,
+    pub attempts: usize,
+}
+
+// SchemaError reports a schema that can't be used for the extraction
+#[derive(Debug)]
+pub struct SchemaError(pub String);
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid schema: {}", self.0)
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+// InvalidOutput reports that the model didn't answer as per the schema within the attempts
+#[derive(Serialize, Debug)]
+pub struct InvalidOutput {
+    pub errors: Vec<String>,
+    // the last answer of the model
+    pub output: String,
+}
+
+impl fmt::Display for InvalidOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the model output doesn't match the schema after {} attempts: {}",
+            MAX_ATTEMPTS,
+            self.errors.join("; ")
+        )
+    }
+}
+
+impl std::error::Error for InvalidOutput {}
+
+// answer expected from the model: the data along with the chunk ids of every field
+#[derive(Deserialize)]
+struct Answer {
+    data: Value,
+    #[serde(default)]
+    citations: BTreeMap<String, Vec<String>>,
+}
+
+// field_queries checks the schema and lists a query per top level field:
+// the field name along with its description, embedded to retrieve the chunks of the field
+pub fn field_queries(schema: &Value) -> Result<Vec<String>> {
+    jsonschema::validator_for(schema).map_err(|e| SchemaError(e.to_string()))?;
+    let properties = schema
+        .get("properties")
+        .and_then(|properties| properties.as_object())
+        .filter(|properties| !properties.is_empty())
+        .ok_or(SchemaError(
+            "an object schema with properties is expected".to_string(),
+        ))?;
+    let queries = properties
+        .iter()
+        .map(|(name, property)| {
+            let mut query = name.replace(['_', '-'], " ");
+            for key in ["title", "description"] {
+                if let Some(text) = property.get(key).and_then(|v| v.as_str()) {
+                    query.push_str(&format!(": {}", text));
+                }
+            }
+            query
+        })
+        .collect();
+    Ok(queries)
+}
+
+// fill asks the model to fill the schema from the chunks, the answer is validated against
+// the schema and sent back along with the validation errors until it is valid
+pub async fn fill(
+    settings: &ConfigVar,
+    schema: &Value,
+    chunks: &[Chunk],
+) -> Result<StructuredData> {
+    let validator = jsonschema::validator_for(schema).map_err(|e| SchemaError(e.to_string()))?;
+    let context = chunks
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| match chunk.citation() {
+            Some(citation) => format!("[C{}] ({}) {}", i + 1, citation, chunk.text),
+            None => format!("[C{}] {}", i + 1, chunk.text),
+        })
+        .collect::<Vec<String>>()
+        .join("\n\n");
+    // the answer wraps the data with the citations of its fields
+    let format = json!({
+        "type": "object",
+        "properties": {
+            "data": schema,
+            "citations": {
+                "type": "object",
+                "additionalProperties": { "type": "array", "items": { "type": "string" } }
+            }
+        },
+        "required": ["data", "citations"]
+    });
+    let base_prompt = format!(
+        "You are an expert extracting structured data from documents.
+        Fill the fields of the JSON schema below using only the information from the context.
+        Leave out the optional fields the context has no value for, never make up a value.
+        Answer with a JSON object having the filled object under `data` and, under `citations`,
+        the ids of the context passages (e.g. C2) every field was read from keyed by the field name.
+        Schema: {schema}
+        Context: {context}"
+    );
+
+    let mut prompt = base_prompt.to_string();
+    let mut errors = vec![];
+    let mut output = String::new();
+    for attempt in 1..=MAX_ATTEMPTS {
+        output = generate_json(settings, &prompt, &format)
+            .await
+            .context("unable to generate the structured data")?;
+        errors = match serde_json::from_str::<Answer>(&output) {
+            Ok(answer) => {
+                let errors: Vec<String> = validator
+                    .iter_errors(&answer.data)
+                    .take(MAX_ERRORS)
+                    .map(|e| format!("{} at `{}`", e, e.instance_path))
+                    .collect();
+                if errors.is_empty() {
+                    return Ok(StructuredData {
+                        citations: sources(answer.citations, chunks),
+                        data: answer.data,
+                        attempts: attempt,
+                    });
+                }
+                errors
+            }
+            Err(e) => vec![format!("the answer is not a valid JSON object: {}", e)],
+        };
+        debug!(
+            "invalid structured output (attempt {}): {:?}",
+            attempt, errors
+        );
+        prompt = format!(
+            "{base_prompt}
+            Your previous answer was: {output}
+            It is invalid: {}. Answer again fixing these errors.",
+            errors.join("; ")
+        );
+    }
+    Err(anyhow!(InvalidOutput { errors, output }))
+}
+
+// sources maps the chunk ids cited for every field to the chunks
+fn sources(
+    citations: BTreeMap<String, Vec<String>>,
+    chunks: &[Chunk],
+) -> BTreeMap<String, Vec<Source>> {
+    citations
+        .into_iter()
+        .map(|(field, ids)| {
+            let mut sources: Vec<Source> = ids
+                .iter()
+                .filter_map(|id| {
+                    let id = id.trim().trim_matches(['[', ']']).to_uppercase();
+                    let index: usize = id.strip_prefix('C')?.parse().ok()?;
+                    let chunk = chunks.get(index.checked_sub(1)?)?;
+                    Some(Source {
+                        id,
+                        citation: chunk.citation(),
+                        excerpt: chunk.text.chars().take(EXCERPT_CHARS).collect(),
+                    })
+                })
+                .collect();
+            sources.dedup_by(|a, b| a.id == b.id);
+            (field, sources)
+        })
+        .filter(|(_, sources)| !sources.is_empty())
+        .collect()
+}
@@ -0,0 +1,323 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    extractor::{
+        Chunk, PAGE_END_KEY, PAGE_START_KEY, PATH_KEY, SECTION_KEY, SHEET_KEY, SLIDE_KEY,
+        SUBJECT_KEY,
+    },
+    utils::{generate_text, ConfigVar},
+};
+
+// characters of the document text (or of the partial summaries) sent to the model at once
+const BATCH_CHARS: usize = 12_000;
+// number of the suggested starter questions
+const QUESTION_COUNT: usize = 5;
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SummaryLength {
+    Short,
+    #[default]
+    Medium,
+    Long,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SummaryStyle {
+    // a few paragraphs leading with the purpose & the conclusions of the document
+    #[default]
+    Executive,
+    // a markdown list of the key points
+    Bullets,
+    // every section (chapter, slide, sheet, file, email..) summarized on its own
+    Sections,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+pub struct SummaryOptions {
+    #[serde(default)]
+    pub length: SummaryLength,
+    #[serde(default)]
+    pub style: SummaryStyle,
+}
+
+// Progress of the summary: the stage (map, reduce, final or section)
+// along with the batches (or sections) of the stage done so far
+#[derive(Serialize, Clone, Debug)]
+pub struct Progress {
+    pub stage: &'static str,
+    pub done: usize,
+    pub total: usize,
+}
+
+// summarize runs a map-reduce over the chunks of the document (in their order):
+// every batch of chunks is summarized on its own, then the partial summaries
+// are reduced (batch by batch while they don't fit) into the final summary
+pub async fn summarize(
+    settings: &ConfigVar,
+    chunks: &[Chunk],
+    options: &SummaryOptions,
+    progress: impl Fn(Progress) + Sync,
+) -> Result<String> {
+    if chunks.iter().all(|chunk| chunk.text.trim().is_empty()) {
+        return Err(anyhow!("the document has no text to summarize"));
+    }
+    if let SummaryStyle::Sections = options.style {
+        return summarize_sections(settings, chunks, options.length, progress).await;
+    }
+    let texts: Vec<String> = chunks.iter().map(|chunk| chunk.text.to_string()).collect();
+    let text = reduce(settings, batch(&texts), &progress).await?;
+    progress(Progress {
+        stage: "final",
+        done: 0,
+        total: 1,
+    });
+    let instruction = match (options.style, options.length) {
+        (SummaryStyle::Bullets, SummaryLength::Short) => {
+            "Summarize the document as a markdown list of at most 5 bullet points"
+        }
+        (SummaryStyle::Bullets, SummaryLength::Medium) => {
+            "Summarize the document as a markdown list of about 10 bullet points"
+        }
+        (SummaryStyle::Bullets, SummaryLength::Long) => {
+            "Summarize the document as a markdown list of about 20 bullet points"
+        }
+        (_, SummaryLength::Short) => {
+            "Write an executive summary of the document in 3 or 4 sentences"
+        }
+        (_, SummaryLength::Medium) => {
+            "Write an executive summary of the document in one or two paragraphs"
+        }
+        (_, SummaryLength::Long) => {
+            "Write an executive summary of the document in four to six paragraphs"
+        }
+    };
+    let prompt = format!(
+        "{instruction}, leading with its purpose and main conclusions followed by the key facts, figures and decisions.
+        Use only the information from the text below, do not add anything that is not in the text.
+        Text: {text} Summary:"
+    );
+    let summary = generate_text(settings, &prompt).await?.trim().to_string();
+    progress(Progress {
+        stage: "final",
+        done: 1,
+        total: 1,
+    });
+    Ok(summary)
+}
+
+// summarize_sections summarizes every section of the document on its own under its title
+async fn summarize_sections(
+    settings: &ConfigVar,
+    chunks: &[Chunk],
+    length: SummaryLength,
+    progress: impl Fn(Progress) + Sync,
+) -> Result<String> {
+    let instruction = match length {
+        SummaryLength::Short => "in one sentence",
+        SummaryLength::Medium => "in 2 or 3 sentences",
+        SummaryLength::Long => "in a paragraph",
+    };
+    let sections = sections(chunks);
+    let mut summaries = vec![];
+    for (i, (title, texts)) in sections.iter().enumerate() {
+        progress(Progress {
+            stage: "section",
+            done: i,
+            total: sections.len(),
+        });
+        let text = reduce(settings, batch(texts), &|_| {}).await?;
+        let prompt = format!(
+            "Summarize the following section of a document {instruction}.
+            Keep the key facts, figures and names, do not add anything that is not in the text.
+            Text: {text} Summary:"
+        );
+        let summary = generate_text(settings, &prompt).await?;
+        summaries.push(format!("### {}\n{}", title, summary.trim()));
+    }
+    progress(Progress {
+        stage: "section",
+        done: sections.len(),
+        total: sections.len(),
+    });
+    Ok(summaries.join("\n\n"))
+}
+
+// reduce summarizes the batches until what is left fits into a single batch
+async fn reduce(
+    settings: &ConfigVar,
+    mut batches: Vec<String>,
+    progress: &(impl Fn(Progress) + Sync),
+) -> Result<String> {
+    let mut stage = "map";
+    while batches.len() > 1 {
+        let mut partials = vec![];
+        for (i, text) in batches.iter().enumerate() {
+            progress(Progress {
+                stage,
+                done: i,
+                total: batches.len(),
+            });
+            let prompt = format!(
+                "Summarize the following part of a document in a few sentences.
+                Keep the key facts, figures and names, do not add anything that is not in the text.
+                Text: {text} Summary:"
+            );
+            partials.push(generate_text(settings, &prompt).await?.trim().to_string());
+        }
+        progress(Progress {
+            stage,
+            done: batches.len(),
+            total: batches.len(),
+        });
+        stage = "reduce";
+        let reduced = batch(&partials);
+        // the summaries are too long to get fewer batches: they are reduced in pairs,
+        // halving them every round rather than leaving any part of the document out
+        if reduced.len() >= batches.len() {
+            warn!(
+                "{} partial summaries don't fit into fewer batches, reducing them in pairs",
+                partials.len()
+            );
+            batches = partials.chunks(2).map(|pair| pair.join("\n\n")).collect();
+        } else {
+            batches = reduced;
+        }
+    }
+    Ok(batches.pop().unwrap_or_default())
+}
+
+// suggest_questions asks the model for the starter questions answered by the document
+pub async fn suggest_questions(settings: &ConfigVar, summary: &str) -> Result<Vec<String>> {
+    let prompt = format!(
+        "Suggest {QUESTION_COUNT} short questions a reader could ask about the document summarized below.
+        The questions must be answerable from the document.
+        Write one question per line without numbering or any other text.
+        Summary: {summary} Questions:"
+    );
+    let response = generate_text(settings, &prompt).await?;
+    let questions: Vec<String> = response
+        .lines()
+        .map(|line| {
+            line.trim()
+                .trim_start_matches(|c: char| {
+                    c.is_ascii_digit() || matches!(c, '.' | ')' | '-' | '*' | '•' | ' ')
+                })
+                .trim()
+                .to_string()
+        })
+        .filter(|line| line.ends_with('?'))
+        .take(QUESTION_COUNT)
+        .collect();
+    if questions.is_empty() {
+        return Err(anyhow!("the model suggested no questions"));
+    }
+    Ok(questions)
+}
+
+// sections groups the consecutive chunks of the same section (chapter, email, file, sheet, slide)
+// under its title, the chunks out of any section are grouped by batch under their pages
+fn sections(chunks: &[Chunk]) -> Vec<(String, Vec<String>)> {
+    let mut groups: Vec<Group> = vec![];
+    for chunk in chunks.iter().filter(|chunk| !chunk.text.trim().is_empty()) {
+        let title = section_title(chunk);
+        let pages = chunk
+            .metadata
+            .get(PAGE_START_KEY)
+            .and_then(|v| v.as_u64())
+            .map(|start| {
+                let end = chunk.metadata.get(PAGE_END_KEY).and_then(|v| v.as_u64());
+                (start, end.unwrap_or(start))
+            });
+        let same_group = groups
+            .last()
+            .is_some_and(|group| match (&group.title, &title) {
+                (Some(last), Some(title)) => last == title,
+                (None, None) => {
+                    let len: usize = group.texts.iter().map(|text| text.len()).sum();
+                    len + chunk.text.len() <= BATCH_CHARS
+                }
+                _ => false,
+            });
+        if !same_group {
+            groups.push(Group {
+                title,
+                pages: None,
+                texts: vec![],
+            });
+        }
+        let group = groups.last_mut().unwrap();
+        if let Some((start, end)) = pages {
+            group.pages = Some(match group.pages {
+                Some((first, last)) => (first.min(start), last.max(end)),
+                None => (start, end),
+            });
+        }
+        group.texts.push(chunk.text.to_string());
+    }
+    groups
+        .into_iter()
+        .enumerate()
+        .map(|(i, group)| {
+            let title = group.title.unwrap_or_else(|| match group.pages {
+                Some((start, end)) if start == end => format!("Page {}", start),
+                Some((start, end)) => format!("Pages {}-{}", start, end),
+                None => format!("Part {}", i + 1),
+            });
+            (title, group.texts)
+        })
+        .collect()
+}
+
+// Group is the run of chunks of the same section (or of a batch of chunks out of any section)
+struct Group {
+    title: Option<String>,
+    // first & last pages of the chunks
+    pages: Option<(u64, u64)>,
+    texts: Vec<String>,
+}
+
+fn section_title(chunk: &Chunk) -> Option<String> {
+    let text = |key: &str| chunk.metadata.get(key).and_then(|v| v.as_str());
+    if let Some(section) = text(SECTION_KEY) {
+        return Some(section.to_string());
+    }
+    if let Some(subject) = text(SUBJECT_KEY) {
+        return Some(format!("Email \"{}\"", subject));
+    }
+    if let Some(path) = text(PATH_KEY) {
+        return Some(path.to_string());
+    }
+    if let Some(sheet) = text(SHEET_KEY) {
+        return Some(format!("Sheet \"{}\"", sheet));
+    }
+    let slide = chunk.metadata.get(SLIDE_KEY).and_then(|v| v.as_u64())?;
+    Some(format!("Slide {}", slide))
+}
+
+// batch joins the consecutive texts up to the batch size,
+// a text longer than the batch size makes a batch on its own
+fn batch(texts: &[String]) -> Vec<String> {
+    let mut batches: Vec<String> = vec![];
+    let mut current = String::new();
+    for text in texts
+        .iter()
+        .map(|text| text.trim())
+        .filter(|t| !t.is_empty())
+    {
+        if !current.is_empty() && current.len() + text.len() > BATCH_CHARS {
+            batches.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.push_str(text);
+    }
+    if !current.is_empty() {
+        batches.push(current);
+    }
+    batches
+}
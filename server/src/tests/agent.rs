@@ -0,0 +1,129 @@
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+
+use super::{unique_name, TestApp, MOCK_ANSWER};
+
+const WARRANTY_V1: &str = "clause,terms\nwarranty,the warranty lasts one year\n";
+const WARRANTY_V2: &str = "clause,terms\nwarranty,the warranty lasts two years\n";
+
+async fn upload_versions(test_app: &TestApp) -> (String, String) {
+    let v1 = format!("{}.csv", unique_name("warranty-v1"));
+    let v2 = format!("{}.csv", unique_name("warranty-v2"));
+    for (file_name, content) in [(&v1, WARRANTY_V1), (&v2, WARRANTY_V2)] {
+        let (status, body) = test_app.upload(file_name, content.as_bytes(), &[]).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+    (v1, v2)
+}
+
+#[tokio::test]
+async fn agent_searches_every_document_before_answering() {
+    let test_app = TestApp::new().await;
+    let (v1, v2) = upload_versions(&test_app).await;
+    let ollama = &test_app.ollama;
+    ollama.push_json_answer(json!({
+        "thought": "find the warranty of v1",
+        "action": "search",
+        "doc_name": v1,
+        "query": "warranty duration"
+    }));
+    ollama.push_json_answer(json!({
+        "thought": "find the warranty of v2",
+        "action": "search",
+        "doc_name": v2,
+        "query": "warranty duration"
+    }));
+    ollama.push_json_answer(json!({ "thought": "both found", "action": "answer" }));
+
+    let (status, body) = test_app
+        .post_json(
+            "/agent",
+            json!({
+                "user_query": "how did the warranty change between v1 and v2?",
+                "doc_names": [v1, v2]
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let answer: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(answer["answer"], MOCK_ANSWER);
+    assert_eq!(answer["finished"], true);
+    let steps = answer["steps"].as_array().unwrap();
+    assert_eq!(steps.len(), 3);
+    assert_eq!(steps[0]["doc_name"], v1);
+    assert!(steps[0]["results"][0]
+        .as_str()
+        .unwrap()
+        .contains("one year"));
+    assert!(steps[1]["results"][0]
+        .as_str()
+        .unwrap()
+        .contains("two years"));
+    assert_eq!(steps[2]["action"], "answer");
+
+    let prompts = ollama.prompts();
+    // the second step sees what the first one found
+    let planning = prompts.iter().find(|p| p.contains("Step 1:")).unwrap();
+    assert!(planning.contains("one year"), "{}", planning);
+    // the answer is synthesized from the chunks of both documents
+    let synthesis = prompts.last().unwrap();
+    assert!(synthesis.contains("one year") && synthesis.contains("two years"));
+}
+
+#[tokio::test]
+async fn agent_answers_when_the_steps_run_out() {
+    let test_app = TestApp::new().await;
+    let (v1, v2) = upload_versions(&test_app).await;
+    let ollama = &test_app.ollama;
+    ollama.push_json_answer(json!({
+        "thought": "search an unknown document",
+        "action": "search",
+        "doc_name": "other.pdf",
+        "query": "warranty"
+    }));
+    ollama.push_json_answer(json!({
+        "thought": "find the warranty of v2",
+        "action": "search",
+        "doc_name": v2,
+        "query": "warranty"
+    }));
+
+    let (status, body) = test_app
+        .post_json(
+            "/agent",
+            json!({
+                "user_query": "how long is the warranty?",
+                "doc_names": [v1, v2],
+                "max_steps": 2
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let answer: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(answer["finished"], false);
+    let steps = answer["steps"].as_array().unwrap();
+    assert_eq!(steps.len(), 2);
+    assert!(steps[0]["error"]
+        .as_str()
+        .unwrap()
+        .contains("unknown document"));
+    assert!(steps[1]["results"][0]
+        .as_str()
+        .unwrap()
+        .contains("two years"));
+
+    let (status, _) = test_app
+        .post_json(
+            "/agent",
+            json!({ "user_query": "warranty?", "doc_names": ["notes.exe"] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = test_app
+        .post_json(
+            "/agent",
+            json!({ "user_query": "warranty?", "doc_names": [] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,242 @@
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+
+use super::{unique_name, zip_archive, TestApp, MOCK_ANSWER};
+
+const SALES_CSV: &str = "region,product,revenue
+north,widgets,1200
+south,gadgets,800
+east,gizmos,450
+";
+
+// upload_csv uploads the sales sheet as a new document and returns its file name
+async fn upload_csv(test_app: &TestApp) -> String {
+    let file_name = format!("{}.csv", unique_name("sales"));
+    let (status, body) = test_app
+        .upload(&file_name, SALES_CSV.as_bytes(), &[("tags", "finance")])
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    file_name
+}
+
+#[tokio::test]
+async fn upload_then_prompt_answers_from_the_document() {
+    let test_app = TestApp::new().await;
+    let file_name = upload_csv(&test_app).await;
+
+    let (status, body) = test_app
+        .post_json(
+            "/prompt",
+            json!({ "user_query": "what is the revenue of widgets?", "doc_name": file_name }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body, MOCK_ANSWER);
+
+    // the rows were retrieved & sent as the context along with their citation
+    let prompts = test_app.ollama.prompts();
+    let prompt = prompts.last().expect("the answer is generated");
+    assert!(prompt.contains("widgets"), "{}", prompt);
+    assert!(prompt.contains("1200"), "{}", prompt);
+    assert!(prompt.contains("sheet \""), "{}", prompt);
+    assert!(prompt.contains("what is the revenue of widgets?"));
+}
+
+#[tokio::test]
+async fn prompt_filter_without_matches_gives_no_context() {
+    let test_app = TestApp::new().await;
+    let file_name = upload_csv(&test_app).await;
+
+    let (status, _) = test_app
+        .post_json(
+            "/prompt",
+            json!({
+                "user_query": "what is the revenue of widgets?",
+                "doc_name": file_name,
+                "filter": { "tags": ["legal"] }
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let prompts = test_app.ollama.prompts();
+    assert!(!prompts.last().unwrap().contains("1200"));
+}
+
+#[tokio::test]
+async fn upload_rejects_unsupported_files() {
+    let test_app = TestApp::new().await;
+    let (status, body) = test_app.upload("notes.exe", b"binary", &[]).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.starts_with("Only pdf, "), "{}", body);
+
+    // the client is told what was wrong with the form
+    let (status, body) = test_app
+        .upload("notes.csv", b"a,b\n1,2\n", &[("metadata", "not json")])
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(
+        body.contains("metadata is expected to be a json object"),
+        "{}",
+        body
+    );
+}
+
+#[tokio::test]
+async fn uploaded_document_is_catalogued() {
+    let test_app = TestApp::new().await;
+    let file_name = upload_csv(&test_app).await;
+    let doc_name = file_name.trim_end_matches(".csv");
+
+    let (status, body) = test_app.get("/").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains(doc_name), "{}", body);
+
+    let (status, body) = test_app.get(&format!("/documents/{}", doc_name)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let entry: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(entry["file_name"], file_name.as_str());
+    assert!(entry["chunks"].as_u64().unwrap() > 0);
+
+    let (status, _) = test_app.get("/documents/missing").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn extract_fills_the_schema_with_citations() {
+    let test_app = TestApp::new().await;
+    let file_name = upload_csv(&test_app).await;
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "top_product": { "type": "string", "description": "product with the most revenue" }
+        },
+        "required": ["top_product"]
+    });
+    // the first answer misses the required field and is sent back to the model
+    test_app
+        .ollama
+        .push_json_answer(json!({ "data": {}, "citations": {} }));
+    test_app.ollama.push_json_answer(json!({
+        "data": { "top_product": "widgets" },
+        "citations": { "top_product": ["C1"] }
+    }));
+
+    let (status, body) = test_app
+        .post_json(
+            "/extract",
+            json!({ "doc_name": file_name, "schema": schema }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let result: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(result["data"]["top_product"], "widgets");
+    assert_eq!(result["attempts"], 2);
+    assert_eq!(result["citations"]["top_product"][0]["id"], "C1");
+    let prompts = test_app.ollama.prompts();
+    assert!(prompts.last().unwrap().contains("Your previous answer was"));
+}
+
+#[tokio::test]
+async fn extract_rejects_invalid_schemas() {
+    let test_app = TestApp::new().await;
+    let file_name = upload_csv(&test_app).await;
+    let (status, _) = test_app
+        .post_json(
+            "/extract",
+            json!({ "doc_name": file_name, "schema": { "type": "string" } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn workspace_documents_are_answered_from_the_workspace() {
+    let test_app = TestApp::new().await;
+    let workspace = unique_name("team");
+    let sales = format!("{}.csv", unique_name("sales"));
+    let staff = format!("{}.csv", unique_name("staff"));
+    let archive = zip_archive(&[
+        (sales.as_str(), SALES_CSV.as_bytes()),
+        (staff.as_str(), b"name,role\nalice,engineer\nbob,designer\n"),
+    ]);
+    let (status, body) = test_app
+        .upload("team.zip", &archive, &[("workspace", workspace.as_str())])
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    // the document is searched within the workspace, the other documents being left out
+    let (status, body) = test_app
+        .post_json(
+            "/prompt",
+            json!({ "user_query": "what is the revenue of widgets?", "doc_name": sales }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let prompts = test_app.ollama.prompts();
+    let prompt = prompts.last().unwrap();
+    assert!(prompt.contains("1200"), "{}", prompt);
+    assert!(!prompt.contains("alice"), "{}", prompt);
+
+    // the whole workspace is searched by its name
+    let (status, body) = test_app
+        .post_json(
+            "/prompt",
+            json!({ "user_query": "who is alice?", "doc_name": workspace }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let prompts = test_app.ollama.prompts();
+    let prompt = prompts.last().unwrap();
+    assert!(prompt.contains("alice"), "{}", prompt);
+    assert!(prompt.contains("1200"), "{}", prompt);
+}
+
+#[tokio::test]
+async fn code_directories_are_asked_by_their_name() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().to_str().unwrap().to_string();
+    let test_app =
+        TestApp::with_settings(|settings| settings.source_roots = Some(vec![root])).await;
+    std::fs::write(
+        dir.path().join("lib.rs"),
+        "// widgets_revenue sums the revenue of the widgets\nfn widgets_revenue() -> u32 {\n    1200\n}\n",
+    )
+    .unwrap();
+    let name = unique_name("repo");
+    let (status, body) = test_app
+        .post_json(
+            "/directory",
+            json!({ "path": dir.path().to_str().unwrap(), "name": name }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    // the directories out of the source roots are not read
+    let (status, _) = test_app
+        .post_json("/directory", json!({ "path": "/etc" }))
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let escape = format!("{}/../..", dir.path().to_str().unwrap());
+    let (status, _) = test_app
+        .post_json("/directory", json!({ "path": escape }))
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // the directory has no extension, it is asked by its name as is
+    let (status, body) = test_app
+        .post_json(
+            "/prompt",
+            json!({ "user_query": "what is the revenue of widgets?", "doc_name": name }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let prompts = test_app.ollama.prompts();
+    assert!(prompts.last().unwrap().contains("lib.rs:1-4"));
+
+    let (status, _) = test_app
+        .post_json(
+            "/prompt",
+            json!({ "user_query": "what is the revenue of widgets?", "doc_name": unique_name("missing") }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,108 @@
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+
+use super::{embedding, unique_name, TestApp};
+use crate::compare::{align, ALIGN_THRESHOLD};
+
+#[test]
+fn sections_are_aligned_once_with_their_most_similar_section() {
+    let a: Vec<Vec<f32>> = [
+        "the warranty lasts one year from the delivery",
+        "payment is due within thirty days of the invoice",
+        "liability is capped at the fees paid",
+    ]
+    .iter()
+    .map(|text| embedding(text))
+    .collect();
+    let b: Vec<Vec<f32>> = [
+        "payment is due within thirty days of the invoice",
+        "support is available during the business hours",
+        "the warranty lasts two years from the delivery",
+    ]
+    .iter()
+    .map(|text| embedding(text))
+    .collect();
+
+    let alignment = align(&a, &b, ALIGN_THRESHOLD);
+    let pairs: Vec<(usize, usize)> = alignment.pairs.iter().map(|(i, j, _)| (*i, *j)).collect();
+    assert_eq!(pairs, [(0, 2), (1, 0)]);
+    assert!(alignment.pairs[1].2 > 0.99);
+    assert_eq!(alignment.only_in_a, [2]);
+    assert_eq!(alignment.only_in_b, [1]);
+}
+
+#[tokio::test]
+async fn compare_reports_the_same_changed_and_unmatched_sections() {
+    let test_app =
+        TestApp::with_settings(|settings| settings.embedding_model_chunk_size = Some(20)).await;
+    let contract_a = format!("{}.csv", unique_name("contract"));
+    let contract_b = format!("{}.csv", unique_name("contract"));
+    let csv_a = "clause,terms\n\
+        warranty,the warranty lasts one year from the delivery of the goods\n\
+        payment,payment is due within thirty days of the invoice date\n\
+        liability,liability is capped at the fees paid by the customer\n";
+    let csv_b = "clause,terms\n\
+        payment,payment is due within thirty days of the invoice date\n\
+        support,support is available during the business hours only\n\
+        warranty,the warranty lasts two years from the delivery of the goods\n";
+    for (file_name, csv) in [(&contract_a, csv_a), (&contract_b, csv_b)] {
+        let (status, body) = test_app.upload(file_name, csv.as_bytes(), &[]).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+    // the pairs differing by the sheet name only are judged too
+    test_app.ollama.push_json_answer(json!({ "pairs": [
+        { "pair": 1, "status": "changed", "difference": "the warranty goes from one to two years" },
+        { "pair": 2, "status": "same" }
+    ]}));
+    let embedded = test_app.ollama.embedded().len();
+
+    let (status, body) = test_app
+        .post_json(
+            "/compare",
+            json!({ "doc_a": contract_a, "doc_b": contract_b }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    // the sections are aligned on their stored vectors
+    assert_eq!(test_app.ollama.embedded().len(), embedded);
+    let comparison: Value = serde_json::from_str(&body).unwrap();
+    let changed = comparison["changed"].as_array().unwrap();
+    assert_eq!(changed.len(), 1, "{}", body);
+    assert!(changed[0]["a"]["text"]
+        .as_str()
+        .unwrap()
+        .contains("one year"));
+    assert!(changed[0]["b"]["text"]
+        .as_str()
+        .unwrap()
+        .contains("two years"));
+    assert!(changed[0]["a"]["citation"]
+        .as_str()
+        .unwrap()
+        .ends_with("row 2"));
+    assert!(changed[0]["b"]["citation"]
+        .as_str()
+        .unwrap()
+        .ends_with("row 4"));
+    assert_eq!(
+        changed[0]["difference"],
+        "the warranty goes from one to two years"
+    );
+    assert_eq!(comparison["same"].as_array().unwrap().len(), 1);
+    assert!(comparison["only_in_a"][0]["text"]
+        .as_str()
+        .unwrap()
+        .contains("liability"));
+    assert!(comparison["only_in_b"][0]["text"]
+        .as_str()
+        .unwrap()
+        .contains("support"));
+
+    let (status, _) = test_app
+        .post_json(
+            "/compare",
+            json!({ "doc_a": contract_a, "doc_b": "missing.pdf" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,75 @@
+use axum::http::StatusCode;
+use serde_json::json;
+
+use super::{unique_name, TestApp, MOCK_ANSWER};
+use crate::evaluation::{evaluate, metrics, QuestionResult};
+
+#[tokio::test]
+async fn the_judged_answer_is_generated_from_the_context_scored() {
+    let test_app = TestApp::new().await;
+    let file_name = format!("{}.csv", unique_name("sales"));
+    let (status, body) = test_app
+        .upload(
+            &file_name,
+            b"region,product,revenue\nnorth,widgets,1200\nsouth,gadgets,800\n",
+            &[],
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let question = "what is the evaluated revenue of widgets?";
+    let dataset = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(
+        dataset.path(),
+        json!({ "question": question, "expected_answer": "1200" }).to_string(),
+    )
+    .unwrap();
+    test_app.ollama.push_json_answer(json!({
+        "faithfulness": 5,
+        "correctness": 4,
+        "reasoning": "the revenue is given"
+    }));
+
+    let report = evaluate(&test_app.processor, dataset.path(), Some(&file_name), 6)
+        .await
+        .unwrap();
+    assert_eq!(report.failed, 0);
+    let result = &report.results[0];
+    assert_eq!(result.answer.as_deref(), Some(MOCK_ANSWER));
+    assert_eq!(result.correctness, Some(4));
+
+    // the question is retrieved once, the answer being generated from that context
+    let embedded = test_app.ollama.embedded();
+    assert_eq!(embedded.iter().filter(|text| *text == question).count(), 1);
+    let prompts = test_app.ollama.prompts();
+    assert!(prompts[0].contains("1200"), "{}", prompts[0]);
+    assert!(prompts[0].contains(question));
+}
+
+#[test]
+fn the_retrieval_is_scored_when_the_generation_failed() {
+    let retrieved = QuestionResult {
+        recall: Some(1.0),
+        reciprocal_rank: Some(0.5),
+        retrieval_ms: Some(10),
+        error: Some("the judge answer is not valid".to_string()),
+        ..Default::default()
+    };
+    let judged = QuestionResult {
+        recall: Some(0.0),
+        reciprocal_rank: Some(0.0),
+        faithfulness: Some(4),
+        retrieval_ms: Some(30),
+        answer_ms: Some(100),
+        ..Default::default()
+    };
+    let unread = QuestionResult {
+        error: Some("document not found".to_string()),
+        ..Default::default()
+    };
+    let metrics = metrics(&[retrieved, judged, unread]);
+    assert_eq!(metrics.recall_at_k, Some(0.5));
+    assert_eq!(metrics.mrr, Some(0.25));
+    assert_eq!(metrics.faithfulness, Some(4.0));
+    assert_eq!(metrics.retrieval_latency.mean_ms, 20);
+    assert_eq!(metrics.answer_latency.mean_ms, 100);
+}
@@ -0,0 +1,95 @@
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+
+use super::{unique_name, TestApp};
+use crate::grounding::split_sentences;
+
+#[test]
+fn answers_are_split_into_sentences() {
+    let answer = "Widgets made 1200 in the north [sheet \"sales\"]. Is it the most?\n\
+        1. Gadgets made 800, e.g. in the south!\n- Gizmos made 4.5k";
+    assert_eq!(
+        split_sentences(answer),
+        [
+            "Widgets made 1200 in the north [sheet \"sales\"].",
+            "Is it the most?",
+            "Gadgets made 800, e.g.",
+            "in the south!",
+            "Gizmos made 4.5k",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn verified_prompt_flags_the_unsupported_sentences() {
+    let test_app = TestApp::new().await;
+    let file_name = format!("{}.csv", unique_name("grounding"));
+    let (status, _) = test_app
+        .upload(
+            &file_name,
+            b"region,product,revenue\nnorth,widgets,1200\n",
+            &[],
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    test_app
+        .ollama
+        .push_answer("Widgets made 1200 in the north. They sell best on Mars.");
+    test_app.ollama.push_json_answer(json!({
+        "verdicts": [
+            { "sentence": 1, "verdict": "supported" },
+            { "sentence": 2, "verdict": "unsupported" }
+        ]
+    }));
+
+    let (status, body) = test_app
+        .post_json(
+            "/prompt",
+            json!({ "user_query": "widgets revenue?", "doc_name": file_name, "verify": true }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let answer: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(
+        answer["answer"],
+        "Widgets made 1200 in the north. They sell best on Mars."
+    );
+    assert_eq!(answer["confidence"], 0.5);
+    assert_eq!(answer["unsupported"], 1);
+    let sentences = answer["sentences"].as_array().unwrap();
+    assert_eq!(sentences[0]["supported"], true);
+    assert_eq!(sentences[0]["verdict"], "supported");
+    assert!(
+        sentences[0]["similarity"].as_f64().unwrap() > sentences[1]["similarity"].as_f64().unwrap()
+    );
+    assert!(sentences[0]["source"].as_str().unwrap().contains("sheet"));
+    assert_eq!(sentences[1]["supported"], false);
+}
+
+#[tokio::test]
+async fn verification_falls_back_on_the_similarity() {
+    let test_app = TestApp::new().await;
+    let file_name = format!("{}.csv", unique_name("grounding"));
+    let (status, _) = test_app
+        .upload(
+            &file_name,
+            b"region,product,revenue\nnorth,widgets,1200\n",
+            &[],
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    // no entailment answer: the mock answers `{}`
+    test_app.ollama.push_answer("Penguins live far away.");
+
+    let (status, body) = test_app
+        .post_json(
+            "/prompt",
+            json!({ "user_query": "widgets revenue?", "doc_name": file_name, "verify": true }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let answer: Value = serde_json::from_str(&body).unwrap();
+    let sentence = &answer["sentences"][0];
+    assert!(sentence.get("verdict").is_none());
+    assert_eq!(sentence["supported"], false);
+}
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use super::embedding;
+use crate::{
+    extractor::Chunk,
+    memory_store::MemoryStore,
+    metadata::{DocMetadata, SearchFilter},
+    vector_db::VectorStore,
+};
+
+fn points(texts: &[&str]) -> Vec<(String, Vec<f32>, Chunk)> {
+    texts
+        .iter()
+        .map(|text| {
+            (
+                uuid::Uuid::new_v4().to_string(),
+                embedding(text),
+                Chunk::from(text.to_string()),
+            )
+        })
+        .collect()
+}
+
+fn metadata(tags: &[&str], doc_type: &str) -> DocMetadata {
+    DocMetadata {
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        doc_type: Some(doc_type.to_string()),
+        metadata: HashMap::from([("client".to_string(), "acme".to_string())]),
+        uploaded_at: 1_700_000_000,
+    }
+}
+
+fn texts(chunks: &[Chunk]) -> Vec<&str> {
+    chunks.iter().map(|chunk| chunk.text.as_str()).collect()
+}
+
+#[tokio::test]
+async fn search_ranks_the_similar_chunks_first() {
+    let store = MemoryStore::new();
+    let chunks = points(&[
+        "the invoice total is due in march",
+        "the contract renews every year",
+        "our office cat sleeps all day",
+    ]);
+    store
+        .store_embeddings("doc", "doc", chunks, &metadata(&[], "pdf"))
+        .await
+        .unwrap();
+
+    let ranked = store
+        .search_ranked("doc", embedding("when does the contract renew"), None, 2)
+        .await
+        .unwrap();
+    assert_eq!(ranked.len(), 2);
+    assert_eq!(ranked[0].text, "the contract renews every year");
+    assert!(store
+        .search_ranked("missing", embedding("contract"), None, 2)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn search_applies_the_filter() {
+    let store = MemoryStore::new();
+    store
+        .store_embeddings(
+            "workspace",
+            "invoices",
+            points(&["invoice of march"]),
+            &metadata(&["finance"], "pdf"),
+        )
+        .await
+        .unwrap();
+    store
+        .store_embeddings(
+            "workspace",
+            "contracts",
+            points(&["contract of march"]),
+            &metadata(&["legal"], "docx"),
+        )
+        .await
+        .unwrap();
+
+    let search = |filter: SearchFilter| {
+        let store = &store;
+        async move {
+            let chunks = store
+                .search_ranked("workspace", embedding("march"), Some(&filter), 10)
+                .await
+                .unwrap();
+            texts(&chunks)
+                .into_iter()
+                .map(|text| text.to_string())
+                .collect::<Vec<String>>()
+        }
+    };
+    let tags = SearchFilter {
+        tags: Some(vec!["legal".to_string(), "hr".to_string()]),
+        ..Default::default()
+    };
+    assert_eq!(search(tags).await, ["contract of march"]);
+    let doc_type = SearchFilter {
+        doc_type: Some("pdf".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(search(doc_type).await, ["invoice of march"]);
+    let doc_name = SearchFilter {
+        doc_name: Some("contracts".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(search(doc_name).await, ["contract of march"]);
+    let uploaded = SearchFilter {
+        uploaded_after: Some(1_800_000_000),
+        ..Default::default()
+    };
+    assert!(search(uploaded).await.is_empty());
+    let custom = SearchFilter {
+        metadata: HashMap::from([("client".to_string(), "acme".to_string())]),
+        ..Default::default()
+    };
+    assert_eq!(search(custom).await.len(), 2);
+}
+
+#[tokio::test]
+async fn documents_are_stored_once_unless_in_a_workspace() {
+    let store = MemoryStore::new();
+    let metadata = metadata(&[], "pdf");
+    store
+        .store_embeddings("doc", "doc", points(&["first"]), &metadata)
+        .await
+        .unwrap();
+    assert!(store
+        .store_embeddings("doc", "doc", points(&["again"]), &metadata)
+        .await
+        .is_err());
+    let mut wrong_size = points(&["short vector"]);
+    wrong_size[0].1.truncate(3);
+    assert!(store
+        .store_embeddings("other", "other", wrong_size, &metadata)
+        .await
+        .is_err());
+
+    store
+        .store_embeddings("team", "a", points(&["a1", "a2"]), &metadata)
+        .await
+        .unwrap();
+    store
+        .store_embeddings("team", "b", points(&["b1"]), &metadata)
+        .await
+        .unwrap();
+    assert_eq!(
+        texts(&store.document_chunks("team", "a").await.unwrap()),
+        ["a1", "a2"]
+    );
+
+    store.delete_document("team", "a").await.unwrap();
+    assert!(store.document_chunks("team", "a").await.unwrap().is_empty());
+    assert_eq!(store.document_chunks("team", "b").await.unwrap().len(), 1);
+    store.delete_document("doc", "doc").await.unwrap();
+    assert_eq!(store.list_collections().await.unwrap(), ["team"]);
+}
+
+#[tokio::test]
+async fn diverse_search_leaves_out_the_near_duplicates() {
+    let store = MemoryStore::new();
+    store
+        .store_embeddings(
+            "doc",
+            "doc",
+            points(&[
+                "alpha beta gamma",
+                "alpha beta gamma",
+                "alpha beta gamma",
+                "alpha delta",
+            ]),
+            &metadata(&[], "pdf"),
+        )
+        .await
+        .unwrap();
+
+    let ranked = store
+        .search_ranked("doc", embedding("alpha beta"), None, 2)
+        .await
+        .unwrap();
+    assert_eq!(texts(&ranked), ["alpha beta gamma", "alpha beta gamma"]);
+    let diverse = store
+        .search_diverse("doc", embedding("alpha beta"), None, 2, 0.5)
+        .await
+        .unwrap();
+    assert_eq!(texts(&diverse), ["alpha beta gamma", "alpha delta"]);
+    // lambda 1 keeps the similarity ranking
+    let similar = store
+        .search_diverse("doc", embedding("alpha beta"), None, 2, 1.0)
+        .await
+        .unwrap();
+    assert_eq!(texts(&similar), texts(&ranked));
+}
@@ -0,0 +1,281 @@
+// test harness: a mock of the ollama api, the app running on the in-memory vector store
+// and the helpers sending the requests through the router in-process
+mod agent;
+mod api;
+mod compare;
+mod evaluation;
+mod grounding;
+mod memory_store;
+mod openai;
+mod parents;
+mod query;
+mod summarizer;
+
+use std::{
+    collections::VecDeque,
+    io::{Cursor, Write},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+};
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, Request, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use tempfile::TempDir;
+use tower::ServiceExt;
+use zip::write::SimpleFileOptions;
+
+use crate::{
+    app,
+    catalog::Catalog,
+    memory_store::MemoryStore,
+    processor::Processor,
+    utils::{get_settings, ConfigVar},
+    vector_db::EMBEDDING_SIZE,
+    AppState, MEMORY_DB_URL,
+};
+
+// answer of the mock to the generate requests without a format
+pub const MOCK_ANSWER: &str = "The answer is in the context.";
+
+// MockOllama serves the embeddings & the generate api of ollama:
+// the embeddings are a deterministic bag of words, the prompts are recorded
+#[derive(Clone, Default)]
+pub struct MockOllama {
+    prompts: Arc<Mutex<Vec<String>>>,
+    // texts sent to the embeddings api
+    embedded: Arc<Mutex<Vec<String>>>,
+    // answers of the text generate requests, in order (then the canned answer)
+    answers: Arc<Mutex<VecDeque<String>>>,
+    // answers of the json generate requests (the structured outputs), in order
+    json_answers: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl MockOllama {
+    // start serves the mock on a free local port and returns its base url
+    async fn start(&self) -> String {
+        let router = Router::new()
+            .route("/api/embeddings", post(mock_embeddings))
+            .route("/api/generate", post(mock_generate))
+            .with_state(self.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        url
+    }
+
+    pub fn prompts(&self) -> Vec<String> {
+        self.prompts.lock().unwrap().clone()
+    }
+
+    pub fn embedded(&self) -> Vec<String> {
+        self.embedded.lock().unwrap().clone()
+    }
+
+    pub fn push_answer(&self, answer: &str) {
+        self.answers.lock().unwrap().push_back(answer.to_string());
+    }
+
+    pub fn push_json_answer(&self, answer: Value) {
+        self.json_answers
+            .lock()
+            .unwrap()
+            .push_back(answer.to_string());
+    }
+}
+
+async fn mock_embeddings(State(mock): State<MockOllama>, Json(body): Json<Value>) -> Json<Value> {
+    let prompt = body["prompt"].as_str().unwrap_or_default();
+    mock.embedded.lock().unwrap().push(prompt.to_string());
+    Json(json!({ "embedding": embedding(prompt) }))
+}
+
+async fn mock_generate(State(mock): State<MockOllama>, Json(body): Json<Value>) -> Response {
+    let prompt = body["prompt"].as_str().unwrap_or_default().to_string();
+    mock.prompts.lock().unwrap().push(prompt);
+    let response = if body.get("format").is_some() {
+        mock.json_answers
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or("{}".to_string())
+    } else {
+        mock.answers
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or(MOCK_ANSWER.to_string())
+    };
+    // the streamed answer comes word by word as json lines
+    if body["stream"] == true {
+        let mut lines = String::new();
+        for word in response.split_inclusive(' ') {
+            lines.push_str(&format!("{}\n", json!({ "response": word, "done": false })));
+        }
+        lines.push_str(&format!("{}\n", json!({ "response": "", "done": true })));
+        return lines.into_response();
+    }
+    Json(json!({ "response": response, "done": true })).into_response()
+}
+
+// embedding hashes the words of the text into a normalized vector,
+// the texts sharing words are similar
+pub fn embedding(text: &str) -> Vec<f32> {
+    let mut vector = vec![0.0f32; EMBEDDING_SIZE as usize];
+    for word in text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        // fnv-1a, stable across the runs unlike the std hasher
+        let hash = word
+            .to_lowercase()
+            .bytes()
+            .fold(0xcbf29ce484222325u64, |hash, b| {
+                (hash ^ b as u64).wrapping_mul(0x100000001b3)
+            });
+        vector[(hash % EMBEDDING_SIZE) as usize] += 1.0;
+    }
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+    vector
+}
+
+// TestApp is the app wired to the mock & the in-memory vector store
+pub struct TestApp {
+    pub router: Router,
+    pub ollama: MockOllama,
+    // the processor behind the router, for the cli commands
+    pub processor: Arc<Processor>,
+}
+
+impl TestApp {
+    pub async fn new() -> Self {
+        Self::with_settings(|_| {}).await
+    }
+
+    // with_settings starts the app with the settings changed by the test
+    pub async fn with_settings(configure: impl FnOnce(&mut ConfigVar)) -> Self {
+        workdir();
+        let ollama = MockOllama::default();
+        let url = ollama.start().await;
+        let mut settings = ConfigVar {
+            embedding_model_url: Some(format!("{}/api/embeddings", url)),
+            generate_model_url: Some(format!("{}/api/generate", url)),
+            db_url: Some(MEMORY_DB_URL.to_string()),
+            watch_dir: None,
+            generate_insights: Some(false),
+            ..get_settings()
+        };
+        configure(&mut settings);
+        let processor = Processor::new(settings, Box::new(MemoryStore::new()), Catalog::load());
+        let processor = Arc::new(processor);
+        let state = AppState {
+            processor: processor.clone(),
+            watcher: None,
+        };
+        Self {
+            router: app(state),
+            ollama,
+            processor,
+        }
+    }
+
+    // send runs the request through the router and reads back the status & the body
+    pub async fn send(&self, request: Request<Body>) -> (StatusCode, String) {
+        let response = self.router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, String::from_utf8_lossy(&body).to_string())
+    }
+
+    pub async fn get(&self, uri: &str) -> (StatusCode, String) {
+        self.send(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+    }
+
+    pub async fn post_json(&self, uri: &str, body: Value) -> (StatusCode, String) {
+        let request = Request::post(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        self.send(request).await
+    }
+
+    // upload sends the file as a multipart form along with the form fields
+    pub async fn upload(
+        &self,
+        file_name: &str,
+        content: &[u8],
+        fields: &[(&str, &str)],
+    ) -> (StatusCode, String) {
+        let boundary = "documind-test-boundary";
+        let mut body = vec![];
+        for (name, value) in fields {
+            body.extend(
+                format!(
+                    "--{boundary}\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n{value}\r\n"
+                )
+                .bytes(),
+            );
+        }
+        body.extend(
+            format!(
+                "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{file_name}\"\r\nContent-Type: application/octet-stream\r\n\r\n"
+            )
+            .bytes(),
+        );
+        body.extend_from_slice(content);
+        body.extend(format!("\r\n--{boundary}--\r\n").bytes());
+        let request = Request::post("/upload")
+            .header(
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(Body::from(body))
+            .unwrap();
+        self.send(request).await
+    }
+}
+
+// the uploads & the catalog are read relative to the working directory,
+// the tests share a temporary one
+fn workdir() {
+    static WORKDIR: OnceLock<TempDir> = OnceLock::new();
+    WORKDIR.get_or_init(|| {
+        let dir = tempfile::tempdir().unwrap();
+        std::env::set_current_dir(dir.path()).unwrap();
+        dir
+    });
+}
+
+// unique_name names the document of the test, the tests run at once in the same uploads folder
+pub fn unique_name(prefix: &str) -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    format!(
+        "{}-{}-{}",
+        prefix,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::SeqCst)
+    )
+}
+
+// zip_archive packs the entries (name & content) into a zip archive
+pub fn zip_archive(entries: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+    for (name, content) in entries {
+        zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+        zip.write_all(content).unwrap();
+    }
+    zip.finish().unwrap().into_inner()
+}
@@ -0,0 +1,127 @@
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+
+use super::{unique_name, TestApp, MOCK_ANSWER};
+use crate::openai::{question, ChatMessage};
+
+const REPORT_CSV: &str = "quarter,revenue\nq1,120 dollars\nq2,150 dollars\n";
+
+async fn upload_report(test_app: &TestApp) -> String {
+    let doc_name = unique_name("report");
+    let file_name = format!("{}.csv", doc_name);
+    let (status, body) = test_app
+        .upload(&file_name, REPORT_CSV.as_bytes(), &[])
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    doc_name
+}
+
+fn message(role: &str, content: Value) -> ChatMessage {
+    ChatMessage {
+        role: role.to_string(),
+        content,
+    }
+}
+
+#[test]
+fn the_last_user_message_is_asked_along_with_the_conversation() {
+    let messages = vec![
+        message("system", json!("You are helpful")),
+        message("user", json!("what was the revenue in q1?")),
+        message("assistant", json!("120 dollars")),
+        message("user", json!([{ "type": "text", "text": "and in q2?" }])),
+    ];
+    let (query, asked) = question(&messages).unwrap();
+    assert_eq!(query, "and in q2?");
+    assert!(asked.starts_with("and in q2?"));
+    assert!(asked.contains("user: what was the revenue in q1? / assistant: 120 dollars"));
+    assert!(!asked.contains("You are helpful"));
+
+    assert!(question(&[message("assistant", json!("hello"))]).is_none());
+}
+
+#[tokio::test]
+async fn documents_are_listed_and_answered_as_models() {
+    let test_app = TestApp::new().await;
+    let doc_name = upload_report(&test_app).await;
+
+    let (status, body) = test_app.get("/v1/models").await;
+    assert_eq!(status, StatusCode::OK);
+    let models: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(models["object"], "list");
+    assert!(models["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|model| model["id"] == doc_name.as_str() && model["object"] == "model"));
+
+    let (status, body) = test_app
+        .post_json(
+            "/v1/chat/completions",
+            json!({
+                "model": doc_name,
+                "messages": [{ "role": "user", "content": "what was the revenue in q2?" }],
+                "temperature": 0.2
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let completion: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(completion["object"], "chat.completion");
+    assert_eq!(completion["model"], doc_name.as_str());
+    assert_eq!(completion["choices"][0]["message"]["role"], "assistant");
+    assert_eq!(completion["choices"][0]["message"]["content"], MOCK_ANSWER);
+    assert_eq!(completion["choices"][0]["finish_reason"], "stop");
+    assert!(!completion["citations"].as_array().unwrap().is_empty());
+    let prompts = test_app.ollama.prompts();
+    let prompt = prompts.last().unwrap();
+    assert!(prompt.contains("150 dollars") && prompt.contains("revenue in q2"));
+
+    let (status, body) = test_app
+        .post_json(
+            "/v1/chat/completions",
+            json!({ "model": "missing", "messages": [{ "role": "user", "content": "hi" }] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let error: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(error["error"]["code"], "model_not_found");
+}
+
+#[tokio::test]
+async fn completions_are_streamed_as_server_sent_events() {
+    let test_app = TestApp::new().await;
+    let doc_name = upload_report(&test_app).await;
+
+    let (status, body) = test_app
+        .post_json(
+            "/v1/chat/completions",
+            json!({
+                "model": format!("{}.csv", doc_name),
+                "messages": [{ "role": "user", "content": "what was the revenue in q1?" }],
+                "stream": true
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let events: Vec<&str> = body
+        .lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .collect();
+    assert_eq!(events.last(), Some(&"[DONE]"));
+    let chunks: Vec<Value> = events[..events.len() - 1]
+        .iter()
+        .map(|event| serde_json::from_str(event).unwrap())
+        .collect();
+    assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+    let content: String = chunks
+        .iter()
+        .filter_map(|chunk| chunk["choices"][0]["delta"]["content"].as_str())
+        .collect();
+    assert_eq!(content, MOCK_ANSWER);
+    // the answer came in several pieces, the last chunk telling the end
+    assert!(chunks.len() > 3);
+    let last = chunks.last().unwrap();
+    assert_eq!(last["object"], "chat.completion.chunk");
+    assert_eq!(last["choices"][0]["finish_reason"], "stop");
+}
@@ -0,0 +1,110 @@
+use axum::http::StatusCode;
+use serde_json::{json, Map};
+
+use super::{unique_name, TestApp};
+use crate::{
+    extractor::{Chunk, CHUNK_INDEX_KEY, PARENT_INDEX_KEY, PARENT_TEXT_KEY},
+    metadata::DOC_NAME_KEY,
+    parents::{split_children, to_parents},
+};
+
+#[test]
+fn children_link_back_to_their_parent() {
+    let parents = vec![
+        Chunk::from("one two three four five six".to_string()),
+        Chunk::from("seven".to_string()),
+    ];
+    let children = split_children(parents, 10);
+    let texts: Vec<&str> = children.iter().map(|chunk| chunk.text.as_str()).collect();
+    assert_eq!(texts, ["one two", "three four", "five six", "seven"]);
+    assert_eq!(children[2].metadata[PARENT_INDEX_KEY], 0);
+    assert_eq!(
+        children[2].metadata[PARENT_TEXT_KEY],
+        "one two three four five six"
+    );
+    // the parent small enough is embedded as is
+    assert_eq!(children[3].metadata[PARENT_INDEX_KEY], 1);
+    assert!(!children[3].metadata.contains_key(PARENT_TEXT_KEY));
+
+    let parents = to_parents(vec![
+        children[1].clone(),
+        children[3].clone(),
+        children[0].clone(),
+    ]);
+    let texts: Vec<&str> = parents.iter().map(|chunk| chunk.text.as_str()).collect();
+    assert_eq!(texts, ["one two three four five six", "seven"]);
+    assert!(!parents[0].metadata.contains_key(PARENT_TEXT_KEY));
+}
+
+#[test]
+fn chunks_without_parent_are_kept_once() {
+    let chunk = |doc: &str, index: u64| {
+        let mut metadata = Map::new();
+        metadata.insert(DOC_NAME_KEY.to_string(), doc.into());
+        metadata.insert(CHUNK_INDEX_KEY.to_string(), index.into());
+        Chunk {
+            text: format!("{} {}", doc, index),
+            metadata,
+        }
+    };
+    let chunks = to_parents(vec![chunk("a", 1), chunk("b", 1), chunk("a", 1)]);
+    assert_eq!(chunks.len(), 2);
+}
+
+#[tokio::test]
+async fn children_are_matched_and_their_parent_is_prompted() {
+    let test_app = TestApp::with_settings(|settings| settings.child_chunk_size = Some(30)).await;
+    let file_name = format!("{}.csv", unique_name("catalogue"));
+    let mut csv = "item,description\n".to_string();
+    for i in 0..10 {
+        csv.push_str(&format!("item{i},description of item{i}\n"));
+    }
+    let (status, body) = test_app.upload(&file_name, csv.as_bytes(), &[]).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let (status, _) = test_app
+        .post_json(
+            "/prompt",
+            json!({ "user_query": "item7", "doc_name": file_name }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let prompts = test_app.ollama.prompts();
+    let prompt = prompts.last().unwrap();
+    // the whole sheet (the parent) once, though many of its children were retrieved
+    assert_eq!(prompt.matches("| item0 |").count(), 1, "{}", prompt);
+    assert_eq!(prompt.matches("| item9 |").count(), 1, "{}", prompt);
+}
+
+#[tokio::test]
+async fn neighbors_of_the_retrieved_chunks_are_prompted() {
+    // a row per chunk
+    let test_app =
+        TestApp::with_settings(|settings| settings.embedding_model_chunk_size = Some(10)).await;
+    let file_name = format!("{}.csv", unique_name("rows"));
+    let mut csv = "code\n".to_string();
+    for i in 0..30 {
+        csv.push_str(&format!("row{i}\n"));
+    }
+    let (status, body) = test_app.upload(&file_name, csv.as_bytes(), &[]).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    for neighbors in [0, 1] {
+        let (status, _) = test_app
+            .post_json(
+                "/prompt",
+                json!({ "user_query": "row15", "doc_name": file_name, "neighbors": neighbors }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let prompts = test_app.ollama.prompts();
+    assert!(prompts[0].contains("| row15 |"));
+    assert!(!prompts[0].contains("| row16 |"));
+    assert!(prompts[1].contains("| row14 |"), "{}", prompts[1]);
+    assert!(prompts[1].contains("| row16 |"));
+    // in the document order
+    let position = |row: &str| prompts[1].find(row).unwrap();
+    assert!(position("| row14 |") < position("| row15 |"));
+    assert!(position("| row15 |") < position("| row16 |"));
+}
@@ -0,0 +1,131 @@
+use axum::http::StatusCode;
+use serde_json::{json, Map};
+
+use super::{unique_name, TestApp, MOCK_ANSWER};
+use crate::{extractor::Chunk, query::fuse};
+
+const INVENTORY_CSV: &[u8] = b"item,location,stock
+widgets,berlin warehouse,120
+gadgets,paris warehouse,80
+";
+
+async fn upload(test_app: &TestApp) -> String {
+    let file_name = format!("{}.csv", unique_name("inventory"));
+    let (status, body) = test_app.upload(&file_name, INVENTORY_CSV, &[]).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    file_name
+}
+
+fn chunks(texts: &[&str]) -> Vec<Chunk> {
+    texts
+        .iter()
+        .map(|text| Chunk {
+            text: text.to_string(),
+            metadata: Map::new(),
+        })
+        .collect()
+}
+
+#[test]
+fn fusion_favours_the_chunks_ranked_by_many_queries() {
+    let fused = fuse(
+        vec![
+            chunks(&["a", "b", "c"]),
+            chunks(&["c", "d"]),
+            chunks(&["c", "b"]),
+        ],
+        3,
+    );
+    let texts: Vec<&str> = fused.iter().map(|chunk| chunk.text.as_str()).collect();
+    assert_eq!(texts, ["c", "b", "a"]);
+}
+
+#[tokio::test]
+async fn multi_query_searches_every_variant() {
+    let test_app = TestApp::new().await;
+    let file_name = upload(&test_app).await;
+    test_app
+        .ollama
+        .push_answer("1. widgets stock level\n2. \"how many widgets in berlin\"\n");
+
+    let (status, body) = test_app
+        .post_json(
+            "/prompt",
+            json!({ "user_query": "widgets?", "doc_name": file_name, "strategy": "multi_query" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body, MOCK_ANSWER);
+    let embedded = test_app.ollama.embedded();
+    for query in [
+        "widgets?",
+        "widgets stock level",
+        "how many widgets in berlin",
+    ] {
+        assert!(embedded.iter().any(|text| text == query), "{:?}", embedded);
+    }
+    // the answer is generated for the question as asked
+    let prompts = test_app.ollama.prompts();
+    assert!(prompts[0].contains("different search queries"));
+    assert!(prompts[1].contains("Question: widgets?"));
+    assert!(prompts[1].contains("berlin warehouse"));
+}
+
+#[tokio::test]
+async fn hyde_searches_the_hypothetical_answer() {
+    let test_app = TestApp::new().await;
+    let file_name = upload(&test_app).await;
+    let passage = "The paris warehouse holds 80 gadgets.";
+    test_app.ollama.push_answer(passage);
+
+    let (status, _) = test_app
+        .post_json(
+            "/prompt",
+            json!({ "user_query": "where are the gadgets?", "doc_name": file_name, "strategy": "hyde" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let embedded = test_app.ollama.embedded();
+    assert!(embedded.iter().any(|text| text == passage));
+    assert!(!embedded.iter().any(|text| text == "where are the gadgets?"));
+}
+
+#[tokio::test]
+async fn rewrite_searches_the_rewritten_query() {
+    let test_app = TestApp::new().await;
+    let file_name = upload(&test_app).await;
+    test_app
+        .ollama
+        .push_answer("\"stock of widgets in the berlin warehouse\"");
+
+    let (status, _) = test_app
+        .post_json(
+            "/prompt",
+            json!({ "user_query": "widgets?", "doc_name": file_name, "strategy": "rewrite" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let embedded = test_app.ollama.embedded();
+    assert!(embedded
+        .iter()
+        .any(|text| text == "stock of widgets in the berlin warehouse"));
+}
+
+#[tokio::test]
+async fn a_long_query_is_embedded_whole() {
+    let test_app =
+        TestApp::with_settings(|settings| settings.embedding_model_chunk_size = Some(20)).await;
+    let file_name = upload(&test_app).await;
+    let query = "how many widgets are stored in the berlin warehouse right now?";
+
+    let (status, body) = test_app
+        .post_json(
+            "/prompt",
+            json!({ "user_query": query, "doc_name": file_name }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let embedded = test_app.ollama.embedded();
+    assert_eq!(embedded.iter().filter(|text| *text == query).count(), 1);
+    assert!(test_app.ollama.prompts()[0].contains("berlin warehouse"));
+}
@@ -0,0 +1,69 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+
+use super::{unique_name, TestApp, MOCK_ANSWER};
+
+// upload_rows uploads a sheet of the rows and returns its document name
+async fn upload_rows(test_app: &TestApp, rows: usize) -> String {
+    let doc_name = unique_name("ledger");
+    let mut csv = "entry,description\n".to_string();
+    for i in 0..rows {
+        csv.push_str(&format!(
+            "entry{i},payment number {i} received from the customer account\n"
+        ));
+    }
+    let (status, body) = test_app
+        .upload(&format!("{}.csv", doc_name), csv.as_bytes(), &[])
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    doc_name
+}
+
+async fn summarize(test_app: &TestApp, doc_name: &str, body: Body) -> (StatusCode, String) {
+    let request = Request::post(format!("/documents/{}/summarize", doc_name))
+        .body(body)
+        .unwrap();
+    test_app.send(request).await
+}
+
+#[tokio::test]
+async fn summarize_needs_no_body() {
+    let test_app = TestApp::new().await;
+    let doc_name = upload_rows(&test_app, 3).await;
+
+    let (status, body) = summarize(&test_app, &doc_name, Body::empty()).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(body.contains("event: summary"), "{}", body);
+    assert!(body.contains(MOCK_ANSWER), "{}", body);
+
+    let (status, _) = summarize(&test_app, &doc_name, Body::from("{\"style\": \"poem\"}")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn long_partial_summaries_are_reduced_without_cutting() {
+    let test_app = TestApp::new().await;
+    // about 2 batches of text
+    let doc_name = upload_rows(&test_app, 300).await;
+    // partial summaries too long to fit together into a batch
+    let long_summary = |marker: &str| format!("{} {}", "detail ".repeat(1000), marker);
+    test_app.ollama.push_answer(&long_summary("first-end"));
+    test_app.ollama.push_answer(&long_summary("second-end"));
+
+    let (status, body) = summarize(&test_app, &doc_name, Body::empty()).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(body.contains("event: summary"), "{}", body);
+    let prompts = test_app.ollama.prompts();
+    let map_prompts = prompts
+        .iter()
+        .filter(|prompt| prompt.contains("part of a document"))
+        .count();
+    assert_eq!(map_prompts, 2);
+    // both partial summaries reach the final summary in full
+    let final_prompt = prompts.last().unwrap();
+    assert!(final_prompt.contains("executive summary"));
+    assert!(final_prompt.contains("first-end") && final_prompt.contains("second-end"));
+    assert!(body.contains(MOCK_ANSWER));
+}
@@ -1,4 +1,4 @@
-use std::{fs, io::Write};
+use std::{collections::HashMap, fs, io::Write};
 
 use axum::{
     extract::{Multipart, Request},
@@ -13,6 +13,15 @@ use tracing::{debug, info};
 
 use anyhow::{anyhow, Context, Ok, Result};
 
+use crate::{
+    extractor::{
+        file_extension, is_supported, unpack_archive, SkippedEntry, ARCHIVE_EXTENSION,
+        SUPPORTED_EXTENSIONS,
+    },
+    metadata::DocMetadata,
+    query::QueryStrategy,
+};
+
 pub enum ModelKind {
     Generate,
     Embedding,
@@ -34,6 +43,23 @@ pub struct ConfigVar {
     pub generate_model_name: Option<String>,
     pub db_url: Option<String>,
     pub embedding_model_chunk_size: Option<usize>,
+    // folder whose files are kept in sync with the documents
+    pub watch_dir: Option<String>,
+    pub watch_debounce_ms: Option<u64>,
+    // generate the summary & the suggested questions of every ingested document (default on)
+    pub generate_insights: Option<bool>,
+    // transformation of the user query before the retrieval (raw, rewrite, multi_query or hyde)
+    pub query_strategy: Option<QueryStrategy>,
+    // diversify the context by maximal marginal relevance (0 the most diverse, 1 the most similar)
+    pub mmr_lambda: Option<f32>,
+    // characters of the child chunks embedded in place of the chunks (the parents fed to the prompt)
+    pub child_chunk_size: Option<usize>,
+    // chunks added on each side of every retrieved chunk
+    pub context_neighbors: Option<usize>,
+    // searches of the agent before it answers
+    pub agent_max_steps: Option<usize>,
+    // folders whose source code directories may be ingested (POST /directory)
+    pub source_roots: Option<Vec<String>>,
 }
 
 impl ConfigVar {
@@ -77,47 +103,131 @@ pub fn get_settings() -> ConfigVar {
     config
 }
 
-// extract the file content
-pub fn extract_file_content(file_name: &str) -> Result<String> {
-    let file_path = format!("./uploads/{}", file_name);
-    match pdf_extract::extract_text(file_path) {
-        std::result::Result::Ok(content) => Ok(content),
-        Err(e) => {
-            debug!("failed to read the file: {}", e.to_string());
-            Err(anyhow::Error::new(e).context("Failed to read the file"))
-        }
+// cosine similarity of the two embeddings, 0 when either is empty
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
     }
 }
 
-// chunk the large text based on chunk size
-pub fn chunk_text(text: &str, chunk_size: usize) -> Vec<String> {
-    text.chars()
-        .collect::<Vec<char>>()
-        .chunks(chunk_size)
-        .map(|chunk| chunk.iter().collect())
-        .collect()
-}
-
 // send_request helps to send the request to ollama api
 pub async fn send_request(url: &str, model_name: &str, prompt: &str) -> Result<String> {
-    let client = Client::new();
-
     let req_body = json!({
         "model": model_name,
         "prompt": prompt,
         "stream": false,
     });
+    post_request(url, &req_body).await
+}
 
+async fn post_request(url: &str, req_body: &serde_json::Value) -> Result<String> {
+    let client = Client::new();
     let response = client
         .post(url)
         .header("Content-Type", "application/json")
-        .json(&req_body)
+        .json(req_body)
         .send()
         .await?;
     let text_result = response.text().await?;
     Ok(text_result)
 }
 
+// generate_text sends the prompt to the generation model and reads back its answer
+pub async fn generate_text(settings: &ConfigVar, prompt: &str) -> Result<String> {
+    generate(settings, prompt, None).await
+}
+
+// generate_json asks the generation model for a json answer following the json schema
+// (the ollama structured outputs), the answer is returned as text to be parsed by the caller
+pub async fn generate_json(
+    settings: &ConfigVar,
+    prompt: &str,
+    schema: &serde_json::Value,
+) -> Result<String> {
+    generate(settings, prompt, Some(schema)).await
+}
+
+// generate_stream streams the answer of the generation model, every piece of text being handed
+// to on_text as soon as it is generated, and reads back the whole answer
+pub async fn generate_stream(
+    settings: &ConfigVar,
+    prompt: &str,
+    mut on_text: impl FnMut(&str),
+) -> Result<String> {
+    let (model_url, model_name) = settings
+        .get_model_details(ModelKind::Generate)
+        .context("unable to fetch the model details")?;
+    let req_body = json!({
+        "model": model_name,
+        "prompt": prompt,
+        "stream": true,
+    });
+    let mut response = Client::new()
+        .post(model_url.as_str())
+        .header("Content-Type", "application/json")
+        .json(&req_body)
+        .send()
+        .await
+        .context("send request to LLM operation failed")?;
+    // the answer comes as json lines, a line possibly split across the chunks of the body
+    let mut pending: Vec<u8> = vec![];
+    let mut answer = String::new();
+    while let Some(bytes) = response.chunk().await? {
+        pending.extend_from_slice(&bytes);
+        while let Some(end) = pending.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            if line.trim().is_empty() {
+                continue;
+            }
+            let res_json: serde_json::Value = serde_json::from_str(line.trim())
+                .context("parsing response into value type failed")?;
+            if let Some(error) = res_json["error"].as_str() {
+                return Err(anyhow!("the generation failed: {}", error));
+            }
+            if let Some(text) = res_json["response"]
+                .as_str()
+                .filter(|text| !text.is_empty())
+            {
+                on_text(text);
+                answer.push_str(text);
+            }
+        }
+    }
+    Ok(answer)
+}
+
+async fn generate(
+    settings: &ConfigVar,
+    prompt: &str,
+    format: Option<&serde_json::Value>,
+) -> Result<String> {
+    let (model_url, model_name) = settings
+        .get_model_details(ModelKind::Generate)
+        .context("unable to fetch the model details")?;
+    let mut req_body = json!({
+        "model": model_name,
+        "prompt": prompt,
+        "stream": false,
+    });
+    if let Some(format) = format {
+        req_body["format"] = format.clone();
+    }
+    let res = post_request(model_url.as_str(), &req_body)
+        .await
+        .context("send request to LLM operation failed")?;
+    let res_json: serde_json::Value =
+        serde_json::from_str(res.as_str()).context("parsing response into value type failed")?;
+    let response: String = serde_json::from_value(res_json["response"].clone())
+        .context("parsing string from value type failed")?;
+    Ok(response)
+}
+
 // get the content embeddings from the embedding gen model
 pub async fn get_content_embeddings(settings: ConfigVar, content: &str) -> Result<Vec<f32>> {
     let (model_url, model_name) = settings.get_model_details(ModelKind::Embedding).unwrap();
@@ -134,18 +244,80 @@ pub async fn get_content_embeddings(settings: ConfigVar, content: &str) -> Resul
     Ok(embeddings)
 }
 
-// Read the pdf file
-pub async fn read_file(mut multipart: Multipart) -> Result<Vec<String>> {
+// UploadedFiles holds the saved file names along with the metadata sent in the form.
+// The passwords are only kept in memory for the extraction and never persisted.
+pub struct UploadedFiles {
+    pub file_names: Vec<String>,
+    pub metadata: DocMetadata,
+    // password used for all the files
+    password: Option<String>,
+    // password per file name
+    passwords: HashMap<String, String>,
+    // the (archive, entry path) the file was extracted from
+    pub sources: HashMap<String, (String, String)>,
+    // the archive entries which were not extracted
+    pub skipped: Vec<(String, SkippedEntry)>,
+    // collection gathering all the uploaded documents
+    pub workspace: Option<String>,
+}
+
+impl UploadedFiles {
+    pub fn password_for(&self, file_name: &str) -> Option<&str> {
+        self.passwords
+            .get(file_name)
+            .or(self.password.as_ref())
+            .map(|password| password.as_str())
+    }
+}
+
+// Read the uploaded files along with the optional metadata fields (tags, doc_type, metadata),
+// the passwords of the encrypted files (password, passwords) and the workspace.
+// The supported entries of the zip archives are extracted as uploaded files.
+pub async fn read_file(mut multipart: Multipart) -> Result<UploadedFiles> {
     let mut uploaded_files = vec![];
+    let mut metadata = DocMetadata::default();
+    let mut password = None;
+    let mut passwords = HashMap::new();
+    let mut sources = HashMap::new();
+    let mut skipped = vec![];
+    let mut workspace = None;
     while let Some(mut field) = multipart.next_field().await.map_err(|e| {
-        std::io::Error::new(
-            std::io::ErrorKind::Other,
-            format!("error status:: {} and text {}", e.status(), e.body_text()),
-        )
+        std::io::Error::other(format!(
+            "error status:: {} and text {}",
+            e.status(),
+            e.body_text()
+        ))
     })? {
+        if field.file_name().is_none() {
+            let field_name = field.name().unwrap_or_default().to_string();
+            let value = field.text().await?;
+            match field_name.as_str() {
+                "tags" => metadata.set_tags(value.as_str()),
+                "doc_type" => metadata.doc_type = Some(value.trim().to_lowercase()),
+                "metadata" => {
+                    metadata.metadata = serde_json::from_str(value.as_str())
+                        .context("metadata is expected to be a json object of strings")?
+                }
+                "password" if !value.is_empty() => password = Some(value),
+                "passwords" => {
+                    passwords = serde_json::from_str(value.as_str())
+                        .context("passwords is expected to be a json object of file names")?
+                }
+                "workspace" if !value.trim().is_empty() => {
+                    workspace = Some(value.trim().to_string())
+                }
+                _ => debug!("ignoring the unknown form field: {}", field_name),
+            }
+            continue;
+        }
         if let Some(file_name) = field.file_name().map(|name| name.to_string()) {
-            if !is_pdf(file_name.as_str()) {
-                return Err(anyhow!("Only Pdf files are allowed"));
+            let is_archive = file_extension(file_name.as_str()) == ARCHIVE_EXTENSION;
+            if !is_supported(file_name.as_str()) && !is_archive {
+                return Err(anyhow!(
+                    "Only {}, {} files are allowed",
+                    SUPPORTED_EXTENSIONS.join(", "),
+                    ARCHIVE_EXTENSION
+                ));
             }
 
             let mut data = Vec::new();
@@ -157,23 +329,39 @@ pub async fn read_file(mut multipart: Multipart) -> Result<Vec<String>> {
             // Save to server
             let file_path = format!("./uploads/{}", file_name);
             save_file(&file_path, &data).context("error occurred while saving the file")?;
+            if is_archive {
+                let unpacked = unpack_archive(&file_path, "./uploads");
+                let _ = fs::remove_file(&file_path);
+                let unpacked =
+                    unpacked.with_context(|| format!("unable to unpack {}", file_name))?;
+                for (entry, entry_file) in unpacked.files {
+                    sources.insert(entry_file.to_string(), (file_name.to_string(), entry));
+                    uploaded_files.push(entry_file);
+                }
+                skipped.extend(
+                    unpacked
+                        .skipped
+                        .into_iter()
+                        .map(|entry| (file_name.to_string(), entry)),
+                );
+                continue;
+            }
             uploaded_files.push(file_name);
         }
     }
 
-    if uploaded_files.is_empty() {
-        return Err(anyhow!("No valid PDF files were uploaded."));
+    if uploaded_files.is_empty() && skipped.is_empty() {
+        return Err(anyhow!("No valid files were uploaded."));
     }
-    Ok(uploaded_files)
-}
-
-// Validate PDF File Extension
-fn is_pdf(file_name: &str) -> bool {
-    std::path::Path::new(file_name)
-        .extension()
-        .and_then(|ext| ext.to_str())
-        .map(|ext| ext.eq_ignore_ascii_case("pdf"))
-        .unwrap_or(false)
+    Ok(UploadedFiles {
+        file_names: uploaded_files,
+        metadata,
+        password,
+        passwords,
+        sources,
+        skipped,
+        workspace,
+    })
 }
 
 // Save File to Server
@@ -1,16 +1,153 @@
 use std::collections::HashMap;
 
 use anyhow::{anyhow, Context, Ok, Result};
+use async_trait::async_trait;
 use qdrant_client::{
     qdrant::{
-        CreateCollectionBuilder, Distance, PointId, PointStruct, SearchPoints, UpsertPointsBuilder,
-        Value, VectorParamsBuilder, Vectors,
+        vector_output, vectors_output::VectorsOptions, Condition, CreateCollectionBuilder,
+        CreateFieldIndexCollectionBuilder, DeletePointsBuilder, Distance, FieldType, Filter,
+        PointId, PointStruct, Range, ScoredPoint, ScrollPointsBuilder, SearchPoints,
+        UpsertPointsBuilder, Value, VectorParamsBuilder, Vectors, VectorsOutput,
     },
-    Qdrant,
+    Payload, Qdrant,
 };
 use tracing::info;
 
-pub struct VectorStore {
+use crate::extractor::{
+    Chunk, CHUNK_INDEX_KEY, CHUNK_TYPE_KEY, LINE_START_KEY, PAGE_START_KEY, ROW_START_KEY,
+    SECTION_INDEX_KEY, SENDER_KEY, SENT_AT_KEY, SLIDE_KEY, TEXT_KEY,
+};
+use crate::metadata::{
+    DocMetadata, SearchFilter, CUSTOM_KEY, DOC_NAME_KEY, DOC_TYPE_KEY, TAGS_KEY, UPLOADED_AT_KEY,
+};
+use crate::utils::cosine_similarity;
+
+// chunks of the context sent to the LLM per prompt chunk
+pub const CONTEXT_LIMIT: u64 = 6;
+// dimension of the embedding vectors (nomic-embed-text)
+pub const EMBEDDING_SIZE: u64 = 768;
+// candidates fetched per chunk selected by maximal marginal relevance
+const MMR_FETCH_FACTOR: u64 = 4;
+// points read per scroll request
+const SCROLL_LIMIT: u32 = 256;
+
+// payload keys ordering the chunks of the document,
+// the chunks stored before the chunk index fall back on their location
+const ORDER_KEYS: [&str; 6] = [
+    CHUNK_INDEX_KEY,
+    PAGE_START_KEY,
+    SECTION_INDEX_KEY,
+    SLIDE_KEY,
+    ROW_START_KEY,
+    LINE_START_KEY,
+];
+
+// VectorStore keeps the chunk embeddings of the documents along with their payload,
+// a collection holds a document or the documents of a workspace
+#[async_trait]
+pub trait VectorStore: Send + Sync {
+    // saves the vector embeddings to database
+    // The document is saved into its own collection unless the collection is a workspace
+    async fn store_embeddings(
+        &self,
+        collection_name: &str,
+        doc_name: &str,
+        embeddings: Vec<(String, Vec<f32>, Chunk)>,
+        metadata: &DocMetadata,
+    ) -> Result<()>;
+
+    // search for the similar points along with payload
+    // payload to be sent to LLM as context.
+    // The optional filter narrows down the points based on the document metadata,
+    // the context is diversified by maximal marginal relevance when a lambda is given.
+    async fn search_result(
+        &self,
+        collection_name: &str,
+        query: Vec<f32>,
+        filter: Option<&SearchFilter>,
+        mmr_lambda: Option<f32>,
+    ) -> Result<Vec<Chunk>> {
+        match mmr_lambda {
+            Some(lambda) => {
+                self.search_diverse(collection_name, query, filter, CONTEXT_LIMIT, lambda)
+                    .await
+            }
+            None => {
+                self.search_ranked(collection_name, query, filter, CONTEXT_LIMIT)
+                    .await
+            }
+        }
+    }
+
+    // search_ranked returns the given number of the most similar chunks, most similar first
+    async fn search_ranked(
+        &self,
+        collection_name: &str,
+        query: Vec<f32>,
+        filter: Option<&SearchFilter>,
+        limit: u64,
+    ) -> Result<Vec<Chunk>>;
+
+    // search_candidates ranks the chunks like search_ranked along with their vectors
+    async fn search_candidates(
+        &self,
+        collection_name: &str,
+        query: Vec<f32>,
+        filter: Option<&SearchFilter>,
+        limit: u64,
+    ) -> Result<Vec<(Chunk, Vec<f32>)>>;
+
+    // search_diverse selects the chunks out of the over-fetched candidates
+    // by maximal marginal relevance, leaving out the near duplicates of the chunks selected
+    async fn search_diverse(
+        &self,
+        collection_name: &str,
+        query: Vec<f32>,
+        filter: Option<&SearchFilter>,
+        limit: u64,
+        lambda: f32,
+    ) -> Result<Vec<Chunk>> {
+        let candidates = self
+            .search_candidates(
+                collection_name,
+                query.clone(),
+                filter,
+                limit * MMR_FETCH_FACTOR,
+            )
+            .await?;
+        Ok(mmr(&query, candidates, limit as usize, lambda))
+    }
+
+    // document_chunks reads all the chunks of the document in their order
+    async fn document_chunks(&self, collection_name: &str, doc_name: &str) -> Result<Vec<Chunk>>;
+
+    // document_vectors reads all the chunks of the document in their order along with their vectors
+    async fn document_vectors(
+        &self,
+        collection_name: &str,
+        doc_name: &str,
+    ) -> Result<Vec<(Chunk, Vec<f32>)>>;
+
+    // chunks_at reads the chunks of the document whose index under the key
+    // (the chunk index or the parent index) is one of the given indexes
+    async fn chunks_at(
+        &self,
+        collection_name: &str,
+        doc_name: &str,
+        key: &str,
+        indexes: &[u64],
+    ) -> Result<Vec<Chunk>>;
+
+    // delete_document removes the chunks of the document,
+    // along with its collection unless the collection is a shared workspace
+    async fn delete_document(&self, collection_name: &str, doc_name: &str) -> Result<()>;
+
+    //list out the collection names
+    async fn list_collections(&self) -> Result<Vec<String>>;
+}
+
+// QdrantStore is the vector store backed by qdrant
+pub struct QdrantStore {
     client: Qdrant,
 }
 
@@ -18,20 +155,192 @@ pub struct VectorStore {
 fn db_init(url: &str) -> Qdrant {
     let qdrant_client = match Qdrant::from_url(url).build() {
         std::result::Result::Ok(client) => client,
-        Err(err) => panic!("unable to connect to DB! {}", err.to_string()),
+        Err(err) => panic!("unable to connect to DB! {}", err),
     };
-    return qdrant_client;
+    qdrant_client
+}
+
+// build the common payload saved along with every chunk of the document
+pub fn doc_payload(doc_name: &str, metadata: &DocMetadata) -> HashMap<String, Value> {
+    let custom: serde_json::Map<String, serde_json::Value> = metadata
+        .metadata
+        .iter()
+        .map(|(k, v)| (k.to_string(), serde_json::Value::from(v.as_str())))
+        .collect();
+    let mut payload = HashMap::from([
+        (DOC_NAME_KEY.to_string(), Value::from(doc_name)),
+        (
+            TAGS_KEY.to_string(),
+            Value::from(serde_json::Value::from(metadata.tags.clone())),
+        ),
+        (
+            UPLOADED_AT_KEY.to_string(),
+            Value::from(metadata.uploaded_at),
+        ),
+        (
+            CUSTOM_KEY.to_string(),
+            Value::from(serde_json::Value::Object(custom)),
+        ),
+    ]);
+    if let Some(doc_type) = &metadata.doc_type {
+        payload.insert(DOC_TYPE_KEY.to_string(), Value::from(doc_type.as_str()));
+    }
+    payload
+}
+
+// point_payload is the payload of the chunk: the document payload,
+// the position & the location metadata of the chunk and its text
+pub fn point_payload(
+    doc_payload: &HashMap<String, Value>,
+    index: usize,
+    chunk: Chunk,
+) -> HashMap<String, Value> {
+    let mut payload = doc_payload.clone();
+    payload.insert(CHUNK_INDEX_KEY.to_string(), Value::from(index as i64));
+    payload.extend(
+        chunk
+            .metadata
+            .into_iter()
+            .map(|(key, value)| (key, Value::from(value))),
+    );
+    payload.insert(TEXT_KEY.to_string(), Value::from(chunk.text));
+    payload
+}
+
+// sort_chunks puts the chunks of the document back in their order
+pub fn sort_chunks(chunks: &mut [Chunk]) {
+    chunks.sort_by_key(order_key);
+}
+
+// order_key is the position of the chunk within its document
+pub fn order_key(chunk: &Chunk) -> [u64; ORDER_KEYS.len()] {
+    ORDER_KEYS.map(|key| {
+        chunk
+            .metadata
+            .get(key)
+            .and_then(|v| v.as_u64())
+            .unwrap_or(u64::MAX)
+    })
+}
+
+// mmr selects the chunks one by one, each maximizing its similarity to the query (weighted by lambda)
+// minus its highest similarity to the chunks already selected (weighted by 1 - lambda):
+// lambda 1 keeps the similarity ranking, lambda 0 favours the diversity only
+pub fn mmr(
+    query: &[f32],
+    candidates: Vec<(Chunk, Vec<f32>)>,
+    limit: usize,
+    lambda: f32,
+) -> Vec<Chunk> {
+    let lambda = lambda.clamp(0.0, 1.0);
+    let mut remaining: Vec<(f32, Chunk, Vec<f32>)> = candidates
+        .into_iter()
+        .map(|(chunk, vector)| (cosine_similarity(query, &vector), chunk, vector))
+        .collect();
+    let mut selected: Vec<(Chunk, Vec<f32>)> = vec![];
+    while selected.len() < limit && !remaining.is_empty() {
+        let scores = remaining.iter().map(|(relevance, _, vector)| {
+            let redundancy = selected
+                .iter()
+                .map(|(_, chosen)| cosine_similarity(vector, chosen))
+                .fold(0.0f32, f32::max);
+            lambda * relevance - (1.0 - lambda) * redundancy
+        });
+        // the first of the best scores: the most similar of the tied candidates
+        let best = scores
+            .enumerate()
+            .fold((0, f32::MIN), |best, (i, score)| {
+                if score > best.1 {
+                    (i, score)
+                } else {
+                    best
+                }
+            })
+            .0;
+        let (_, chunk, vector) = remaining.remove(best);
+        selected.push((chunk, vector));
+    }
+    selected.into_iter().map(|(chunk, _)| chunk).collect()
+}
+
+// dense_vector reads the vector of the scored point
+fn dense_vector(vectors: Option<VectorsOutput>) -> Option<Vec<f32>> {
+    match vectors?.vectors_options? {
+        VectorsOptions::Vector(vector) => match vector.vector {
+            Some(vector_output::Vector::Dense(dense)) => Some(dense.data),
+            _ => (!vector.data.is_empty()).then_some(vector.data),
+        },
+        VectorsOptions::Vectors(_) => None,
+    }
+}
+
+// to_chunk reads back the chunk text along with its location metadata from the payload
+fn to_chunk(payload: HashMap<String, Value>) -> Option<Chunk> {
+    let mut metadata = serde_json::Map::from(Payload::from(payload));
+    let text = metadata.remove(TEXT_KEY)?.as_str()?.to_string();
+    Some(Chunk { text, metadata })
+}
+
+// translate the search filter into the qdrant filter conditions
+fn to_qdrant_filter(filter: &SearchFilter) -> Option<Filter> {
+    if filter.is_empty() {
+        return None;
+    }
+    let mut conditions = vec![];
+    if let Some(tags) = filter.tags.as_ref().filter(|t| !t.is_empty()) {
+        conditions.push(Condition::matches(TAGS_KEY, tags.clone()));
+    }
+    if let Some(doc_type) = &filter.doc_type {
+        conditions.push(Condition::matches(DOC_TYPE_KEY, doc_type.to_string()));
+    }
+    if let Some(chunk_type) = &filter.chunk_type {
+        conditions.push(Condition::matches(CHUNK_TYPE_KEY, chunk_type.to_string()));
+    }
+    if filter.uploaded_after.is_some() || filter.uploaded_before.is_some() {
+        conditions.push(Condition::range(
+            UPLOADED_AT_KEY,
+            Range {
+                gte: filter.uploaded_after.map(|t| t as f64),
+                lte: filter.uploaded_before.map(|t| t as f64),
+                ..Default::default()
+            },
+        ));
+    }
+    if let Some(sender) = &filter.sender {
+        conditions.push(Condition::matches(SENDER_KEY, sender.to_lowercase()));
+    }
+    if filter.sent_after.is_some() || filter.sent_before.is_some() {
+        conditions.push(Condition::range(
+            SENT_AT_KEY,
+            Range {
+                gte: filter.sent_after.map(|t| t as f64),
+                lte: filter.sent_before.map(|t| t as f64),
+                ..Default::default()
+            },
+        ));
+    }
+    if let Some(doc_name) = &filter.doc_name {
+        conditions.push(Condition::matches(DOC_NAME_KEY, doc_name.to_string()));
+    }
+    for (key, value) in filter.metadata.iter() {
+        conditions.push(Condition::matches(
+            format!("{}.{}", CUSTOM_KEY, key),
+            value.to_string(),
+        ));
+    }
+    Some(Filter::must(conditions))
 }
 
 // impl the vector store
-impl VectorStore {
+impl QdrantStore {
     pub fn new(db_url: &str) -> Self {
         let db_client = db_init(db_url);
         Self { client: db_client }
     }
 
-    // create_collection to be a private method. Collection to be created based on doc name
-    async fn create_collection(&self, collection_name: &str) -> Result<()> {
+    // create_collection to be a private method. Collection to be created based on doc name,
+    // a workspace collection is shared by many documents and created by the first of them
+    async fn create_collection(&self, collection_name: &str, workspace: bool) -> Result<()> {
         let collection_exists = self
             .client
             .collection_exists(collection_name)
@@ -42,7 +351,7 @@ impl VectorStore {
                 .client
                 .create_collection(
                     CreateCollectionBuilder::new(collection_name)
-                        .vectors_config(VectorParamsBuilder::new(768, Distance::Cosine)),
+                        .vectors_config(VectorParamsBuilder::new(EMBEDDING_SIZE, Distance::Cosine)),
                 )
                 .await
                 .context("create new collection failed")?;
@@ -52,22 +361,144 @@ impl VectorStore {
                 return Err(anyhow!("unable to create the new collection"));
             }
         }
+        if workspace {
+            return Ok(());
+        }
         Err(anyhow!("collection already exists"))
     }
 
-    // saves the vector embeddings to database
-    pub async fn store_embeddings(
+    // search_points runs the similarity search, along with the vectors of the points if asked
+    async fn search_points(
+        &self,
+        collection_name: &str,
+        query: Vec<f32>,
+        filter: Option<&SearchFilter>,
+        limit: u64,
+        with_vectors: bool,
+    ) -> Result<Vec<ScoredPoint>> {
+        let search_result = self
+            .client
+            .search_points(SearchPoints {
+                collection_name: collection_name.to_string(),
+                vector: query,
+                limit,
+                with_payload: Some(true.into()),
+                with_vectors: with_vectors.then(|| true.into()),
+                filter: filter.and_then(to_qdrant_filter),
+                ..Default::default()
+            })
+            .await
+            .context("unable to fetch the results")?;
+        Ok(search_result.result)
+    }
+
+    // scroll_chunks reads the chunks matching the filter, scrolling through them page by page
+    async fn scroll_chunks(
+        &self,
+        collection_name: &str,
+        filter: Option<Filter>,
+    ) -> Result<Vec<Chunk>> {
+        let points = self.scroll_points(collection_name, filter, false).await?;
+        Ok(points.into_iter().map(|(chunk, _)| chunk).collect())
+    }
+
+    // scroll_points reads the chunks matching the filter along with their vectors if asked
+    async fn scroll_points(
+        &self,
+        collection_name: &str,
+        filter: Option<Filter>,
+        with_vectors: bool,
+    ) -> Result<Vec<(Chunk, Option<Vec<f32>>)>> {
+        let mut points = vec![];
+        let mut offset: Option<PointId> = None;
+        loop {
+            let mut request = ScrollPointsBuilder::new(collection_name)
+                .limit(SCROLL_LIMIT)
+                .with_payload(true)
+                .with_vectors(with_vectors);
+            if let Some(filter) = filter.clone() {
+                request = request.filter(filter);
+            }
+            if let Some(offset) = offset {
+                request = request.offset(offset);
+            }
+            let response = self
+                .client
+                .scroll(request)
+                .await
+                .context("scroll operation failed")?;
+            points.extend(
+                response
+                    .result
+                    .into_iter()
+                    .filter_map(|p| Some((to_chunk(p.payload)?, dense_vector(p.vectors)))),
+            );
+            offset = response.next_page_offset;
+            if offset.is_none() {
+                break;
+            }
+        }
+        Ok(points)
+    }
+
+    // create_payload_indexes indexes the metadata fields used by the search filters
+    async fn create_payload_indexes(
+        &self,
+        collection_name: &str,
+        metadata: &DocMetadata,
+    ) -> Result<()> {
+        let mut fields = vec![
+            (TAGS_KEY.to_string(), FieldType::Keyword),
+            (DOC_TYPE_KEY.to_string(), FieldType::Keyword),
+            (CHUNK_TYPE_KEY.to_string(), FieldType::Keyword),
+            (UPLOADED_AT_KEY.to_string(), FieldType::Integer),
+            (SENDER_KEY.to_string(), FieldType::Keyword),
+            (SENT_AT_KEY.to_string(), FieldType::Integer),
+        ];
+        fields.extend(
+            metadata
+                .metadata
+                .keys()
+                .map(|key| (format!("{}.{}", CUSTOM_KEY, key), FieldType::Keyword)),
+        );
+        for (field, field_type) in fields {
+            self.client
+                .create_field_index(
+                    CreateFieldIndexCollectionBuilder::new(
+                        collection_name,
+                        field.as_str(),
+                        field_type,
+                    )
+                    .wait(true),
+                )
+                .await
+                .with_context(|| format!("create_field_index operation failed for {}", field))?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl VectorStore for QdrantStore {
+    async fn store_embeddings(
         &self,
         collection_name: &str,
-        embeddings: Vec<(String, Vec<f32>, String)>,
+        doc_name: &str,
+        embeddings: Vec<(String, Vec<f32>, Chunk)>,
+        metadata: &DocMetadata,
     ) -> Result<()> {
-        self.create_collection(collection_name).await?;
+        self.create_collection(collection_name, collection_name != doc_name)
+            .await?;
+        self.create_payload_indexes(collection_name, metadata)
+            .await?;
+        let doc_payload = doc_payload(doc_name, metadata);
         let points: Vec<PointStruct> = embeddings
             .into_iter()
-            .map(|(id, vec, content)| PointStruct {
+            .enumerate()
+            .map(|(index, (id, vec, chunk))| PointStruct {
                 id: Some(PointId::from(id)),
                 vectors: Some(Vectors::from(vec)),
-                payload: HashMap::from([("text".to_string(), Value::from(content))]),
+                payload: point_payload(&doc_payload, index, chunk),
             })
             .collect();
 
@@ -79,39 +510,107 @@ impl VectorStore {
         Ok(())
     }
 
-    // search for the similar points along with payload
-    // payload to be sent to LLM as context.
-    pub async fn search_result(
+    async fn search_ranked(
         &self,
         collection_name: &str,
         query: Vec<f32>,
-    ) -> Result<Vec<String>> {
-        let search_result = self
-            .client
-            .search_points(SearchPoints {
-                collection_name: collection_name.to_string(),
-                vector: query,
-                limit: 6,
-                with_payload: Some(true.into()),
-                ..Default::default()
-            })
-            .await
-            .context("unable to fetch the results")?;
-
-        let payloads: Vec<String> = search_result
-            .result
-            .iter()
-            .filter_map(|p| {
-                p.payload
-                    .get("text")
-                    .and_then(|v| v.as_str().map(|s| s.to_string()))
-            })
+        filter: Option<&SearchFilter>,
+        limit: u64,
+    ) -> Result<Vec<Chunk>> {
+        let points = self
+            .search_points(collection_name, query, filter, limit, false)
+            .await?;
+        let payloads: Vec<Chunk> = points
+            .into_iter()
+            .filter_map(|p| to_chunk(p.payload))
             .collect();
         Ok(payloads)
     }
 
-    //list out the collection names
-    pub async fn list_collections(&self) -> Result<Vec<String>> {
+    async fn search_candidates(
+        &self,
+        collection_name: &str,
+        query: Vec<f32>,
+        filter: Option<&SearchFilter>,
+        limit: u64,
+    ) -> Result<Vec<(Chunk, Vec<f32>)>> {
+        let points = self
+            .search_points(collection_name, query, filter, limit, true)
+            .await?;
+        Ok(points
+            .into_iter()
+            .filter_map(|p| Some((to_chunk(p.payload)?, dense_vector(p.vectors)?)))
+            .collect())
+    }
+
+    async fn document_chunks(&self, collection_name: &str, doc_name: &str) -> Result<Vec<Chunk>> {
+        // a workspace collection holds the chunks of other documents too
+        let filter = (collection_name != doc_name)
+            .then(|| Filter::must([Condition::matches(DOC_NAME_KEY, doc_name.to_string())]));
+        let mut chunks = self.scroll_chunks(collection_name, filter).await?;
+        sort_chunks(&mut chunks);
+        Ok(chunks)
+    }
+
+    async fn document_vectors(
+        &self,
+        collection_name: &str,
+        doc_name: &str,
+    ) -> Result<Vec<(Chunk, Vec<f32>)>> {
+        let filter = (collection_name != doc_name)
+            .then(|| Filter::must([Condition::matches(DOC_NAME_KEY, doc_name.to_string())]));
+        let mut points: Vec<(Chunk, Vec<f32>)> = self
+            .scroll_points(collection_name, filter, true)
+            .await?
+            .into_iter()
+            .filter_map(|(chunk, vector)| Some((chunk, vector?)))
+            .collect();
+        points.sort_by_key(|(chunk, _)| order_key(chunk));
+        Ok(points)
+    }
+
+    async fn chunks_at(
+        &self,
+        collection_name: &str,
+        doc_name: &str,
+        key: &str,
+        indexes: &[u64],
+    ) -> Result<Vec<Chunk>> {
+        if indexes.is_empty() {
+            return Ok(vec![]);
+        }
+        let filter = Filter::must([
+            Condition::matches(DOC_NAME_KEY, doc_name.to_string()),
+            Condition::matches(key, indexes.iter().map(|i| *i as i64).collect::<Vec<i64>>()),
+        ]);
+        let mut chunks = self.scroll_chunks(collection_name, Some(filter)).await?;
+        sort_chunks(&mut chunks);
+        Ok(chunks)
+    }
+
+    async fn delete_document(&self, collection_name: &str, doc_name: &str) -> Result<()> {
+        if collection_name == doc_name {
+            self.client
+                .delete_collection(collection_name)
+                .await
+                .context("delete_collection operation failed")?;
+        } else {
+            self.client
+                .delete_points(
+                    DeletePointsBuilder::new(collection_name)
+                        .points(Filter::must([Condition::matches(
+                            DOC_NAME_KEY,
+                            doc_name.to_string(),
+                        )]))
+                        .wait(true),
+                )
+                .await
+                .context("delete_points operation failed")?;
+        }
+        Ok(())
+    }
+
+    async fn list_collections(&self) -> Result<Vec<String>> {
         let collections = self
             .client
             .list_collections()
@@ -0,0 +1,295 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context, Result};
+use notify::{recommended_watcher, Event, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::{
+    extractor::{doc_name, is_supported},
+    metadata::DocMetadata,
+    processor::Processor,
+};
+
+// wait for the folder to be quiet for this long before syncing the changed files
+const DEFAULT_DEBOUNCE_MS: u64 = 2000;
+
+// FileSync is the sync state of a file of the watched folder
+#[derive(Serialize, Clone, Debug)]
+pub struct FileSync {
+    pub doc_name: String,
+    // pending, ingested, unchanged, removed or failed
+    pub state: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub updated_at: i64,
+}
+
+// SyncStatus is reported by the sync status endpoint
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct SyncStatus {
+    pub enabled: bool,
+    pub watch_dir: Option<String>,
+    // unix timestamp (seconds) of the last completed sync
+    pub last_sync: Option<i64>,
+    // files being synced right now
+    pub pending: usize,
+    // the state of every file keyed by its path within the folder
+    pub files: BTreeMap<String, FileSync>,
+}
+
+// FolderWatcher keeps the documents in sync with the files of the watched folder
+pub struct FolderWatcher {
+    status: Arc<RwLock<SyncStatus>>,
+    // the notify watcher stops with its drop
+    _watcher: RecommendedWatcher,
+}
+
+impl FolderWatcher {
+    // start syncs the whole folder, then the files changed as per the debounced file events
+    pub fn start(
+        processor: Arc<Processor>,
+        watch_dir: &str,
+        debounce_ms: Option<u64>,
+    ) -> Result<Self> {
+        let root = fs::canonicalize(watch_dir)
+            .with_context(|| format!("the watched folder {} is not readable", watch_dir))?;
+        if !root.is_dir() {
+            return Err(anyhow!("{} is not a directory", watch_dir));
+        }
+        let status = Arc::new(RwLock::new(SyncStatus {
+            enabled: true,
+            watch_dir: Some(root.to_string_lossy().to_string()),
+            ..Default::default()
+        }));
+
+        let (sender, mut receiver) = mpsc::unbounded_channel::<PathBuf>();
+        let mut watcher = recommended_watcher(move |event: notify::Result<Event>| match event {
+            Ok(event) => {
+                for path in event.paths {
+                    let _ = sender.send(path);
+                }
+            }
+            Err(e) => warn!("watch error: {}", e),
+        })
+        .context("unable to watch the folder")?;
+        watcher
+            .watch(&root, RecursiveMode::Recursive)
+            .context("unable to watch the folder")?;
+
+        let debounce = Duration::from_millis(debounce_ms.unwrap_or(DEFAULT_DEBOUNCE_MS));
+        let sync = FolderSync {
+            processor,
+            root,
+            status: status.clone(),
+        };
+        tokio::spawn(async move {
+            sync.full_sync().await;
+            while let Some(path) = receiver.recv().await {
+                // collect the changed paths until the folder is quiet
+                let mut paths = HashSet::from([path]);
+                while let Ok(Some(path)) = tokio::time::timeout(debounce, receiver.recv()).await {
+                    paths.insert(path);
+                }
+                sync.sync_paths(paths).await;
+            }
+        });
+        info!("watching {} for changes", watch_dir);
+        Ok(Self {
+            status,
+            _watcher: watcher,
+        })
+    }
+
+    pub fn status(&self) -> SyncStatus {
+        self.status.read().unwrap().clone()
+    }
+}
+
+struct FolderSync {
+    processor: Arc<Processor>,
+    root: PathBuf,
+    status: Arc<RwLock<SyncStatus>>,
+}
+
+impl FolderSync {
+    // full_sync ingests the new & modified files of the folder
+    // and removes the documents of the files deleted while the server was down
+    async fn full_sync(&self) {
+        let mut paths: HashSet<PathBuf> = walk(&self.root).into_iter().collect();
+        for entry in self.processor.catalog.list() {
+            if let Some(source) = entry.source {
+                paths.insert(self.root.join(source));
+            }
+        }
+        self.sync_paths(paths).await;
+    }
+
+    async fn sync_paths(&self, paths: HashSet<PathBuf>) {
+        let mut files: Vec<(String, PathBuf)> = paths
+            .into_iter()
+            .flat_map(|path| self.expand(path))
+            .collect::<HashSet<PathBuf>>()
+            .into_iter()
+            .filter_map(|path| Some((self.relative(&path)?, path)))
+            .filter(|(relative, _)| is_supported(relative) && !is_hidden(relative))
+            .collect();
+        files.sort();
+        self.status.write().unwrap().pending += files.len();
+        for (relative, path) in files {
+            let doc_name = doc_name(&upload_name(&relative)).to_string();
+            let result = if path.is_file() {
+                self.ingest(&relative, &path).await
+            } else {
+                self.remove(&relative).await
+            };
+            let (state, error) = match result {
+                Ok(state) => (state, None),
+                Err(e) => {
+                    warn!("sync of {} failed: {:#}", relative, e);
+                    ("failed", Some(format!("{:#}", e)))
+                }
+            };
+            let mut status = self.status.write().unwrap();
+            status.pending = status.pending.saturating_sub(1);
+            status.files.insert(
+                relative,
+                FileSync {
+                    doc_name,
+                    state,
+                    error,
+                    updated_at: now(),
+                },
+            );
+        }
+        self.status.write().unwrap().last_sync = Some(now());
+    }
+
+    // ingest the new file or re-index the file whose content changed since its ingestion
+    async fn ingest(&self, relative: &str, path: &Path) -> Result<&'static str> {
+        let data = fs::read(path).with_context(|| format!("unable to read {}", relative))?;
+        let hash = format!("{:x}", Sha256::digest(&data));
+        let file_name = upload_name(relative);
+        let doc_name = doc_name(&file_name).to_string();
+        let catalog = &self.processor.catalog;
+        if let Some(entry) = catalog.get(&doc_name) {
+            if entry.source.as_deref() != Some(relative) {
+                return Err(anyhow!(
+                    "a document named {} was ingested from another source",
+                    doc_name
+                ));
+            }
+            if entry.hash.as_deref() == Some(hash.as_str()) {
+                return Ok("unchanged");
+            }
+            self.processor.remove_document(&doc_name).await?;
+        }
+
+        fs::create_dir_all("./uploads")?;
+        fs::write(format!("./uploads/{}", file_name), &data)
+            .context("error occurred while saving the file")?;
+        let metadata = DocMetadata::default().for_file(&file_name);
+        self.processor
+            .process_file(&file_name, &metadata, None, None)
+            .await?;
+        catalog.update(&doc_name, |entry| {
+            entry.source = Some(relative.to_string());
+            entry.hash = Some(hash);
+        })?;
+        info!("{} synced", relative);
+        Ok("ingested")
+    }
+
+    // remove the document of the deleted file
+    async fn remove(&self, relative: &str) -> Result<&'static str> {
+        let file_name = upload_name(relative);
+        let doc_name = doc_name(&file_name).to_string();
+        let watched = self
+            .processor
+            .catalog
+            .get(&doc_name)
+            .is_some_and(|entry| entry.source.as_deref() == Some(relative));
+        if !watched {
+            return Ok("removed");
+        }
+        self.processor.remove_document(&doc_name).await?;
+        let _ = fs::remove_file(format!("./uploads/{}", file_name));
+        info!("{} removed", relative);
+        Ok("removed")
+    }
+
+    // expand the directories moved in & out of the folder into their files
+    fn expand(&self, path: PathBuf) -> Vec<PathBuf> {
+        if path == self.root {
+            return vec![];
+        }
+        if path.is_dir() {
+            return walk(&path);
+        }
+        let Some(relative) = self.relative(&path).filter(|_| !path.exists()) else {
+            return vec![path];
+        };
+        let prefix = format!("{}/", relative);
+        let mut paths: Vec<PathBuf> = self
+            .processor
+            .catalog
+            .list()
+            .into_iter()
+            .filter_map(|entry| entry.source)
+            .filter(|source| source.starts_with(&prefix))
+            .map(|source| self.root.join(source))
+            .collect();
+        paths.push(path);
+        paths
+    }
+
+    // the path within the watched folder, `/` separated
+    fn relative(&self, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(&self.root).ok()?;
+        Some(relative.to_string_lossy().replace('\\', "/"))
+    }
+}
+
+// the uploaded file name of the watched file, `guides/setup.pdf` -> `guides_setup.pdf`
+fn upload_name(relative: &str) -> String {
+    relative.replace('/', "_")
+}
+
+// hidden, temporary & office lock files (`~$report.docx`) are not synced
+fn is_hidden(relative: &str) -> bool {
+    relative
+        .split('/')
+        .any(|part| part.starts_with('.') || part.starts_with('~'))
+}
+
+fn walk(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .flat_map(|entry| {
+            let path = entry.path();
+            if path.is_dir() {
+                walk(&path)
+            } else {
+                vec![path]
+            }
+        })
+        .collect()
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}